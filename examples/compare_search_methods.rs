@@ -1,5 +1,6 @@
+use cratespro_search::search::metrics::{precision_at_k, recall};
 use cratespro_search::search::{
-    RecommendCrate, SearchModule, SearchSortCriteria, TraditionalSearchModule,
+    truncate, RecommendCrate, SearchModule, SearchSortCriteria, TraditionalSearchModule,
 };
 use dotenv::dotenv;
 use prettytable::{format, Cell, Row, Table};
@@ -11,7 +12,7 @@ use std::fs::File;
 use std::io::{BufReader, Write};
 use std::path::Path;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use tokio_postgres::NoTls;
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -115,6 +116,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             test_case.description, test_case.query
         );
 
+        // 已标注的相关crate集合，用于计算召回率
+        let relevant_set: HashSet<String> = test_case.relevant_packages.iter().cloned().collect();
+
         // LLM增强搜索
         println!("\n  🧠 LLM增强搜索:");
         let llm_start = Instant::now();
@@ -145,14 +149,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // 使用LLM相关性判断计算指标
         let llm_metrics = calculate_metrics_from_llm_judgments(&llm_results, &llm_relevance);
+        let llm_recall = recall(&llm_results, &relevant_set);
 
         println!(
             "    ⏱️ 搜索耗时: {:.2?}, 相关性评估耗时: {:.2?}",
             llm_duration, llm_eval_duration
         );
         println!(
-            "    P@1: {:.2}, P@3: {:.2}, P@5: {:.2}, P@10: {:.2}, 相关结果: {}",
-            llm_metrics.0, llm_metrics.1, llm_metrics.2, llm_metrics.3, llm_metrics.4
+            "    P@1: {:.2}, P@3: {:.2}, P@5: {:.2}, P@10: {:.2}, 相关结果: {}, 召回率: {:.2}",
+            llm_metrics.0, llm_metrics.1, llm_metrics.2, llm_metrics.3, llm_metrics.4, llm_recall
         );
 
         // 打印LLM搜索的前5个结果及其相关性
@@ -188,14 +193,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // 使用LLM相关性判断计算指标
         let trad_metrics = calculate_metrics_from_llm_judgments(&trad_results, &trad_relevance);
+        let trad_recall = recall(&trad_results, &relevant_set);
 
         println!(
             "    ⏱️ 搜索耗时: {:.2?}, 相关性评估耗时: {:.2?}",
             trad_duration, trad_eval_duration
         );
         println!(
-            "    P@1: {:.2}, P@3: {:.2}, P@5: {:.2}, P@10: {:.2}, 相关结果: {}",
-            trad_metrics.0, trad_metrics.1, trad_metrics.2, trad_metrics.3, trad_metrics.4
+            "    P@1: {:.2}, P@3: {:.2}, P@5: {:.2}, P@10: {:.2}, 相关结果: {}, 召回率: {:.2}",
+            trad_metrics.0,
+            trad_metrics.1,
+            trad_metrics.2,
+            trad_metrics.3,
+            trad_metrics.4,
+            trad_recall
         );
 
         // 打印传统搜索的前5个结果及其相关性
@@ -210,7 +221,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             precision_at_3: llm_metrics.1,
             precision_at_5: llm_metrics.2,
             precision_at_10: llm_metrics.3,
-            recall: llm_metrics.4 as f64, // 使用相关结果数量作为召回指标
+            recall: llm_recall,
             latency_ms: llm_duration.as_millis() as f64,
         });
 
@@ -222,7 +233,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             precision_at_3: trad_metrics.1,
             precision_at_5: trad_metrics.2,
             precision_at_10: trad_metrics.3,
-            recall: trad_metrics.4 as f64, // 使用相关结果数量作为召回指标
+            recall: trad_recall,
             latency_ms: trad_duration.as_millis() as f64,
         });
     }
@@ -385,10 +396,10 @@ fn calculate_metrics_from_llm_judgments(
         .map(|r| judgments.get(&r.name).copied().unwrap_or(false))
         .collect();
 
-    let p1 = calculate_precision_at_k(&relevant_flags, 1);
-    let p3 = calculate_precision_at_k(&relevant_flags, 3);
-    let p5 = calculate_precision_at_k(&relevant_flags, 5);
-    let p10 = calculate_precision_at_k(&relevant_flags, 10);
+    let p1 = precision_at_k(&relevant_flags, 1);
+    let p3 = precision_at_k(&relevant_flags, 3);
+    let p5 = precision_at_k(&relevant_flags, 5);
+    let p10 = precision_at_k(&relevant_flags, 10);
 
     let relevant_count = relevant_flags
         .iter()
@@ -415,7 +426,7 @@ fn print_results_with_llm_judgments(
             i + 1,
             mark,
             result.name,
-            truncate_text(&result.description, 40),
+            truncate(&result.description, 40),
             result.final_score
         );
     }
@@ -506,21 +517,6 @@ fn load_test_cases() -> Vec<TestCase> {
     ]
 }
 
-fn calculate_precision_at_k(relevant_flags: &[bool], k: usize) -> f64 {
-    if relevant_flags.is_empty() || k == 0 {
-        return 0.0;
-    }
-
-    let k_actual = k.min(relevant_flags.len());
-    let relevant_count = relevant_flags
-        .iter()
-        .take(k_actual)
-        .filter(|&&is_relevant| is_relevant)
-        .count();
-
-    relevant_count as f64 / k_actual as f64
-}
-
 fn generate_report(results: &[ComparisonResult]) {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_BOX_CHARS);
@@ -537,7 +533,7 @@ fn generate_report(results: &[ComparisonResult]) {
 
     for result in results {
         table.add_row(Row::new(vec![
-            Cell::new(&truncate_text(
+            Cell::new(&truncate(
                 &format!("{}({})", &result.query, &result.description),
                 30,
             )),
@@ -600,13 +596,3 @@ fn generate_report(results: &[ComparisonResult]) {
         println!("  延迟开销: {:+.1}%", latency_increase);
     }
 }
-
-fn truncate_text(s: &str, max_chars: usize) -> String {
-    let chars: Vec<char> = s.chars().collect();
-
-    if chars.len() <= max_chars {
-        s.to_string()
-    } else {
-        chars.into_iter().take(max_chars).collect::<String>() + "..."
-    }
-}
@@ -1,4 +1,5 @@
-use cratespro_search::search::{RecommendCrate, SearchModule, SearchSortCriteria};
+use cratespro_search::search::metrics::precision_at_k;
+use cratespro_search::search::{truncate, RecommendCrate, SearchModule, SearchSortCriteria};
 use dotenv::dotenv;
 use prettytable::{format, Cell, Row, Table};
 use reqwest::Client;
@@ -496,6 +497,14 @@ fn convert_to_recommend_crates(crates_io_crates: Vec<CratesIoCrate>) -> Vec<Reco
             rank: 0.0,                       // 我们没有直接的排名信息
             vector_score: 0.0,               // 没有向量得分
             final_score: c.downloads as f32, // 使用下载量作为最终得分
+            version: Some(c.version),
+            downloads: c.downloads,
+            repository: None,
+            keywords: Vec::new(),
+            updated_at: None,
+            metadata: HashMap::new(),
+            popularity: None,
+            embedding: None,
         })
         .collect()
 }
@@ -683,6 +692,7 @@ async fn evaluate_with_llm_detailed(
 }
 
 // 保留原有的evaluate_with_llm函数调用新的详细版本
+#[allow(dead_code)]
 async fn evaluate_with_llm(
     client: &Client,
     query: &str,
@@ -706,11 +716,11 @@ fn calculate_metrics_from_llm_judgments(
         .collect();
 
     // 计算P@K
-    let p1 = calculate_precision_at_k(&relevant_flags, 1);
-    let p3 = calculate_precision_at_k(&relevant_flags, 3);
-    let p5 = calculate_precision_at_k(&relevant_flags, 5);
-    let p10 = calculate_precision_at_k(&relevant_flags, 10);
-    let p20 = calculate_precision_at_k(&relevant_flags, 20);
+    let p1 = precision_at_k(&relevant_flags, 1);
+    let p3 = precision_at_k(&relevant_flags, 3);
+    let p5 = precision_at_k(&relevant_flags, 5);
+    let p10 = precision_at_k(&relevant_flags, 10);
+    let p20 = precision_at_k(&relevant_flags, 20);
 
     // 计算相关结果数量
     let relevant_count = relevant_flags
@@ -722,21 +732,6 @@ fn calculate_metrics_from_llm_judgments(
 }
 
 // 计算Precision@K
-fn calculate_precision_at_k(relevant_flags: &[bool], k: usize) -> f64 {
-    if relevant_flags.is_empty() || k == 0 {
-        return 0.0;
-    }
-
-    let k_actual = std::cmp::min(k, relevant_flags.len());
-    let relevant_count = relevant_flags
-        .iter()
-        .take(k_actual)
-        .filter(|&&is_relevant| is_relevant)
-        .count();
-
-    relevant_count as f64 / k_actual as f64
-}
-
 // 打印结果并显示LLM判断的相关性
 fn print_results_with_llm_judgments(
     method: &str,
@@ -755,7 +750,7 @@ fn print_results_with_llm_judgments(
             i + 1,
             mark,
             result.name,
-            truncate_text(&result.description, 40),
+            truncate(&result.description, 40),
         );
     }
 }
@@ -781,7 +776,7 @@ fn generate_report(results: &[ComparisonResult]) {
     // 添加数据行
     for result in results {
         table.add_row(Row::new(vec![
-            Cell::new(&truncate_text(
+            Cell::new(&truncate(
                 &format!("{}({})", &result.query, &result.description),
                 25,
             )),
@@ -890,14 +885,3 @@ fn generate_report(results: &[ComparisonResult]) {
         }
     }
 }
-
-// 辅助函数：截断文本
-fn truncate_text(s: &str, max_chars: usize) -> String {
-    let chars: Vec<char> = s.chars().collect();
-
-    if chars.len() <= max_chars {
-        s.to_string()
-    } else {
-        chars.into_iter().take(max_chars).collect::<String>() + "..."
-    }
-}
@@ -0,0 +1,59 @@
+use crate::search::core::{RecommendCrate, SearchModule};
+use crate::search::error::SearchError;
+use crate::search::utils::{escape_like_pattern, validate_identifier};
+
+impl SearchModule<'_> {
+    /// 输入时的快速补全：返回名称以 `prefix` 开头（不区分大小写）的crate，按下载量降序
+    ///
+    /// 只执行一条名称前缀查询，不做查询改写、向量嵌入和多表检索，也不受离线模式影响；
+    /// 结果不计算得分，`rank`、`vector_score` 和 `final_score` 均为0。前缀为空时返回空列表
+    pub async fn autocomplete(
+        &self,
+        prefix: &str,
+        limit: usize,
+    ) -> Result<Vec<RecommendCrate>, SearchError> {
+        validate_identifier(&self.table_name)?;
+        let prefix = prefix.trim();
+        if prefix.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        // 用户输入中的 % 和 _ 按字面匹配
+        let statement = format!(
+            "SELECT id, name, description, version, downloads, repository, keywords
+            FROM {}
+            WHERE name ILIKE $1 || '%' ESCAPE '\\'
+            ORDER BY downloads DESC NULLS LAST, name
+            LIMIT $2",
+            self.table_name
+        );
+        let rows = self
+            .pg_client
+            .query(
+                &statement,
+                &[
+                    &escape_like_pattern(prefix),
+                    &(limit.min(i64::MAX as usize) as i64),
+                ],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| RecommendCrate {
+                id: row.get("id"),
+                name: row.get("name"),
+                description: row
+                    .get::<_, Option<String>>("description")
+                    .unwrap_or_default(),
+                version: row.get("version"),
+                downloads: row.get::<_, Option<i64>>("downloads").unwrap_or(0),
+                repository: row.get("repository"),
+                keywords: row
+                    .get::<_, Option<Vec<String>>>("keywords")
+                    .unwrap_or_default(),
+                ..Default::default()
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,213 @@
+use crate::search::config::LlmConfig;
+use crate::search::core::{SearchModule, SearchOutcome};
+use crate::search::embedder::Embedder;
+use crate::search::rewrite::prepare_query;
+use crate::search::utils::normalize_unicode;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// 热门查询的改写结果和查询向量缓存
+///
+/// 由 [`spawn_cache_warmer`] 定期写入，搜索时命中缓存的查询跳过LLM改写和查询向量接口调用。
+/// 只缓存预热过的查询，不会随普通搜索增长；多个 `SearchModule` 可通过
+/// `SearchModuleBuilder::query_cache` 共享同一个缓存
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    rewrites: RwLock<HashMap<String, String>>,
+    embeddings: RwLock<HashMap<String, Vec<f32>>>,
+}
+
+impl QueryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 查询的缓存改写结果
+    pub fn rewrite(&self, query: &str) -> Option<String> {
+        self.rewrites.read().unwrap().get(query).cloned()
+    }
+
+    /// 文本的缓存向量
+    pub fn embedding(&self, text: &str) -> Option<Vec<f32>> {
+        self.embeddings.read().unwrap().get(text).cloned()
+    }
+
+    /// 缓存的查询数量（有改写结果或查询向量的查询各计一次）
+    pub fn len(&self) -> usize {
+        let rewrites = self.rewrites.read().unwrap();
+        let embeddings = self.embeddings.read().unwrap();
+        rewrites.len()
+            + embeddings
+                .keys()
+                .filter(|text| !rewrites.contains_key(*text))
+                .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 清空缓存
+    pub fn clear(&self) {
+        self.rewrites.write().unwrap().clear();
+        self.embeddings.write().unwrap().clear();
+    }
+
+    pub(crate) fn insert_rewrite(&self, query: String, rewritten: String) {
+        self.rewrites.write().unwrap().insert(query, rewritten);
+    }
+
+    pub(crate) fn insert_embedding(&self, text: String, embedding: Vec<f32>) {
+        self.embeddings.write().unwrap().insert(text, embedding);
+    }
+}
+
+/// 启动后台任务，按 `interval` 周期刷新热门查询的改写结果和查询向量
+///
+/// 启动后立即预热一次，之后每个周期重新计算并覆盖缓存；某个查询改写或获取向量失败时
+/// 保留上一次的缓存。任务不会自行结束，不再需要时对返回的 `JoinHandle` 调用 `abort`。
+/// 离线模式下只做基础规则改写，无需预热，任务直接结束
+pub fn spawn_cache_warmer(
+    module: &SearchModule<'_>,
+    queries: Vec<String>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    let cache = module.query_cache.clone();
+    let embedder = module.embedder.clone();
+    let llm_config = module.llm_config.clone();
+    let normalize = module.config.normalize_unicode;
+    let offline = module.config.offline;
+    let max_ngram = module.config.keyword_max_ngram;
+
+    tokio::spawn(async move {
+        if offline {
+            return;
+        }
+        // tokio的interval不接受零间隔
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_millis(1)));
+        loop {
+            ticker.tick().await;
+            warm_queries(
+                &cache,
+                embedder.as_ref(),
+                &llm_config,
+                &queries,
+                normalize,
+                max_ngram,
+            )
+            .await;
+        }
+    })
+}
+
+// 预热一轮：逐个改写查询，再批量获取查询向量
+async fn warm_queries(
+    cache: &QueryCache,
+    embedder: &dyn Embedder,
+    llm_config: &LlmConfig,
+    queries: &[String],
+    normalize: bool,
+    max_ngram: usize,
+) {
+    for query in queries {
+        // 与搜索时相同：改写前先规范化，缓存以规范化后的查询为键
+        let query = if normalize {
+            normalize_unicode(query)
+        } else {
+            query.clone()
+        };
+        let mut outcome = SearchOutcome::default();
+        let rewritten = prepare_query(&query, llm_config, max_ngram, &mut outcome).await;
+        // 只缓存LLM成功改写的结果，降级结果计算成本很低，缓存反而会掩盖LLM恢复
+        if outcome.used_llm_rewrite && outcome.fallback_reasons.is_empty() {
+            cache.insert_rewrite(query, rewritten);
+        }
+    }
+
+    match embedder.embed(queries).await {
+        Ok(embeddings) => {
+            for (query, embedding) in queries.iter().zip(embeddings) {
+                if !embedding.is_empty() {
+                    cache.insert_embedding(query.clone(), embedding);
+                }
+            }
+        }
+        Err(e) => eprintln!("预热查询向量失败: {}", e),
+    }
+}
+
+// 先查缓存的嵌入提供者，未命中的文本交给内部提供者计算
+//
+// 同一提供者对相同文本的向量相同，因此候选crate的文本恰好命中缓存时结果不变
+pub(crate) struct CachedEmbedder<'a> {
+    pub(crate) inner: &'a dyn Embedder,
+    pub(crate) cache: &'a QueryCache,
+}
+
+#[async_trait]
+impl Embedder for CachedEmbedder<'_> {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        let mut embeddings: Vec<Option<Vec<f32>>> = texts
+            .iter()
+            .map(|text| self.cache.embedding(text))
+            .collect();
+        let missing: Vec<String> = texts
+            .iter()
+            .zip(&embeddings)
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(text, _)| text.clone())
+            .collect();
+        if !missing.is_empty() {
+            let mut computed = self.inner.embed(&missing).await?.into_iter();
+            for embedding in embeddings.iter_mut().filter(|e| e.is_none()) {
+                *embedding = Some(computed.next().unwrap_or_default());
+            }
+        }
+        Ok(embeddings
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect())
+    }
+
+    async fn embed_one(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        match self.cache.embedding(text) {
+            Some(embedding) => Ok(embedding),
+            None => self.inner.embed_one(text).await,
+        }
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+}
+
+// 返回预先获取的查询向量的嵌入提供者，其他文本交给内部提供者计算
+//
+// 用于查询向量与改写、检索并发获取后，重排序阶段不再重复请求查询向量
+pub(crate) struct PrefetchedEmbedder<'a> {
+    pub(crate) inner: &'a dyn Embedder,
+    pub(crate) text: &'a str,
+    pub(crate) embedding: Vec<f32>,
+}
+
+#[async_trait]
+impl Embedder for PrefetchedEmbedder<'_> {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        self.inner.embed(texts).await
+    }
+
+    async fn embed_one(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        if text == self.text {
+            Ok(self.embedding.clone())
+        } else {
+            self.inner.embed_one(text).await
+        }
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+}
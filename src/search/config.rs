@@ -0,0 +1,776 @@
+use crate::search::core::{RecommendCrate, SearchSortCriteria};
+use crate::search::embedder::{EmbeddingMode, PrecomputedPolicy, SimilarityMetric};
+use crate::search::error::SearchError;
+use crate::search::lang::QueryKind;
+use crate::search::utils::{validate_identifier, DEFAULT_KEYWORD_MAX_NGRAM};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, RequestBuilder};
+use semver::VersionReq;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 关键词得分与向量得分的权重
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreWeights {
+    pub keyword: f32,
+    pub vector: f32,
+}
+
+impl ScoreWeights {
+    pub fn new(keyword: f32, vector: f32) -> Self {
+        ScoreWeights { keyword, vector }
+    }
+}
+
+/// 按查询类型调整向量得分权重的系数
+///
+/// 搜索时按改写阶段识别出的查询类型，把排序方式对应权重中的向量权重乘以该系数，
+/// 再按比例缩放使关键词与向量权重之和不变：精确的关键词查询（如 `serde`）更依赖关键词得分，
+/// 含义模糊的自然语言查询（如“连接postgres的库”）更依赖向量得分。`Custom` 排序不受影响
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryKindWeights {
+    pub keyword: f32,
+    pub natural_language: f32,
+    pub code_or_error: f32,
+}
+
+impl Default for QueryKindWeights {
+    fn default() -> Self {
+        QueryKindWeights {
+            keyword: 0.5,
+            natural_language: 1.5,
+            code_or_error: 1.0,
+        }
+    }
+}
+
+impl QueryKindWeights {
+    /// 查询类型对应的向量权重系数
+    pub fn vector_scale(&self, kind: QueryKind) -> f32 {
+        match kind {
+            QueryKind::Keyword => self.keyword,
+            QueryKind::NaturalLanguage => self.natural_language,
+            QueryKind::CodeOrError => self.code_or_error,
+        }
+    }
+
+    // 调整一组权重：向量权重乘以系数后，两项按比例缩放回原来的总和
+    fn adjust(&self, weights: ScoreWeights, kind: QueryKind) -> ScoreWeights {
+        let total = weights.keyword + weights.vector;
+        let vector = weights.vector * self.vector_scale(kind).max(0.0);
+        let adjusted_total = weights.keyword + vector;
+        if adjusted_total <= 0.0 || !adjusted_total.is_finite() {
+            return weights;
+        }
+        let factor = total / adjusted_total;
+        ScoreWeights::new(weights.keyword * factor, vector * factor)
+    }
+}
+
+/// 查询拼写纠正的配置，见 `SearchConfig::spelling_correction`
+///
+/// 改写前把查询中与已知crate名称编辑距离不超过 `max_edit_distance` 的词替换为该名称，
+/// 如 "tokoi async" -> "tokio async"。名称词表在首次搜索时从数据表加载一次，
+/// 也可通过 `SearchModuleBuilder::vocabulary` 直接提供
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpellingCorrection {
+    // 纠正时允许的最大编辑距离（Levenshtein，按字符计算）
+    pub max_edit_distance: usize,
+    // 从数据表加载名称词表时，按下载量降序最多加载的名称数量；为None时加载全部名称
+    pub vocabulary_limit: Option<usize>,
+}
+
+impl Default for SpellingCorrection {
+    fn default() -> Self {
+        SpellingCorrection {
+            max_edit_distance: 2,
+            vocabulary_limit: Some(20_000),
+        }
+    }
+}
+
+/// 关键词检索的打分方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum KeywordScorer {
+    /// PostgreSQL 的 ts_rank（默认）
+    #[default]
+    TsRank,
+    /// PostgreSQL 的 ts_rank_cd，`norm` 为文档长度归一化标志（如 1 表示除以 1 + log(长度)）
+    TsRankCd { norm: i32 },
+    /// BM25，在Rust中根据词频、文档长度和全表文档频率计算，并归一化到 [0, 1]
+    Bm25 { k1: f32, b: f32 },
+}
+
+/// 关键词检索没有结果时依次尝试的后备检索方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetrievalFallback {
+    /// 对原始查询使用 `websearch_to_tsquery`（不可用时退回 `plainto_tsquery`）
+    WebSearch,
+    /// 基于pg_trgm的三元组相似度匹配名称和描述，可容忍拼写差异，需要安装 pg_trgm 扩展
+    Trigram,
+}
+
+/// 多个关键词在tsquery中的组合方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TermCombination {
+    /// 命中任一关键词即可（默认），召回最多
+    #[default]
+    Or,
+    /// 必须命中全部关键词，精度最高
+    And,
+    /// 必须命中全部核心关键词（分号之前的部分），或者命中任一同义词
+    PrimaryAnd,
+}
+
+/// 改写后的关键词交给数据库解析时使用的函数
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TsQuerySyntax {
+    /// 在Rust中拼接 `to_tsquery` 表达式（默认），关键词按前缀匹配
+    #[default]
+    ToTsQuery,
+    /// 拼接为网页搜索语法交给 `websearch_to_tsquery` 解析，关键词中的特殊字符不会导致语法错误；
+    /// 数据库不支持时回退到 `plainto_tsquery`。该方式不做前缀匹配
+    WebSearch,
+}
+
+/// 关键词检索时名称与描述命中的权重，取值范围 [0, 1]
+///
+/// 要求 `tsv` 列按字段设置权重：名称为 A、描述为 B，例如
+/// `setweight(to_tsvector('english', name), 'A') || setweight(to_tsvector('english', description), 'B')`
+/// （`SearchPrepare::set_tsv_column` 即按此方式生成）。未设置权重的词位按描述权重计算；
+/// 使用BM25打分时只影响候选的取回顺序
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldWeights {
+    pub name: f32,
+    pub description: f32,
+}
+
+impl Default for FieldWeights {
+    fn default() -> Self {
+        FieldWeights {
+            name: 1.0,
+            description: 0.4,
+        }
+    }
+}
+
+impl FieldWeights {
+    // ts_rank 的权重数组，顺序为 {D, C, B, A}
+    pub(crate) fn ts_rank_weights(&self) -> String {
+        let name = self.name.clamp(0.0, 1.0);
+        let description = self.description.clamp(0.0, 1.0);
+        format!("'{{{0}, {0}, {0}, {1}}}'::float4[]", description, name)
+    }
+}
+
+/// 传统搜索各检索策略从数据库取回的候选数量上限
+///
+/// 未显式设置时按 `SearchConfig::candidate_limit` 折算，见 `from_candidate_limit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrategyLimits {
+    // 精确匹配
+    pub exact: usize,
+    // 前缀匹配
+    pub prefix: usize,
+    // 全文搜索
+    pub fulltext: usize,
+    // 候选过少时补充的高级全文搜索
+    pub advanced_fulltext: usize,
+}
+
+impl StrategyLimits {
+    /// 精确匹配取1/4，前缀匹配和全文搜索取3/4，高级全文搜索取全部
+    pub fn from_candidate_limit(candidate_limit: usize) -> Self {
+        StrategyLimits {
+            exact: candidate_limit / 4,
+            prefix: candidate_limit * 3 / 4,
+            fulltext: candidate_limit * 3 / 4,
+            advanced_fulltext: candidate_limit,
+        }
+    }
+}
+
+/// 近期更新加分：按crate距上次更新的天数指数衰减，作为最终得分中独立的一项
+///
+/// 加分为 `weight * 0.5^(天数 / half_life_days)`，刚更新的crate得到完整的 `weight`，
+/// 每经过一个半衰期减半。要求crate表有 `updated_at` 列
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecencyBoost {
+    pub weight: f32,
+    pub half_life_days: f32,
+}
+
+impl Default for RecencyBoost {
+    fn default() -> Self {
+        RecencyBoost {
+            weight: 0.1,
+            half_life_days: 180.0,
+        }
+    }
+}
+
+impl RecencyBoost {
+    // 根据更新时间计算加分，未来的时间按刚更新处理
+    pub(crate) fn score(&self, updated_at: DateTime<Utc>, now: DateTime<Utc>) -> f32 {
+        if self.half_life_days <= 0.0 {
+            return 0.0;
+        }
+        let age_days = (now - updated_at).num_seconds().max(0) as f32 / 86_400.0;
+        self.weight * 0.5f32.powf(age_days / self.half_life_days)
+    }
+}
+
+/// 共现关系加分：与排名靠前的结果在关联表中有边相连的候选获得额外加分
+///
+/// 关联表结构为 `(a_id, b_id, weight)`，边视为无向；权重按本次候选之间最大的边权重归一化，
+/// 每个候选取与各种子结果之间最大的归一化权重，加分为 `weight * 归一化权重`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphBoost {
+    // 关联表名，只接受由字母、数字和下划线组成的名称
+    pub edge_table: String,
+    // 加分系数
+    pub weight: f32,
+    // 作为种子的高分结果数量
+    pub seed_count: usize,
+}
+
+impl Default for GraphBoost {
+    fn default() -> Self {
+        GraphBoost {
+            edge_table: "crate_edges".to_string(),
+            weight: 0.1,
+            seed_count: 5,
+        }
+    }
+}
+
+/// 预先计算的热度列（如综合了下载量、近期增长和被依赖数的得分）
+///
+/// 按 `Downloads` 排序时，该列的值按 `ln(1 + 值) / ln(1 + full_score)` 归一化到 [0, 1]
+/// （不小于 `full_score` 的值为满分，负值和NULL按0计算），乘以 `weight` 后计入最终得分
+/// 与排序方式一样，退回仅关键词排序（离线模式、查询向量获取失败等）时不计入
+#[derive(Debug, Clone, PartialEq)]
+pub struct PopularityColumn {
+    // 列名，只接受由字母、数字和下划线组成的名称，列类型须可转换为双精度
+    pub column: String,
+    pub weight: f32,
+    // 热度得分为满分的列值
+    pub full_score: f64,
+}
+
+impl Default for PopularityColumn {
+    fn default() -> Self {
+        PopularityColumn {
+            column: "popularity".to_string(),
+            weight: 0.3,
+            full_score: 100_000_000.0,
+        }
+    }
+}
+
+impl PopularityColumn {
+    // 把列值按对数归一化到 [0, 1]
+    pub(crate) fn score(&self, value: Option<f64>) -> f32 {
+        let value = value.filter(|v| v.is_finite()).unwrap_or(0.0).max(0.0);
+        if self.full_score <= 0.0 {
+            return 0.0;
+        }
+        (value.ln_1p() / self.full_score.ln_1p()).min(1.0) as f32
+    }
+}
+
+/// 向量相似度的计算位置
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VectorScoreSource {
+    /// 取回嵌入向量后在Rust中计算相似度（默认），缺失的向量可按需生成
+    #[default]
+    Rust,
+    /// 在检索SQL中用 `similarity_metric` 对应的pgvector运算符计算，无需传输向量；只使用已存储的向量
+    Postgres,
+}
+
+/// 搜索配置：各排序方式下的得分权重等可调参数
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    // 综合排序的权重
+    pub comprehensive: ScoreWeights,
+    // 相关性排序的权重
+    pub relevance: ScoreWeights,
+    // 下载量排序的权重
+    pub downloads: ScoreWeights,
+    // crate名称与查询词完全相同时最终得分的加分
+    pub name_match_boost: f32,
+    // crate名称是某个查询词的前缀时最终得分的加分
+    pub name_prefix_boost: f32,
+    // 相关性反馈调整值的缩放系数
+    pub feedback_weight: f32,
+    // find_similar_with_negatives 中从种子向量减去反例平均向量的系数
+    pub negative_example_weight: f32,
+    // 关键词检索阶段从数据库取回的候选数量上限
+    pub candidate_limit: usize,
+    // 向量重排序阶段最多处理的候选数量，按关键词得分保留前若干个后再获取嵌入向量，
+    // 限制每次搜索调用嵌入接口的次数和延迟；为None时重排序全部候选
+    pub rerank_candidate_cap: Option<usize>,
+    // 只对关键词得分最高的前N个候选做向量重排序，其余候选按关键词得分原样接在其后；
+    // 与 rerank_candidate_cap 不同，尾部候选不会被丢弃。为None时重排序全部候选
+    pub rerank_top_n: Option<usize>,
+    // 最终返回给调用方的结果数量上限（默认100），为None时返回全部排序结果，
+    // 适用于需要计算 P@20 等更深位置指标的评估
+    pub result_limit: Option<usize>,
+    // 生成tsquery时最多使用的关键词数量（按LLM返回的顺序）
+    pub max_keywords: usize,
+    // 多个关键词在tsquery中的组合方式
+    pub term_combination: TermCombination,
+    // 改写后的关键词生成tsquery的方式
+    pub tsquery_syntax: TsQuerySyntax,
+    // 改写后的关键词检索没有结果时，按顺序尝试的后备检索方式，为空时不做后备检索
+    pub retrieval_fallbacks: Vec<RetrievalFallback>,
+    // 改写结果区分核心关键词和同义词时，核心关键词得分的额外权重
+    pub primary_keyword_boost: f32,
+    // 关键词检索的打分方式
+    pub keyword_scorer: KeywordScorer,
+    // 名称与描述命中的权重，为None时使用PostgreSQL的默认权重
+    pub field_weights: Option<FieldWeights>,
+    // 是否把搜索时生成的嵌入向量写回数据库，只读副本或无写权限时应设为false
+    pub persist_on_demand: bool,
+    // 嵌入向量的获取方式
+    pub embedding_mode: EmbeddingMode,
+    // 预先计算模式下缺少嵌入向量时的处理策略
+    pub precomputed_policy: PrecomputedPolicy,
+    // 向量相似度的计算位置
+    pub vector_score_source: VectorScoreSource,
+    // 向量相似度的度量方式，应与嵌入模型的训练目标一致
+    pub similarity_metric: SimilarityMetric,
+    // 离线模式：不调用任何外部接口，查询改写使用基础规则，排序仅使用关键词得分
+    pub offline: bool,
+    // 是否在查询改写前对查询做Unicode NFKC规范化（全角转半角、合并组合字符），
+    // LLM改写的输出同样规范化；小写转换在生成tsquery时进行，不受该选项影响
+    pub normalize_unicode: bool,
+    // 近期更新加分，为None时不考虑更新时间
+    pub recency_boost: Option<RecencyBoost>,
+    // 生成tsquery使用的文本搜索配置（如 "english"、"simple"），应与 `tsv` 列的生成方式一致；
+    // 为None时使用数据库的 default_text_search_config
+    pub text_search_config: Option<String>,
+    // 检索时保留原始查询词并按该权重额外加权，防止LLM改写偏离原意；为None时只使用改写结果
+    pub original_terms_weight: Option<f32>,
+    // 检索时额外读取的列（如 license、yanked），结果见 RecommendCrate::metadata，
+    // 可配合 SearchOptions::filter 按业务规则过滤结果；只接受由字母、数字和下划线组成的列名
+    pub metadata_columns: Vec<String>,
+    // 向量重排序阶段获取嵌入向量的时限，超时后直接返回按关键词得分排序的结果；为None时不限制
+    pub rerank_timeout: Option<Duration>,
+    // 按crate之间的共现关系（依赖图、共同下载等）为与高分结果相关的候选加分；为None时不使用
+    pub graph_boost: Option<GraphBoost>,
+    // 按下载量排序时计入的预先计算热度列；为None时下载量排序只按权重组合关键词和向量得分
+    pub popularity: Option<PopularityColumn>,
+    // 按查询类型调整关键词与向量得分的比例；为None时所有查询使用相同的权重
+    pub query_kind_weights: Option<QueryKindWeights>,
+    // 改写前按已知crate名称纠正查询中的拼写错误；为None时不纠正
+    pub spelling_correction: Option<SpellingCorrection>,
+    // 查询向量与LLM改写、关键词检索并发获取，两次网络请求的耗时重叠；
+    // 关键词检索没有结果时查询向量请求也会照常发出
+    pub concurrent_query_embedding: bool,
+    // 独立存放嵌入向量的表名，为None时向量存放在crate表的 embedding 列；
+    // 该表需要包含 `id`（主键或唯一约束，与crate表的id对应）和 `embedding` 两列，
+    // 适用于无法修改crate表结构的场景。表名直接拼接进SQL，只接受由字母、数字和下划线组成的名称
+    pub embeddings_table: Option<String>,
+    // 生成嵌入文本使用的列（如整理过的 `search_text` 列），该列为空时仍使用描述；为None时使用描述。
+    // 列名直接拼接进SQL，只接受由字母、数字和下划线组成的名称
+    pub embedding_text_column: Option<String>,
+    // 嵌入文本的最大字符数，超出部分截断；默认8000，中文约一字一token，保证不超过常见嵌入模型的8k token上限
+    pub max_embedding_chars: usize,
+    // 按需生成嵌入时调用嵌入接口和写回数据库的并发上限（默认4），
+    // 在同一搜索模块的所有并发搜索之间共享，避免大量搜索同时请求嵌入接口或写入数据库
+    pub embedding_concurrency: usize,
+    // 未使用LLM时基本关键词提取生成的最长词组（单词数），如 "http client"；设为1时只使用单个关键词
+    pub keyword_max_ngram: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            comprehensive: ScoreWeights::new(0.6, 0.4),
+            relevance: ScoreWeights::new(0.8, 0.2),
+            downloads: ScoreWeights::new(0.5, 0.5),
+            name_match_boost: 0.3,
+            name_prefix_boost: 0.1,
+            feedback_weight: 0.2,
+            negative_example_weight: 0.5,
+            candidate_limit: 200,
+            rerank_candidate_cap: None,
+            rerank_top_n: None,
+            result_limit: Some(100),
+            max_keywords: 6,
+            term_combination: TermCombination::default(),
+            tsquery_syntax: TsQuerySyntax::default(),
+            retrieval_fallbacks: vec![RetrievalFallback::WebSearch, RetrievalFallback::Trigram],
+            primary_keyword_boost: 1.0,
+            keyword_scorer: KeywordScorer::default(),
+            field_weights: None,
+            persist_on_demand: true,
+            embedding_mode: EmbeddingMode::default(),
+            precomputed_policy: PrecomputedPolicy::default(),
+            vector_score_source: VectorScoreSource::default(),
+            similarity_metric: SimilarityMetric::default(),
+            offline: false,
+            normalize_unicode: false,
+            recency_boost: None,
+            text_search_config: None,
+            original_terms_weight: None,
+            metadata_columns: Vec::new(),
+            rerank_timeout: None,
+            graph_boost: None,
+            popularity: None,
+            query_kind_weights: Some(QueryKindWeights::default()),
+            spelling_correction: None,
+            concurrent_query_embedding: false,
+            embeddings_table: None,
+            embedding_text_column: None,
+            max_embedding_chars: 8000,
+            embedding_concurrency: 4,
+            keyword_max_ngram: DEFAULT_KEYWORD_MAX_NGRAM,
+        }
+    }
+}
+
+impl SearchConfig {
+    // 校验配置中直接拼接进SQL的名称，在构建搜索模块和各嵌入函数的入口处检查
+    pub(crate) fn validate_identifiers(&self) -> Result<(), SearchError> {
+        if let Some(embeddings_table) = &self.embeddings_table {
+            validate_identifier(embeddings_table)?;
+        }
+        if let Some(column) = &self.embedding_text_column {
+            validate_identifier(column)?;
+        }
+        Ok(())
+    }
+
+    // 存放嵌入向量的表：配置了独立嵌入表时为该表，否则为crate表本身
+    pub(crate) fn embedding_table_for<'t>(&'t self, table_name: &'t str) -> &'t str {
+        self.embeddings_table.as_deref().unwrap_or(table_name)
+    }
+
+    // 读取crate行时连接独立嵌入表的子句，之后可用 `{embedding_table_for}.embedding` 引用向量；
+    // 未配置独立嵌入表时为空
+    pub(crate) fn embedding_join(&self, table_name: &str) -> String {
+        match &self.embeddings_table {
+            Some(embeddings) => {
+                format!(" LEFT JOIN {0} ON {0}.id = {1}.id", embeddings, table_name)
+            }
+            None => String::new(),
+        }
+    }
+
+    // 解析改写后关键词的tsquery函数调用，与 `tsquery_syntax` 对应
+    pub(crate) fn keyword_tsquery_call(&self, argument: &str) -> String {
+        match self.tsquery_syntax {
+            TsQuerySyntax::ToTsQuery => self.tsquery_call("to_tsquery", argument),
+            TsQuerySyntax::WebSearch => self.tsquery_call("websearch_to_tsquery", argument),
+        }
+    }
+
+    // 截断结果时使用的数量上限，result_limit 为None时不截断
+    pub(crate) fn result_cap(&self) -> usize {
+        self.result_limit.unwrap_or(usize::MAX)
+    }
+
+    // 生成tsquery函数调用，如 `to_tsquery('simple', $1)`，未指定文本搜索配置时省略
+    pub(crate) fn tsquery_call(&self, function: &str, argument: &str) -> String {
+        match &self.text_search_config {
+            Some(ts_config) => format!(
+                "{}('{}', {})",
+                function,
+                ts_config.replace('\'', "''"),
+                argument
+            ),
+            None => format!("{}({})", function, argument),
+        }
+    }
+
+    // 按查询类型调整各排序方式权重后的配置，未启用 query_kind_weights 时原样返回
+    pub(crate) fn for_query_kind(&self, kind: QueryKind) -> Cow<'_, SearchConfig> {
+        match self.query_kind_weights {
+            Some(kind_weights) => Cow::Owned(SearchConfig {
+                comprehensive: kind_weights.adjust(self.comprehensive, kind),
+                relevance: kind_weights.adjust(self.relevance, kind),
+                downloads: kind_weights.adjust(self.downloads, kind),
+                ..self.clone()
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// 获取指定排序方式对应的权重，`Custom` 返回调用方指定的（未归一化的）关键词与向量权重
+    pub fn weights_for(&self, sort_criteria: &SearchSortCriteria) -> ScoreWeights {
+        match sort_criteria {
+            SearchSortCriteria::Comprehensive => self.comprehensive,
+            SearchSortCriteria::Relavance => self.relevance,
+            SearchSortCriteria::Downloads => self.downloads,
+            SearchSortCriteria::Custom {
+                keyword, vector, ..
+            } => ScoreWeights::new(*keyword, *vector),
+        }
+    }
+}
+
+/// 单次搜索请求的选项
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    // 相关性反馈：crate名称 -> 调整值，取值范围 [-1, 1]，正值提升、负值降低排名
+    pub feedback: HashMap<String, f32>,
+    // 要检索的数据表，为空时使用模块配置的表；多个表时合并各表的候选结果后统一重排序
+    pub tables: Vec<String>,
+    // 最终得分下限，低于该值的结果被丢弃
+    pub min_final_score: Option<f32>,
+    // 所有结果都低于下限时是否允许返回空列表，默认仍保留得分最高的一个
+    pub allow_empty_results: bool,
+    // 是否统计匹配结果在各分类和关键词下的数量，结果见 SearchOutcome::facets
+    pub with_facets: bool,
+    // 是否额外查询匹配总数（用于分页），结果见 SearchOutcome::total_matches
+    pub with_total_count: bool,
+    // 是否按crate家族（名称中第一个 `-` 之前的部分）分组，结果见 SearchOutcome::groups
+    pub group_by_family: bool,
+    // 自定义过滤条件，在重排序之后、截断到 result_limit 之前应用，返回false的crate被丢弃
+    pub filter: Option<ResultFilter>,
+    // 版本要求，只保留 `version` 满足该要求的crate，在截断到 result_limit 之前应用
+    pub version_req: Option<VersionReq>,
+    // 设置了版本要求时，版本号缺失或无法解析的crate是否保留，默认丢弃
+    pub keep_unparsable_versions: bool,
+    // 是否在结果中附带重排序时已获取的嵌入向量（RecommendCrate::embedding），不额外查询数据库；
+    // 由数据库计算向量相似度（VectorScoreSource::Postgres）或未进行向量重排序时不附带
+    pub include_embeddings: bool,
+    // 计算得分时使用的查询类型（见 SearchConfig::query_kind_weights），为None时自动识别
+    pub query_kind: Option<QueryKind>,
+    // 是否统计改写、检索、获取嵌入向量和重排序各阶段的耗时，结果见 SearchOutcome::timings
+    pub with_timings: bool,
+    // 不应出现在结果中的crate（名称或id），如恶意或仿冒的crate；
+    // 在重排序之后、截断到 result_limit 之前排除，列表较短时检索阶段也直接排除
+    pub exclude: Vec<String>,
+}
+
+impl SearchOptions {
+    /// 设置结果过滤条件，例如隐藏已撤回或没有许可证的crate
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: FnMut(&RecommendCrate) -> bool + Send + 'static,
+    {
+        self.filter = Some(ResultFilter::new(filter));
+        self
+    }
+}
+
+// 过滤函数，返回false的crate被丢弃
+type FilterFn = dyn FnMut(&RecommendCrate) -> bool + Send;
+
+/// 搜索结果的自定义过滤条件
+#[derive(Clone)]
+pub struct ResultFilter(Arc<Mutex<FilterFn>>);
+
+impl ResultFilter {
+    pub fn new<F>(filter: F) -> Self
+    where
+        F: FnMut(&RecommendCrate) -> bool + Send + 'static,
+    {
+        ResultFilter(Arc::new(Mutex::new(filter)))
+    }
+
+    // 判断crate是否保留，过滤函数panic导致锁中毒时仍继续使用
+    pub(crate) fn retain(&self, crates: &mut Vec<RecommendCrate>) {
+        let mut filter = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        crates.retain(|crate_item| filter(crate_item));
+    }
+}
+
+impl fmt::Debug for ResultFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResultFilter")
+    }
+}
+
+/// 调用外部接口（LLM、向量嵌入）时附加的HTTP设置，用于代理、网关等环境
+#[derive(Debug, Clone, Default)]
+pub struct HttpOptions {
+    // 请求使用的User-Agent
+    pub user_agent: Option<String>,
+    // 每个请求都附加的请求头，如组织ID、网关要求的认证头
+    pub headers: Vec<(String, String)>,
+    // 预先构建的HTTP客户端（如配置了代理、超时、TLS），设置后所有请求复用该客户端的连接池，
+    // 此时忽略 user_agent 和 headers
+    pub client: Option<Client>,
+}
+
+impl HttpOptions {
+    /// 从环境变量读取配置
+    ///
+    /// SEARCH_USER_AGENT；SEARCH_EXTRA_HEADERS 格式为 `名称: 值`，多个请求头以分号分隔
+    pub fn from_env() -> Self {
+        let headers = env::var("SEARCH_EXTRA_HEADERS")
+            .unwrap_or_default()
+            .split(';')
+            .filter_map(|header| {
+                let (name, value) = header.split_once(':')?;
+                let name = name.trim();
+                (!name.is_empty()).then(|| (name.to_string(), value.trim().to_string()))
+            })
+            .collect();
+
+        HttpOptions {
+            user_agent: env::var("SEARCH_USER_AGENT")
+                .ok()
+                .filter(|agent| !agent.is_empty()),
+            headers,
+            client: None,
+        }
+    }
+
+    /// 按配置构建HTTP客户端，无效的请求头会被忽略；设置了 `client` 时返回共享该连接池的副本
+    pub fn build_client(&self) -> Client {
+        if let Some(client) = &self.client {
+            return client.clone();
+        }
+
+        let mut header_map = HeaderMap::new();
+        for (name, value) in &self.headers {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    header_map.insert(name, value);
+                }
+                _ => eprintln!("忽略无效的请求头: {}", name),
+            }
+        }
+
+        let mut builder = Client::builder().default_headers(header_map);
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        builder.build().unwrap_or_else(|e| {
+            eprintln!("构建HTTP客户端失败，使用默认设置: {}", e);
+            Client::new()
+        })
+    }
+}
+
+/// OpenAI兼容接口的类型，决定请求地址的拼接方式和认证请求头
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ApiFlavor {
+    /// OpenAI（默认）：配置的地址即完整接口地址，使用 `Authorization: Bearer` 认证
+    #[default]
+    OpenAi,
+    /// Azure OpenAI：配置的地址为资源地址（如 `https://xxx.openai.azure.com`），
+    /// 请求发往 `/openai/deployments/{deployment}/...?api-version=...`，使用 `api-key` 请求头认证
+    Azure {
+        deployment: String,
+        api_version: String,
+    },
+}
+
+impl ApiFlavor {
+    // 设置了指定的部署名环境变量时使用Azure，api-version 取自 AZURE_OPENAI_API_VERSION
+    pub(crate) fn from_env(deployment_var: &str) -> Self {
+        match env::var(deployment_var) {
+            Ok(deployment) if !deployment.is_empty() => ApiFlavor::Azure {
+                deployment,
+                api_version: env::var("AZURE_OPENAI_API_VERSION")
+                    .unwrap_or_else(|_| "2024-02-01".to_string()),
+            },
+            _ => ApiFlavor::OpenAi,
+        }
+    }
+
+    /// 拼接接口地址，`operation` 为 `chat/completions` 或 `embeddings`
+    pub fn endpoint(&self, url: &str, operation: &str) -> String {
+        match self {
+            ApiFlavor::OpenAi => url.to_string(),
+            ApiFlavor::Azure {
+                deployment,
+                api_version,
+            } => format!(
+                "{}/openai/deployments/{}/{}?api-version={}",
+                url.trim_end_matches('/'),
+                deployment,
+                operation,
+                api_version
+            ),
+        }
+    }
+
+    // 为请求添加对应的认证请求头
+    pub(crate) fn authorize(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+        match self {
+            ApiFlavor::OpenAi => request.header("Authorization", format!("Bearer {}", api_key)),
+            ApiFlavor::Azure { .. } => request.header("api-key", api_key),
+        }
+    }
+}
+
+/// LLM 查询改写配置
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    // OpenAI API密钥，为空时不调用LLM
+    pub api_key: Option<String>,
+    // Chat Completions 接口地址
+    pub chat_url: String,
+    // 使用的模型名称
+    pub model: String,
+    // 是否使用LLM进行查询改写和关键词提取
+    pub enabled: bool,
+    // User-Agent和附加请求头
+    pub http: HttpOptions,
+    // 接口类型，Azure OpenAI时 chat_url 为资源地址
+    pub flavor: ApiFlavor,
+    // 改写和关键词提取请求的采样温度，设为0.0时回复基本固定，便于多次评估结果可比
+    pub temperature: f32,
+    // 回复的最大token数，为None时按请求类型使用默认值（关键词提取100、改写150、结构化改写200）
+    pub max_tokens: Option<u32>,
+    // 采样随机种子，接口支持时（如OpenAI）相同种子和参数的请求返回相同结果；为None时不发送
+    pub seed: Option<u64>,
+}
+
+impl LlmConfig {
+    /// 从环境变量读取配置（OPENAI_API_KEY、OPEN_AI_CHAT_URL）
+    ///
+    /// 设置 AZURE_OPENAI_CHAT_DEPLOYMENT 时使用Azure OpenAI，地址取自 AZURE_OPENAI_ENDPOINT
+    pub fn from_env() -> Self {
+        let flavor = ApiFlavor::from_env("AZURE_OPENAI_CHAT_DEPLOYMENT");
+        let chat_url = match flavor {
+            ApiFlavor::OpenAi => env::var("OPEN_AI_CHAT_URL")
+                .unwrap_or_else(|_| "https://api.openai.com/v1/chat/completions".to_string()),
+            ApiFlavor::Azure { .. } => env::var("AZURE_OPENAI_ENDPOINT").unwrap_or_default(),
+        };
+
+        LlmConfig {
+            api_key: env::var("OPENAI_API_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            chat_url,
+            model: "gpt-3.5-turbo".to_string(),
+            enabled: true,
+            http: HttpOptions::from_env(),
+            flavor,
+            temperature: 0.3,
+            max_tokens: None,
+            seed: None,
+        }
+    }
+
+    /// 实际请求的Chat Completions接口地址
+    pub fn chat_endpoint(&self) -> String {
+        self.flavor.endpoint(&self.chat_url, "chat/completions")
+    }
+
+    /// 返回可用的API密钥；未启用LLM或未配置密钥时返回None
+    pub fn active_api_key(&self) -> Option<&str> {
+        if self.enabled {
+            self.api_key.as_deref()
+        } else {
+            None
+        }
+    }
+}
@@ -1,23 +1,68 @@
-use crate::search::rerank::rerank_crates;
-use crate::search::retrieve::retrive_crates;
-use crate::search::rewrite::process_query;
-use crate::search::rewrite::rewrite_query;
+use crate::search::cache::{CachedEmbedder, PrefetchedEmbedder, QueryCache};
+use crate::search::config::{LlmConfig, SearchConfig, SearchOptions, VectorScoreSource};
+use crate::search::embedder::{Embedder, EmbeddingProvider};
+use crate::search::error::{normalize_error, SearchError};
+use crate::search::lang::classify_query;
+use crate::search::logger::{SearchLogger, SearchTrace};
+use crate::search::rerank::{
+    embed_query_with_retry, group_by_family, load_graph_edges, rank_by_keyword_only,
+    rank_keyword_only_finalized, rank_with_vector_scores, reciprocal_rank_fusion,
+    rerank_crates_in_tables,
+};
+use crate::search::retrieve::{
+    count_facets, count_matches, merge_original_terms, query_keywords,
+    retrive_crates_with_embedding, retrive_crates_with_fallbacks, transfer_query_to_tsquery,
+    CorpusStatsCache,
+};
+use crate::search::rewrite::prepare_query;
+use crate::search::spelling::CrateVocabulary;
+use crate::search::utils::{
+    basic_keyword_extraction_with_ngrams, normalize_unicode, validate_identifier,
+};
+use chrono::{DateTime, Utc};
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, OnceCell, Semaphore};
 use tokio_postgres::Client as PgClient;
 
 pub struct SearchModule<'a> {
     pub pg_client: &'a PgClient,
     pub table_name: String,
+    pub config: SearchConfig,
+    pub llm_config: LlmConfig,
+    pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) logger: Option<Arc<dyn SearchLogger>>,
+    pub(crate) query_cache: Arc<QueryCache>,
+    pub(crate) vocabulary: OnceCell<Arc<CrateVocabulary>>,
+    // 按需生成嵌入时调用嵌入接口和写回数据库的并发许可，在使用该模块的所有并发搜索之间共享
+    pub(crate) embedding_permits: Arc<Semaphore>,
+    pub(crate) corpus_stats: CorpusStatsCache,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SearchSortCriteria {
     Comprehensive,
     Relavance,
     Downloads,
+    /// 调用方指定的权重，四项得分按权重之和归一化后加权，用于在不修改配置的情况下调参
+    ///
+    /// 下载量得分为 `log10(1 + 下载量) / 8`（1亿次下载为满分）；更新时间得分按
+    /// `SearchConfig::recency_boost` 的半衰期衰减，只有启用该配置时才会读取更新时间，
+    /// 且不再额外叠加近期更新加分
+    Custom {
+        keyword: f32,
+        vector: f32,
+        downloads: f32,
+        recency: f32,
+    },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RecommendCrate {
     pub id: String,
     pub name: String,
@@ -25,49 +70,880 @@ pub struct RecommendCrate {
     pub rank: f32,
     pub vector_score: f32,
     pub final_score: f32,
+    // 最新版本号
+    pub version: Option<String>,
+    // 下载量
+    pub downloads: i64,
+    // 代码仓库地址
+    pub repository: Option<String>,
+    // crates.io上的关键词标签，数据库中为NULL时为空
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    // 最近一次更新时间，仅在启用近期更新加分时从数据库读取
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    // SearchConfig::metadata_columns 中配置的额外列，列名 -> 值，值为NULL的列不出现
+    #[serde(default)]
+    pub metadata: HashMap<String, serde_json::Value>,
+    // SearchConfig::popularity 中配置的热度列的值，未配置或值为NULL时为None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub popularity: Option<f64>,
+    // 重排序时使用的嵌入向量，仅在 SearchOptions::include_embeddings 为true时附带
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// 同一家族的crate（如 reqwest、reqwest-middleware、reqwest-retry）
+///
+/// `primary` 为家族中得分最高的crate，`related` 为其余成员，按得分降序排列
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateGroup {
+    pub primary: RecommendCrate,
+    pub related: Vec<RecommendCrate>,
+}
+
+/// 流式搜索推送的事件
+#[derive(Debug, Clone)]
+pub enum SearchEvent {
+    /// 按关键词得分排序的初步结果
+    KeywordResults(Vec<RecommendCrate>),
+    /// 向量重排序完成后的最终结果
+    Reranked(Vec<RecommendCrate>),
+    /// 搜索失败
+    Failed(String),
+}
+
+/// 带降级信息的搜索结果
+///
+/// 查询改写或向量重排序失败时搜索仍会返回结果，调用方可据此统计降级情况；
+/// 未启用LLM、离线模式等主动关闭的功能不计入降级原因
+#[derive(Debug, Clone, Default)]
+pub struct SearchOutcome {
+    // 排序后的搜索结果
+    pub results: Vec<RecommendCrate>,
+    // 是否实际使用了LLM改写查询
+    pub used_llm_rewrite: bool,
+    // 是否实际使用了向量重排序
+    pub used_vector_rerank: bool,
+    // 各阶段退回后备方案的原因
+    pub fallback_reasons: Vec<String>,
+    // 分类/关键词 -> 匹配的crate数量，仅在 SearchOptions::with_facets 为true时统计
+    pub facets: HashMap<String, usize>,
+    // 关键词检索的匹配总数（不受候选数量上限限制，不含后备检索），
+    // 仅在 SearchOptions::with_total_count 为true时统计
+    pub total_matches: Option<u64>,
+    // 按crate家族分组的结果，仅在 SearchOptions::group_by_family 为true时生成
+    pub groups: Vec<CrateGroup>,
+    // 拼写纠正后的查询，未启用 SearchConfig::spelling_correction 或没有需要纠正的词时为None
+    pub corrected_query: Option<String>,
+    // 各阶段耗时，仅在 SearchOptions::with_timings 为true时统计
+    pub timings: Option<StageTimings>,
+}
+
+/// 一次搜索各阶段的耗时（毫秒），见 `SearchOutcome::timings`
+///
+/// `rewrite_ms` 包含查询规范化和拼写纠正，`embed_ms` 为获取查询向量和候选嵌入向量的耗时，
+/// `rerank_ms` 为其余的排序、过滤等耗时。启用 `concurrent_query_embedding` 时
+/// 查询向量与改写、检索并发获取，这部分耗时不计入 `embed_ms`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StageTimings {
+    pub rewrite_ms: u64,
+    pub retrieve_ms: u64,
+    pub embed_ms: u64,
+    pub rerank_ms: u64,
+}
+
+impl SearchOutcome {
+    // 统计耗时时累加获取嵌入向量的耗时
+    pub(crate) fn record_embed_time(&mut self, elapsed: Duration) {
+        if let Some(timings) = &mut self.timings {
+            timings.embed_ms += elapsed.as_millis() as u64;
+        }
+    }
+}
+
+/// 带查询解释的搜索结果，见 `SearchModule::search_crate_with_response`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchResponse {
+    // 排序后的搜索结果
+    pub results: Vec<RecommendCrate>,
+    // LLM（或后备方案）改写后的查询，保留原始查询词时包含原始查询词
+    pub rewritten_query: String,
+    // 实际参与检索的关键词（已去重、转为小写，受 max_keywords 限制）
+    pub extracted_keywords: Vec<String>,
+}
+
+/// SearchModule 构建器
+///
+/// 未显式设置的项使用环境变量（TABLE_NAME、OPENAI_API_KEY、OPEN_AI_CHAT_URL、
+/// OPEN_AI_EMBEDDING_URL、EMBEDDING_PROVIDER）或默认值
+#[derive(Default)]
+pub struct SearchModuleBuilder {
+    table_name: Option<String>,
+    config: SearchConfig,
+    embedder: Option<Arc<dyn Embedder>>,
+    api_key: Option<String>,
+    chat_url: Option<String>,
+    chat_model: Option<String>,
+    use_llm_rewrite: Option<bool>,
+    user_agent: Option<String>,
+    headers: Vec<(String, String)>,
+    http_client: Option<HttpClient>,
+    logger: Option<Arc<dyn SearchLogger>>,
+    query_cache: Option<Arc<QueryCache>>,
+    vocabulary: Option<Arc<CrateVocabulary>>,
+    embeddings_table: Option<String>,
+    embedding_permits: Option<Arc<Semaphore>>,
+}
+
+impl SearchModuleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 设置crate数据表名
+    pub fn table_name(mut self, table_name: impl Into<String>) -> Self {
+        self.table_name = Some(table_name.into());
+        self
+    }
+
+    /// 设置得分权重等搜索配置
+    pub fn config(mut self, config: SearchConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 设置向量嵌入提供者
+    pub fn embedder(mut self, embedder: impl Embedder + 'static) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// 通过配置选择向量嵌入提供者（OpenAI、Cohere或通用HTTP接口）
+    pub fn embedding_provider(mut self, provider: EmbeddingProvider) -> Self {
+        self.embedder = Some(provider.into_embedder());
+        self
+    }
+
+    /// 设置LLM使用的API密钥
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// 设置Chat Completions接口地址
+    pub fn chat_url(mut self, chat_url: impl Into<String>) -> Self {
+        self.chat_url = Some(chat_url.into());
+        self
+    }
+
+    /// 设置查询改写使用的模型
+    pub fn chat_model(mut self, chat_model: impl Into<String>) -> Self {
+        self.chat_model = Some(chat_model.into());
+        self
+    }
+
+    /// 设置是否使用LLM改写查询
+    pub fn use_llm_rewrite(mut self, use_llm_rewrite: bool) -> Self {
+        self.use_llm_rewrite = Some(use_llm_rewrite);
+        self
+    }
+
+    /// 设置调用LLM和向量嵌入接口时使用的User-Agent
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// 添加调用LLM和向量嵌入接口时附加的请求头
+    ///
+    /// 通过 `embedder` 显式设置的嵌入提供者不受影响
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// 设置调用LLM和向量嵌入接口时复用的HTTP客户端（如配置了代理、超时、TLS）
+    ///
+    /// 设置后 `user_agent` 和 `header` 不再生效，应在构建客户端时自行配置；
+    /// 通过 `embedder` 显式设置的嵌入提供者不受影响
+    pub fn http_client(mut self, client: HttpClient) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// 设置搜索日志钩子，每次搜索成功后调用一次
+    pub fn logger(mut self, logger: impl SearchLogger + 'static) -> Self {
+        self.logger = Some(Arc::new(logger));
+        self
+    }
+
+    /// 使用共享的热门查询缓存，未设置时每个模块使用各自的空缓存
+    pub fn query_cache(mut self, cache: Arc<QueryCache>) -> Self {
+        self.query_cache = Some(cache);
+        self
+    }
+
+    /// 拼写纠正使用的crate名称词表，未设置时在启用 `spelling_correction` 后的首次搜索中从数据表加载
+    pub fn vocabulary(mut self, vocabulary: Arc<CrateVocabulary>) -> Self {
+        self.vocabulary = Some(vocabulary);
+        self
+    }
+
+    /// 使用共享的按需嵌入并发许可，未设置时每个模块按 `SearchConfig::embedding_concurrency` 创建各自的许可
+    pub fn embedding_permits(mut self, permits: Arc<Semaphore>) -> Self {
+        self.embedding_permits = Some(permits);
+        self
+    }
+
+    /// 设置独立存放嵌入向量的表名，覆盖 `SearchConfig::embeddings_table`，不合法时构建失败
+    pub fn embeddings_table(mut self, embeddings_table: impl Into<String>) -> Self {
+        self.embeddings_table = Some(embeddings_table.into());
+        self
+    }
+
+    /// 构建搜索模块，配置无效时 panic，需要处理错误时使用 [`SearchModuleBuilder::try_build`]
+    pub fn build(self, pg_client: &PgClient) -> SearchModule<'_> {
+        self.try_build(pg_client)
+            .unwrap_or_else(|e| panic!("构建搜索模块失败: {}", e))
+    }
+
+    /// 构建搜索模块，嵌入表名、嵌入文本列名等直接拼接进SQL的名称不合法时返回 `SearchError::Config`
+    pub fn try_build(self, pg_client: &PgClient) -> Result<SearchModule<'_>, SearchError> {
+        let table_name = self
+            .table_name
+            .unwrap_or_else(|| env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string()));
+
+        let mut llm_config = LlmConfig::from_env();
+        if let Some(api_key) = self.api_key {
+            llm_config.api_key = Some(api_key);
+        }
+        if let Some(chat_url) = self.chat_url {
+            llm_config.chat_url = chat_url;
+        }
+        if let Some(chat_model) = self.chat_model {
+            llm_config.model = chat_model;
+        }
+        if let Some(use_llm_rewrite) = self.use_llm_rewrite {
+            llm_config.enabled = use_llm_rewrite;
+        }
+        if let Some(user_agent) = self.user_agent {
+            llm_config.http.user_agent = Some(user_agent);
+        }
+        llm_config.http.headers.extend(self.headers);
+        if let Some(client) = self.http_client {
+            llm_config.http.client = Some(client);
+        }
+
+        let embedder = self.embedder.unwrap_or_else(|| {
+            EmbeddingProvider::from_env()
+                .with_http_options(&llm_config.http)
+                .into_embedder()
+        });
+
+        let mut config = self.config;
+        if let Some(embeddings_table) = self.embeddings_table {
+            config.embeddings_table = Some(embeddings_table);
+        }
+        // 表名和列名直接拼接进SQL，不合法时拒绝构建
+        config.validate_identifiers()?;
+
+        let embedding_permits = self
+            .embedding_permits
+            .unwrap_or_else(|| Arc::new(Semaphore::new(config.embedding_concurrency.max(1))));
+
+        Ok(SearchModule {
+            pg_client,
+            table_name,
+            config,
+            llm_config,
+            embedder,
+            logger: self.logger,
+            query_cache: self.query_cache.unwrap_or_default(),
+            vocabulary: OnceCell::new_with(self.vocabulary),
+            embedding_permits,
+            corpus_stats: CorpusStatsCache::default(),
+        })
+    }
 }
 
 impl<'a> SearchModule<'a> {
     pub async fn new(pg_client: &'a PgClient) -> Self {
-        let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
-        SearchModule {
-            pg_client: pg_client,
-            table_name,
-        }
+        SearchModuleBuilder::new().build(pg_client)
     }
 
+    pub fn builder() -> SearchModuleBuilder {
+        SearchModuleBuilder::new()
+    }
+
+    /// 搜索crate
+    ///
+    /// 取消安全：搜索过程只读取数据库，唯一的写入是按需生成的嵌入向量，
+    /// 由一条UPDATE语句整体写回，因此在任意时刻丢弃返回的future
+    /// （如客户端断开、外层 `tokio::time::timeout` 超时）都不会留下写了一半的数据。
+    ///
+    /// 数据库错误以 `SearchError::Database` 返回，可向下转型后用 `SearchError::is_retriable`
+    /// 区分连接断开（可在新连接上重试）与查询本身的错误
     pub async fn search_crate(
         &self,
         query: &str,
         sort_by: SearchSortCriteria,
     ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
-        let processed_query = process_query(query).await;
+        self.search_crate_with_options(query, sort_by, &SearchOptions::default())
+            .await
+    }
+
+    /// 带单次请求选项（如相关性反馈）的搜索
+    pub async fn search_crate_with_options(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+        options: &SearchOptions,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        Ok(self
+            .search_crate_with_outcome(query, sort_by, options)
+            .await?
+            .results)
+    }
+
+    /// 搜索并返回各阶段是否退回了后备方案
+    pub async fn search_crate_with_outcome(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+        options: &SearchOptions,
+    ) -> Result<SearchOutcome, Box<dyn std::error::Error>> {
+        self.search_traced(query, sort_by, options)
+            .await
+            .map(|(outcome, _)| outcome)
+            .map_err(normalize_error)
+    }
+
+    /// 搜索并返回查询被解释成的改写结果和关键词列表，便于界面展示“搜索了：…”并允许用户修改
+    pub async fn search_crate_with_response(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+        options: &SearchOptions,
+    ) -> Result<SearchResponse, Box<dyn std::error::Error>> {
+        let (outcome, trace) = self
+            .search_traced(query, sort_by, options)
+            .await
+            .map_err(normalize_error)?;
+        Ok(SearchResponse {
+            results: outcome.results,
+            extracted_keywords: query_keywords(&trace.rewritten_query, self.config.max_keywords),
+            rewritten_query: trace.rewritten_query,
+        })
+    }
+
+    // 执行搜索，同时返回记录改写结果和各阶段耗时的跟踪信息
+    async fn search_traced(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+        options: &SearchOptions,
+    ) -> Result<(SearchOutcome, SearchTrace), Box<dyn std::error::Error>> {
+        let started_at = Instant::now();
+        let timestamp = Utc::now();
+        let mut outcome = SearchOutcome {
+            timings: options.with_timings.then(StageTimings::default),
+            ..Default::default()
+        };
+        let mut trace = SearchTrace::default();
+        let logged_sort_by = sort_by.clone();
+
+        if self.config.vector_score_source == VectorScoreSource::Postgres && !self.config.offline {
+            outcome.results = self
+                .search_with_database_vector_scores(
+                    query,
+                    sort_by,
+                    options,
+                    &mut outcome,
+                    &mut trace,
+                )
+                .await?;
+        } else {
+            // 获取基于关键词的检索结果；启用 concurrent_query_embedding 时同时获取查询向量
+            let cached_embedder = self.search_embedder();
+            let (keyword_results, query_embedding) =
+                if self.config.concurrent_query_embedding && !self.config.offline {
+                    let (keyword_results, query_embedding) = tokio::join!(
+                        self.retrieve_candidates(query, options, None, &mut outcome, &mut trace),
+                        embed_query_with_retry(&cached_embedder, query)
+                    );
+                    (keyword_results?, query_embedding)
+                } else {
+                    let keyword_results = self
+                        .retrieve_candidates(query, options, None, &mut outcome, &mut trace)
+                        .await?;
+                    (keyword_results, None)
+                };
+            // 并发获取失败时由重排序阶段照常获取（并重试）查询向量
+            let prefetched = query_embedding.map(|embedding| PrefetchedEmbedder {
+                inner: &cached_embedder,
+                text: query,
+                embedding,
+            });
+            let embedder: &dyn Embedder = match &prefetched {
+                Some(prefetched) => prefetched,
+                None => &cached_embedder,
+            };
 
-        // 使用处理后的查询进行改写
-        let rewritten_query = match rewrite_query(&processed_query).await {
-            Ok(q) => q,
+            // 获取向量嵌入并进行混合排序
+            outcome.results = rerank_crates_in_tables(
+                keyword_results,
+                query,
+                sort_by,
+                self.pg_client,
+                embedder,
+                Some(&self.embedding_permits),
+                &self.scoring_config(query, options),
+                options,
+                &mut outcome,
+            )
+            .await?;
+        }
+
+        if options.group_by_family {
+            outcome.groups = group_by_family(&outcome.results);
+        }
+
+        // 重排序阶段的耗时为总耗时减去改写、检索和获取嵌入向量的耗时
+        if let Some(timings) = &mut outcome.timings {
+            timings.rewrite_ms = trace.rewrite.as_millis() as u64;
+            timings.retrieve_ms = trace.retrieve.as_millis() as u64;
+            let remaining = started_at
+                .elapsed()
+                .saturating_sub(trace.rewrite + trace.retrieve);
+            timings.rerank_ms = (remaining.as_millis() as u64).saturating_sub(timings.embed_ms);
+        }
+
+        if let Some(logger) = &self.logger {
+            logger.log(trace.to_record(
+                query,
+                logged_sort_by,
+                timestamp,
+                started_at.elapsed(),
+                &outcome,
+            ));
+        }
+
+        Ok((outcome, trace))
+    }
+
+    /// 按多种排序方式分别搜索，再用倒数排名融合（RRF）合并为一个综合排名
+    ///
+    /// 关键词检索只执行一次，各排序方式分别重排序后取前 `k` 个结果参与融合，
+    /// 返回结果的 `final_score` 为融合得分（见 `reciprocal_rank_fusion`），仍受 `result_limit` 限制。
+    /// 在多种排序方式下都靠前的crate排在前面；`criteria` 为空时返回配置错误
+    pub async fn search_consensus(
+        &self,
+        query: &str,
+        criteria: &[SearchSortCriteria],
+        k: usize,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        self.search_consensus_inner(query, criteria, k)
+            .await
+            .map_err(normalize_error)
+    }
+
+    async fn search_consensus_inner(
+        &self,
+        query: &str,
+        criteria: &[SearchSortCriteria],
+        k: usize,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        if criteria.is_empty() {
+            return Err(Box::new(SearchError::Config(
+                "综合排名至少需要一种排序方式".to_string(),
+            )));
+        }
+
+        let options = SearchOptions::default();
+        let mut outcome = SearchOutcome::default();
+        let keyword_results = self
+            .retrieve_candidates(
+                query,
+                &options,
+                None,
+                &mut outcome,
+                &mut SearchTrace::default(),
+            )
+            .await?;
+
+        let mut rankings = Vec::with_capacity(criteria.len());
+        for sort_by in criteria {
+            rankings.push(
+                rerank_crates_in_tables(
+                    keyword_results.clone(),
+                    query,
+                    sort_by.clone(),
+                    self.pg_client,
+                    &self.search_embedder(),
+                    Some(&self.embedding_permits),
+                    &self.scoring_config(query, &options),
+                    &options,
+                    &mut outcome,
+                )
+                .await?,
+            );
+        }
+
+        let mut results = reciprocal_rank_fusion(rankings, k);
+        results.truncate(self.config.result_cap());
+        Ok(results)
+    }
+
+    /// 分阶段推送结果的搜索
+    ///
+    /// 先发送按关键词得分排序的结果，向量重排序完成后再发送最终结果。
+    /// 调用方自行创建channel并与该future并发消费接收端，例如放在 `tokio::join!` 中；
+    /// 接收端被丢弃时提前结束搜索
+    pub async fn search_crate_stream(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+        sender: mpsc::Sender<SearchEvent>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.search_crate_stream_inner(query, sort_by, sender)
+            .await
+            .map_err(normalize_error)
+    }
+
+    async fn search_crate_stream_inner(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+        sender: mpsc::Sender<SearchEvent>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let options = SearchOptions::default();
+        let mut outcome = SearchOutcome::default();
+        let keyword_results = match self
+            .retrieve_candidates(
+                query,
+                &options,
+                None,
+                &mut outcome,
+                &mut SearchTrace::default(),
+            )
+            .await
+        {
+            Ok(results) => results,
             Err(e) => {
-                eprintln!("查询改写失败: {}", e);
-                processed_query // 如果改写失败则使用处理后的查询
+                let _ = sender.send(SearchEvent::Failed(e.to_string())).await;
+                return Err(e);
             }
         };
 
-        println!("改写后的查询: {}", rewritten_query);
-
-        // 获取基于关键词的检索结果
-        let keyword_results =
-            retrive_crates(self.pg_client, &self.table_name, &rewritten_query).await?;
+        // 1. 关键词检索结果
+        let keyword_ranked = rank_by_keyword_only(
+            keyword_results
+                .iter()
+                .flat_map(|(_, crates)| crates.iter().cloned())
+                .collect(),
+            self.config.result_cap(),
+        );
+        if sender
+            .send(SearchEvent::KeywordResults(keyword_ranked))
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
 
-        // 获取向量嵌入并进行混合排序
-        let ranked_results = rerank_crates(
+        // 2. 向量重排序后的最终结果
+        match rerank_crates_in_tables(
             keyword_results,
             query,
             sort_by,
             self.pg_client,
-            &self.table_name,
+            &self.search_embedder(),
+            Some(&self.embedding_permits),
+            &self.scoring_config(query, &options),
+            &options,
+            &mut outcome,
+        )
+        .await
+        {
+            Ok(ranked) => {
+                let _ = sender.send(SearchEvent::Reranked(ranked)).await;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = sender.send(SearchEvent::Failed(e.to_string())).await;
+                Err(e)
+            }
+        }
+    }
+
+    // 设置了 original_terms_weight 时，把原始查询中的关键词并入改写结果，
+    // 返回合并后的查询和用于额外加权的原始查询词
+    pub(crate) fn with_original_terms(
+        &self,
+        query: &str,
+        rewritten_query: String,
+    ) -> (String, Vec<String>) {
+        if self.config.original_terms_weight.is_none() {
+            return (rewritten_query, Vec::new());
+        }
+        let original_terms = query_keywords(
+            &basic_keyword_extraction_with_ngrams(query, self.config.keyword_max_ngram),
+            self.config.max_keywords,
+        );
+        (
+            merge_original_terms(&rewritten_query, &original_terms),
+            original_terms,
         )
-        .await?;
+    }
+
+    // 启用 normalize_unicode 时对查询做NFKC规范化，否则原样返回
+    pub(crate) fn normalize_query<'q>(&self, query: &'q str) -> Cow<'q, str> {
+        if self.config.normalize_unicode {
+            Cow::Owned(normalize_unicode(query))
+        } else {
+            Cow::Borrowed(query)
+        }
+    }
+
+    /// 热门查询缓存，可交给 `spawn_cache_warmer` 预热或在多个模块之间共享
+    pub fn query_cache(&self) -> &Arc<QueryCache> {
+        &self.query_cache
+    }
+
+    // 搜索时使用的嵌入提供者，查询向量优先从热门查询缓存读取
+    pub(crate) fn search_embedder(&self) -> CachedEmbedder<'_> {
+        CachedEmbedder {
+            inner: self.embedder.as_ref(),
+            cache: &self.query_cache,
+        }
+    }
+
+    // 启用 spelling_correction 时按crate名称词表纠正查询，词表只在首次使用时加载；
+    // 加载失败时不纠正，下次搜索重新加载
+    async fn correct_spelling<'q>(
+        &self,
+        query: &'q str,
+        outcome: &mut SearchOutcome,
+    ) -> Cow<'q, str> {
+        let Some(correction) = self.config.spelling_correction else {
+            return Cow::Borrowed(query);
+        };
+        let vocabulary = self
+            .vocabulary
+            .get_or_try_init(|| async {
+                CrateVocabulary::load(
+                    self.pg_client,
+                    &self.table_name,
+                    correction.vocabulary_limit,
+                )
+                .await
+                .map(Arc::new)
+            })
+            .await;
+        match vocabulary {
+            Ok(vocabulary) => {
+                let corrected = vocabulary.correct(query, correction.max_edit_distance);
+                if corrected == query {
+                    return Cow::Borrowed(query);
+                }
+                println!("拼写纠正后的查询: {}", corrected);
+                outcome.corrected_query = Some(corrected.clone());
+                Cow::Owned(corrected)
+            }
+            Err(e) => {
+                outcome
+                    .fallback_reasons
+                    .push(format!("加载crate名称词表失败，不纠正拼写: {}", e));
+                Cow::Borrowed(query)
+            }
+        }
+    }
+
+    // 改写查询，预热过的查询直接使用缓存的LLM改写结果；离线模式下不读取缓存
+    async fn rewrite_with_cache(&self, query: &str, outcome: &mut SearchOutcome) -> String {
+        if !self.config.offline {
+            if let Some(rewritten) = self.query_cache.rewrite(query) {
+                outcome.used_llm_rewrite = true;
+                return rewritten;
+            }
+        }
+        prepare_query(
+            query,
+            &self.active_llm_config(),
+            self.config.keyword_max_ngram,
+            outcome,
+        )
+        .await
+    }
+
+    // 计算得分使用的配置：按查询类型（调用方指定或自动识别）调整关键词与向量得分的比例
+    fn scoring_config(&self, query: &str, options: &SearchOptions) -> Cow<'_, SearchConfig> {
+        let kind = options
+            .query_kind
+            .unwrap_or_else(|| classify_query(&self.normalize_query(query)));
+        self.config.for_query_kind(kind)
+    }
+
+    // 实际使用的LLM配置，离线模式下强制关闭LLM
+    pub(crate) fn active_llm_config(&self) -> Cow<'_, LlmConfig> {
+        if self.config.offline {
+            Cow::Owned(LlmConfig {
+                enabled: false,
+                ..self.llm_config.clone()
+            })
+        } else {
+            Cow::Borrowed(&self.llm_config)
+        }
+    }
+
+    // 先获取查询向量，在检索SQL中由数据库计算向量相似度
+    async fn search_with_database_vector_scores(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+        options: &SearchOptions,
+        outcome: &mut SearchOutcome,
+        trace: &mut SearchTrace,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        let embed_started_at = Instant::now();
+        let query_embedding = embed_query_with_retry(&self.search_embedder(), query).await;
+        outcome.record_embed_time(embed_started_at.elapsed());
+        let crates: Vec<RecommendCrate> = self
+            .retrieve_candidates(query, options, query_embedding.as_deref(), outcome, trace)
+            .await?
+            .into_iter()
+            .flat_map(|(_, crates)| crates)
+            .collect();
+        let ids: Vec<&str> = crates.iter().map(|c| c.id.as_str()).collect();
+        let edges = load_graph_edges(self.pg_client, &ids, &self.config, outcome).await;
+
+        Ok(match query_embedding {
+            Some(_) => {
+                outcome.used_vector_rerank = true;
+                let config = self.scoring_config(query, options);
+                rank_with_vector_scores(crates, query, sort_by, &config, options, &edges)
+            }
+            None => {
+                eprintln!("无法获取查询向量，使用仅关键词排序");
+                outcome
+                    .fallback_reasons
+                    .push("无法获取查询向量，使用仅关键词排序".to_string());
+                rank_keyword_only_finalized(crates, query, &self.config, options, &edges)
+            }
+        })
+    }
+
+    // 处理和改写查询后对每个数据表执行关键词检索
+    async fn retrieve_candidates(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        query_embedding: Option<&[f32]>,
+        outcome: &mut SearchOutcome,
+        trace: &mut SearchTrace,
+    ) -> Result<Vec<(String, Vec<RecommendCrate>)>, Box<dyn std::error::Error>> {
+        // 指定了多个表时分别检索；表名直接拼接进SQL，检索前先校验
+        let tables = if options.tables.is_empty() {
+            vec![self.table_name.clone()]
+        } else {
+            options.tables.clone()
+        };
+        for table_name in &tables {
+            validate_identifier(table_name)?;
+        }
+
+        let rewrite_started_at = Instant::now();
+        let query = self.normalize_query(query);
+        let query = self.correct_spelling(&query, outcome).await;
+        let query = query.as_ref();
+        let rewritten_query = self.rewrite_with_cache(query, outcome).await;
+        let rewritten_query = self.normalize_query(&rewritten_query).into_owned();
+        let (rewritten_query, original_terms) = self.with_original_terms(query, rewritten_query);
+        trace.rewrite = rewrite_started_at.elapsed();
+
+        println!("改写后的查询: {}", rewritten_query);
+        let retrieve_started_at = Instant::now();
+        let tsquery = transfer_query_to_tsquery(&rewritten_query, &self.config).await?;
+
+        let mut keyword_results = Vec::with_capacity(tables.len());
+        for table_name in tables {
+            let results = retrive_crates_with_embedding(
+                self.pg_client,
+                &table_name,
+                &rewritten_query,
+                query,
+                &original_terms,
+                &options.exclude,
+                &self.config,
+                query_embedding,
+                Some(&self.corpus_stats),
+            )
+            .await?;
+
+            // 改写后的关键词没有命中时，用原始查询依次尝试后备检索方式
+            let results = if results.is_empty() {
+                match retrive_crates_with_fallbacks(
+                    self.pg_client,
+                    &table_name,
+                    query,
+                    &options.exclude,
+                    &self.config,
+                )
+                .await?
+                {
+                    Some((fallback, fallback_results)) => {
+                        let reason = format!(
+                            "数据表 {} 的关键词检索没有结果，使用后备检索方式 {:?}",
+                            table_name, fallback
+                        );
+                        println!("{}", reason);
+                        outcome.fallback_reasons.push(reason);
+                        fallback_results
+                    }
+                    None => results,
+                }
+            } else {
+                results
+            };
+            keyword_results.push((table_name, results));
+        }
+
+        // 使用与检索相同的匹配条件统计分面数量和匹配总数，多个表的计数相加
+        if options.with_facets || options.with_total_count {
+            for (table_name, _) in &keyword_results {
+                if options.with_facets {
+                    let facets = count_facets(
+                        self.pg_client,
+                        table_name,
+                        &tsquery,
+                        query,
+                        &options.exclude,
+                        &self.config,
+                    )
+                    .await?;
+                    for (facet, count) in facets {
+                        *outcome.facets.entry(facet).or_insert(0) += count;
+                    }
+                }
+                if options.with_total_count {
+                    let count = count_matches(
+                        self.pg_client,
+                        table_name,
+                        &tsquery,
+                        query,
+                        &options.exclude,
+                        &self.config,
+                    )
+                    .await?;
+                    *outcome.total_matches.get_or_insert(0) += count;
+                }
+            }
+        }
 
-        Ok(ranked_results)
+        trace.retrieve = retrieve_started_at.elapsed();
+        trace.rewritten_query = rewritten_query;
+        trace.tsquery = tsquery;
+        Ok(keyword_results)
     }
 }
@@ -1,121 +1,822 @@
+use crate::search::config::{ApiFlavor, HttpOptions, SearchConfig};
 use crate::search::core::RecommendCrate;
+use crate::search::error::SearchError;
+use crate::search::utils::validate_identifier;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use pgvector::Vector;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
-use tokio_postgres::Client as PgClient;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore, SemaphorePermit};
+use tokio_postgres::{Client as PgClient, Row};
+
+// 增量更新时每批处理的crate数量
+const DEFAULT_EMBEDDING_BATCH_SIZE: usize = 100;
 
 /// 嵌入向量计算模式
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum EmbeddingMode {
     /// 预先计算模式：在系统非高峰期预先计算所有crate的嵌入向量并存储
     Precomputed,
     /// 搜索时计算模式（默认）：仅在搜索时为候选crate生成嵌入向量
+    #[default]
     OnDemand,
 }
 
-impl Default for EmbeddingMode {
+/// 预先计算模式下部分crate缺少嵌入向量时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PrecomputedPolicy {
+    /// 缺少向量的crate按仅关键词得分排序，其余crate仍使用向量得分（默认）
+    #[default]
+    FallbackToKeyword,
+    /// 返回错误，提示先运行 `precompute_all_embeddings`
+    Error,
+    /// 为缺失的crate即时计算嵌入向量，计算失败的crate按仅关键词得分排序
+    ComputeMissing,
+}
+
+/// 文本向量嵌入提供者
+///
+/// 实现该trait即可替换默认的OpenAI嵌入接口
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// 批量获取文本的向量嵌入
+    ///
+    /// 返回结果与输入一一对应、顺序一致；个别文本无法获取时对应位置为空向量，
+    /// 调用方应跳过空向量而不是按位置错位使用
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>>;
+
+    /// 获取单个文本的向量嵌入
+    async fn embed_one(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let embeddings = self.embed(&[text.to_string()]).await?;
+        embeddings
+            .into_iter()
+            .next()
+            .filter(|embedding| !embedding.is_empty())
+            .ok_or_else(|| "无法获取查询向量嵌入".into())
+    }
+
+    /// 返回向量的维度，None表示不限定
+    ///
+    /// 读取数据库中已存储的向量时，维度与之不一致的向量（如切换维度前生成的）会被忽略
+    fn dimensions(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// 不访问网络的确定性嵌入提供者，用于测试和基准测试
+///
+/// 把文本按非字母数字字符切分为小写词，每个词经FNV-1a哈希映射到一个维度并累加，
+/// 最后归一化为单位向量。相同文本总是得到相同向量，共享词越多的文本余弦相似度越高；
+/// 也可以用 `with_vector` 为指定文本固定返回的向量
+#[derive(Debug, Clone)]
+pub struct MockEmbedder {
+    dimensions: usize,
+    fixed: HashMap<String, Vec<f32>>,
+}
+
+impl Default for MockEmbedder {
     fn default() -> Self {
-        EmbeddingMode::OnDemand // 默认使用搜索时计算模式
+        MockEmbedder::new(64)
     }
 }
 
-// 获取查询的向量嵌入
-pub async fn get_query_embedding(query: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
-    // 将单个查询包装成一个批处理请求
-    let embeddings = batch_get_embeddings(&[query.to_string()]).await?;
+impl MockEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        MockEmbedder {
+            dimensions: dimensions.max(1),
+            fixed: HashMap::new(),
+        }
+    }
 
-    if embeddings.is_empty() {
-        return Err("无法获取查询向量嵌入".into());
+    /// 为指定文本固定返回的向量
+    pub fn with_vector(mut self, text: impl Into<String>, embedding: Vec<f32>) -> Self {
+        self.fixed.insert(text.into(), embedding);
+        self
     }
 
-    Ok(embeddings[0].clone())
+    /// 计算文本的向量，没有任何词的文本返回全零向量
+    pub fn embed_text(&self, text: &str) -> Vec<f32> {
+        if let Some(embedding) = self.fixed.get(text) {
+            return embedding.clone();
+        }
+
+        let mut embedding = vec![0.0f32; self.dimensions];
+        for word in text
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            // FNV-1a，保证不同平台和Rust版本下结果一致
+            let hash = word.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+            embedding[(hash % self.dimensions as u64) as usize] += 1.0;
+        }
+
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for value in embedding.iter_mut() {
+                *value /= norm;
+            }
+        }
+        embedding
+    }
 }
 
-// 批量获取向量嵌入
-pub async fn batch_get_embeddings(
-    texts: &[String],
-) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
-    if texts.is_empty() {
-        return Ok(Vec::new());
+#[async_trait]
+impl Embedder for MockEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        Ok(texts.iter().map(|text| self.embed_text(text)).collect())
     }
 
-    // 使用OpenAI API获取向量嵌入
-    if let Ok(api_key) = env::var("OPENAI_API_KEY") {
-        if !api_key.is_empty() {
-            let client = Client::new();
-            let embedding_url = env::var("OPEN_AI_EMBEDDING_URL")
-                .unwrap_or_else(|_| "https://api.openai.com/v1/embeddings".to_string());
+    fn dimensions(&self) -> Option<usize> {
+        Some(self.dimensions)
+    }
+}
 
-            #[derive(Serialize)]
-            struct BatchEmbeddingRequest {
-                model: String,
-                input: Vec<String>,
-            }
+/// 基于OpenAI Embeddings接口的嵌入提供者
+#[derive(Debug, Clone)]
+pub struct OpenAiEmbedder {
+    client: Client,
+    api_key: Option<String>,
+    url: String,
+    model: String,
+    flavor: ApiFlavor,
+    dimensions: Option<usize>,
+}
 
-            #[derive(Deserialize)]
-            struct EmbeddingData {
-                embedding: Vec<f32>,
-                index: usize,
-            }
+impl OpenAiEmbedder {
+    pub fn new(
+        api_key: impl Into<String>,
+        url: impl Into<String>,
+        model: impl Into<String>,
+    ) -> Self {
+        OpenAiEmbedder {
+            client: Client::new(),
+            api_key: Some(api_key.into()),
+            url: url.into(),
+            model: model.into(),
+            flavor: ApiFlavor::OpenAi,
+            dimensions: None,
+        }
+    }
 
-            #[derive(Deserialize)]
-            struct BatchEmbeddingResponse {
-                data: Vec<EmbeddingData>,
-            }
+    /// 设置返回向量的维度（text-embedding-3 系列支持的 `dimensions` 参数）
+    ///
+    /// 接口返回的向量长于该维度时（如代理服务忽略了该参数）在本地截断并重新归一化
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions).filter(|&dimensions| dimensions > 0);
+        self
+    }
 
-            // 每批处理的最大文本数
-            const BATCH_SIZE: usize = 100;
-            let mut all_embeddings = Vec::with_capacity(texts.len());
-
-            // 分批处理
-            for chunk in texts.chunks(BATCH_SIZE) {
-                let request = BatchEmbeddingRequest {
-                    model: "text-embedding-3-small".to_string(),
-                    input: chunk.to_vec(),
-                };
-
-                match client
-                    .post(&embedding_url)
-                    .header("Content-Type", "application/json")
-                    .header("Authorization", format!("Bearer {}", api_key))
-                    .json(&request)
-                    .send()
-                    .await
-                {
-                    Ok(response) => {
-                        if let Ok(embedding_resp) = response.json::<BatchEmbeddingResponse>().await
-                        {
-                            // 按索引排序，确保顺序与输入一致
-                            let mut sorted_data = embedding_resp.data;
-                            sorted_data.sort_by_key(|data| data.index);
-
-                            for data in sorted_data {
-                                all_embeddings.push(data.embedding);
-                            }
-                        } else {
-                            eprintln!("解析嵌入响应失败");
-                        }
+    /// 设置User-Agent和附加请求头
+    pub fn with_http_options(mut self, http: &HttpOptions) -> Self {
+        self.client = http.build_client();
+        self
+    }
+
+    /// 使用预先构建的HTTP客户端
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// 设置接口类型，Azure OpenAI时 `url` 为资源地址
+    pub fn with_flavor(mut self, flavor: ApiFlavor) -> Self {
+        self.flavor = flavor;
+        self
+    }
+
+    /// 从环境变量读取配置（OPENAI_API_KEY、OPEN_AI_EMBEDDING_URL、SEARCH_USER_AGENT、SEARCH_EXTRA_HEADERS）
+    ///
+    /// 设置 AZURE_OPENAI_EMBEDDING_DEPLOYMENT 时使用Azure OpenAI，地址取自 AZURE_OPENAI_ENDPOINT；
+    /// 设置 EMBEDDING_DIMENSIONS 时请求指定维度的向量
+    pub fn from_env() -> Self {
+        let flavor = ApiFlavor::from_env("AZURE_OPENAI_EMBEDDING_DEPLOYMENT");
+        let url = match flavor {
+            ApiFlavor::OpenAi => env::var("OPEN_AI_EMBEDDING_URL")
+                .unwrap_or_else(|_| "https://api.openai.com/v1/embeddings".to_string()),
+            ApiFlavor::Azure { .. } => env::var("AZURE_OPENAI_ENDPOINT").unwrap_or_default(),
+        };
+
+        OpenAiEmbedder {
+            client: HttpOptions::from_env().build_client(),
+            api_key: env::var("OPENAI_API_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            url,
+            model: "text-embedding-3-small".to_string(),
+            flavor,
+            dimensions: env::var("EMBEDDING_DIMENSIONS")
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .filter(|&dimensions: &usize| dimensions > 0),
+        }
+    }
+}
+
+impl OpenAiEmbedder {
+    // 请求一批文本的嵌入，返回 (批内索引, 向量)，可能少于输入数量
+    async fn request_batch(
+        &self,
+        api_key: &str,
+        texts: &[String],
+    ) -> Result<Vec<(usize, Vec<f32>)>, reqwest::Error> {
+        #[derive(Serialize)]
+        struct BatchEmbeddingRequest<'r> {
+            model: &'r str,
+            input: &'r [String],
+            #[serde(skip_serializing_if = "Option::is_none")]
+            dimensions: Option<usize>,
+        }
+
+        #[derive(Deserialize)]
+        struct EmbeddingData {
+            embedding: Vec<f32>,
+            index: usize,
+        }
+
+        #[derive(Deserialize)]
+        struct BatchEmbeddingResponse {
+            data: Vec<EmbeddingData>,
+        }
+
+        let request = BatchEmbeddingRequest {
+            model: &self.model,
+            input: texts,
+            dimensions: self.dimensions,
+        };
+
+        let http_request = self
+            .client
+            .post(self.flavor.endpoint(&self.url, "embeddings"))
+            .header("Content-Type", "application/json");
+        let response = self
+            .flavor
+            .authorize(http_request, api_key)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<BatchEmbeddingResponse>()
+            .await?;
+
+        Ok(response
+            .data
+            .into_iter()
+            .filter(|data| data.index < texts.len())
+            .map(|data| match self.dimensions {
+                Some(dimensions) => (data.index, truncate_embedding(data.embedding, dimensions)),
+                None => (data.index, data.embedding),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAiEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // 使用OpenAI API获取向量嵌入
+        let api_key = self.api_key.as_deref().ok_or("无法获取向量嵌入")?;
+
+        // 每批处理的最大文本数
+        const BATCH_SIZE: usize = 100;
+        let mut all_embeddings: Vec<Vec<f32>> = vec![Vec::new(); texts.len()];
+        // 因认证失败、请求无效等原因整批失败的文本，重新请求也不会成功
+        let mut unretriable = vec![false; texts.len()];
+        let mut last_error = None;
+
+        // 分批处理，按响应中的索引放回对应位置
+        for (chunk_index, chunk) in texts.chunks(BATCH_SIZE).enumerate() {
+            let offset = chunk_index * BATCH_SIZE;
+            match self.request_batch(api_key, chunk).await {
+                Ok(data) => {
+                    for (index, embedding) in data {
+                        all_embeddings[offset + index] = embedding;
                     }
-                    Err(e) => {
-                        eprintln!("批量获取向量嵌入失败: {}", e);
-                        // 继续处理其他批次
+                }
+                Err(e) => {
+                    eprintln!("批量获取向量嵌入失败: {}", e);
+                    // 继续处理其他批次，可重试的缺失部分稍后重新请求
+                    if !is_retriable_embedding_error(&e) {
+                        unretriable[offset..offset + chunk.len()].fill(true);
                     }
+                    last_error = Some(e);
                 }
             }
+        }
 
-            if !all_embeddings.is_empty() {
-                return Ok(all_embeddings);
+        // 响应缺少部分向量（或整批因可重试的原因失败）时，只重新请求缺失的文本一次
+        let missing: Vec<usize> = (0..texts.len())
+            .filter(|&i| all_embeddings[i].is_empty() && !unretriable[i])
+            .collect();
+        if !missing.is_empty() && missing.len() < texts.len() {
+            println!("有 {} 个文本未返回向量嵌入，重新请求", missing.len());
+        }
+        for missing_chunk in missing.chunks(BATCH_SIZE) {
+            let retry_texts: Vec<String> =
+                missing_chunk.iter().map(|&i| texts[i].clone()).collect();
+            match self.request_batch(api_key, &retry_texts).await {
+                Ok(data) => {
+                    for (index, embedding) in data {
+                        all_embeddings[missing_chunk[index]] = embedding;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("重新请求向量嵌入失败: {}", e);
+                    last_error = Some(e);
+                }
             }
         }
+
+        let still_missing = all_embeddings.iter().filter(|e| e.is_empty()).count();
+        if still_missing == texts.len() {
+            // 如果无法获取嵌入，返回最后一次请求的错误，便于区分认证失败等原因
+            return Err(match last_error {
+                Some(e) => Box::new(e),
+                None => "无法获取向量嵌入".into(),
+            });
+        }
+        if still_missing > 0 {
+            eprintln!("仍有 {} 个文本无法获取向量嵌入，已跳过", still_missing);
+        }
+
+        Ok(all_embeddings)
     }
 
-    // 如果无法获取嵌入，返回错误
-    Err("无法获取向量嵌入".into())
+    fn dimensions(&self) -> Option<usize> {
+        self.dimensions
+    }
+}
+
+/// 基于Cohere Embed接口的嵌入提供者
+///
+/// 文档使用 `search_document`、查询使用 `search_query` 作为 input_type
+#[derive(Debug, Clone)]
+pub struct CohereEmbedder {
+    client: Client,
+    api_key: Option<String>,
+    url: String,
+    model: String,
+}
+
+impl CohereEmbedder {
+    pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        CohereEmbedder {
+            client: Client::new(),
+            api_key: Some(api_key.into()),
+            url: "https://api.cohere.ai/v1/embed".to_string(),
+            model: model.into(),
+        }
+    }
+
+    /// 设置接口地址（默认 https://api.cohere.ai/v1/embed）
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// 设置User-Agent和附加请求头
+    pub fn with_http_options(mut self, http: &HttpOptions) -> Self {
+        self.client = http.build_client();
+        self
+    }
+
+    /// 使用预先构建的HTTP客户端
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// 从环境变量读取配置（COHERE_API_KEY、COHERE_EMBEDDING_URL、COHERE_EMBEDDING_MODEL）
+    pub fn from_env() -> Self {
+        CohereEmbedder {
+            client: HttpOptions::from_env().build_client(),
+            api_key: env::var("COHERE_API_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            url: env::var("COHERE_EMBEDDING_URL")
+                .unwrap_or_else(|_| "https://api.cohere.ai/v1/embed".to_string()),
+            model: env::var("COHERE_EMBEDDING_MODEL")
+                .unwrap_or_else(|_| "embed-english-v3.0".to_string()),
+        }
+    }
+
+    async fn embed_with_input_type(
+        &self,
+        texts: &[String],
+        input_type: &str,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let api_key = self.api_key.as_ref().ok_or("未配置Cohere API密钥")?;
+
+        #[derive(Serialize)]
+        struct CohereEmbedRequest<'r> {
+            model: &'r str,
+            texts: &'r [String],
+            input_type: &'r str,
+        }
+
+        #[derive(Deserialize)]
+        struct CohereEmbedResponse {
+            embeddings: Vec<Vec<f32>>,
+        }
+
+        // Cohere 单次请求最多接受96条文本
+        const BATCH_SIZE: usize = 96;
+        let mut all_embeddings = Vec::with_capacity(texts.len());
+
+        for chunk in texts.chunks(BATCH_SIZE) {
+            let request = CohereEmbedRequest {
+                model: &self.model,
+                texts: chunk,
+                input_type,
+            };
+
+            let response = self
+                .client
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {}", api_key))
+                .json(&request)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<CohereEmbedResponse>()
+                .await?;
+
+            if response.embeddings.len() != chunk.len() {
+                return Err(format!(
+                    "Cohere返回的向量数量 {} 与输入数量 {} 不一致",
+                    response.embeddings.len(),
+                    chunk.len()
+                )
+                .into());
+            }
+            all_embeddings.extend(response.embeddings);
+        }
+
+        Ok(all_embeddings)
+    }
 }
 
-// 计算余弦相似度
+#[async_trait]
+impl Embedder for CohereEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        self.embed_with_input_type(texts, "search_document").await
+    }
+
+    async fn embed_one(&self, text: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        self.embed_with_input_type(&[text.to_string()], "search_query")
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| "无法获取查询向量嵌入".into())
+    }
+}
+
+/// 通用HTTP嵌入提供者，可对接任意JSON接口（如Voyage、本地部署的嵌入服务）
+///
+/// 路径使用 `.` 分隔的字段名：
+/// - `input_path`：请求体中放置文本数组的位置，如 `input`、`inputs`
+/// - `response_path`：响应中向量的位置。含 `*` 时每个匹配项是一个向量（如 `data.*.embedding`），
+///   否则匹配项应为向量数组（如 `embeddings`）。返回顺序需与输入一致
+#[derive(Debug, Clone)]
+pub struct HttpEmbedder {
+    client: Client,
+    url: String,
+    headers: Vec<(String, String)>,
+    request_template: serde_json::Value,
+    input_path: String,
+    response_path: String,
+    batch_size: usize,
+}
+
+impl HttpEmbedder {
+    pub fn new(
+        url: impl Into<String>,
+        input_path: impl Into<String>,
+        response_path: impl Into<String>,
+    ) -> Self {
+        HttpEmbedder {
+            client: Client::new(),
+            url: url.into(),
+            headers: Vec::new(),
+            request_template: serde_json::Value::Object(serde_json::Map::new()),
+            input_path: input_path.into(),
+            response_path: response_path.into(),
+            batch_size: DEFAULT_EMBEDDING_BATCH_SIZE,
+        }
+    }
+
+    /// 添加请求头，如 `Authorization`
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// 设置请求体模板（如 `{"model": "voyage-3"}`），文本数组会写入 `input_path` 处
+    pub fn request_template(mut self, template: serde_json::Value) -> Self {
+        self.request_template = template;
+        self
+    }
+
+    /// 设置User-Agent和附加请求头
+    pub fn with_http_options(mut self, http: &HttpOptions) -> Self {
+        self.client = http.build_client();
+        self
+    }
+
+    /// 使用预先构建的HTTP客户端
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// 设置单次请求的最大文本数
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// 从环境变量读取配置
+    ///
+    /// HTTP_EMBEDDING_URL、HTTP_EMBEDDING_INPUT_PATH（默认 input）、
+    /// HTTP_EMBEDDING_RESPONSE_PATH（默认 data.*.embedding）、HTTP_EMBEDDING_MODEL、
+    /// HTTP_EMBEDDING_API_KEY（以 Bearer 方式发送）
+    pub fn from_env() -> Self {
+        let mut embedder = HttpEmbedder::new(
+            env::var("HTTP_EMBEDDING_URL").unwrap_or_default(),
+            env::var("HTTP_EMBEDDING_INPUT_PATH").unwrap_or_else(|_| "input".to_string()),
+            env::var("HTTP_EMBEDDING_RESPONSE_PATH")
+                .unwrap_or_else(|_| "data.*.embedding".to_string()),
+        )
+        .with_http_options(&HttpOptions::from_env());
+        if let Ok(model) = env::var("HTTP_EMBEDDING_MODEL") {
+            embedder = embedder.request_template(serde_json::json!({ "model": model }));
+        }
+        if let Some(api_key) = env::var("HTTP_EMBEDDING_API_KEY")
+            .ok()
+            .filter(|key| !key.is_empty())
+        {
+            embedder = embedder.header("Authorization", format!("Bearer {}", api_key));
+        }
+        embedder
+    }
+
+    // 按模板构造请求体，并把文本数组写入 input_path 处
+    fn build_request(&self, texts: &[String]) -> Result<serde_json::Value, String> {
+        let mut body = self.request_template.clone();
+        let mut target = &mut body;
+        for segment in self.input_path.split('.') {
+            let object = target
+                .as_object_mut()
+                .ok_or_else(|| format!("请求体路径 {} 不是JSON对象", self.input_path))?;
+            target = object
+                .entry(segment.to_string())
+                .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+        }
+        *target = serde_json::json!(texts);
+        Ok(body)
+    }
+
+    // 按 response_path 从响应中取出向量
+    fn extract_embeddings(&self, response: &serde_json::Value) -> Result<Vec<Vec<f32>>, String> {
+        let segments: Vec<&str> = self.response_path.split('.').collect();
+        let selected = select_json_path(response, &segments);
+
+        let vectors: Vec<&serde_json::Value> = if segments.contains(&"*") {
+            selected
+        } else {
+            selected
+                .into_iter()
+                .filter_map(|value| value.as_array())
+                .flatten()
+                .collect()
+        };
+
+        vectors
+            .into_iter()
+            .map(|vector| {
+                serde_json::from_value::<Vec<f32>>(vector.clone())
+                    .map_err(|e| format!("解析嵌入向量失败: {}", e))
+            })
+            .collect()
+    }
+}
+
+// 按路径选取JSON节点，`*` 展开数组中的所有元素，数字段可作为数组下标
+fn select_json_path<'v>(
+    value: &'v serde_json::Value,
+    segments: &[&str],
+) -> Vec<&'v serde_json::Value> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return vec![value];
+    };
+
+    if *segment == "*" {
+        return value
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .flat_map(|item| select_json_path(item, rest))
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+
+    let next = match value {
+        serde_json::Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        serde_json::Value::Object(map) => map.get(*segment),
+        _ => None,
+    };
+
+    next.map(|child| select_json_path(child, rest))
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl Embedder for HttpEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut all_embeddings = Vec::with_capacity(texts.len());
+
+        for chunk in texts.chunks(self.batch_size) {
+            let body = self.build_request(chunk)?;
+
+            let mut request = self
+                .client
+                .post(&self.url)
+                .header("Content-Type", "application/json");
+            for (name, value) in &self.headers {
+                request = request.header(name.as_str(), value.as_str());
+            }
+
+            let response = request
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<serde_json::Value>()
+                .await?;
+
+            let embeddings = self.extract_embeddings(&response)?;
+            if embeddings.len() != chunk.len() {
+                return Err(format!(
+                    "嵌入接口返回的向量数量 {} 与输入数量 {} 不一致",
+                    embeddings.len(),
+                    chunk.len()
+                )
+                .into());
+            }
+            all_embeddings.extend(embeddings);
+        }
+
+        Ok(all_embeddings)
+    }
+}
+
+/// 嵌入提供者配置，用于在不修改代码的情况下切换提供者
+#[derive(Debug, Clone)]
+pub enum EmbeddingProvider {
+    OpenAi(OpenAiEmbedder),
+    Cohere(CohereEmbedder),
+    Http(HttpEmbedder),
+}
+
+impl EmbeddingProvider {
+    /// 根据环境变量 EMBEDDING_PROVIDER（openai、cohere、http，默认 openai）选择提供者
+    pub fn from_env() -> Self {
+        let provider = env::var("EMBEDDING_PROVIDER").unwrap_or_default();
+        match provider.trim().to_lowercase().as_str() {
+            "cohere" => EmbeddingProvider::Cohere(CohereEmbedder::from_env()),
+            "http" => EmbeddingProvider::Http(HttpEmbedder::from_env()),
+            "" | "openai" => EmbeddingProvider::OpenAi(OpenAiEmbedder::from_env()),
+            other => {
+                eprintln!("未知的嵌入提供者 {}，使用OpenAI", other);
+                EmbeddingProvider::OpenAi(OpenAiEmbedder::from_env())
+            }
+        }
+    }
+
+    /// 为所选提供者设置User-Agent和附加请求头
+    pub fn with_http_options(self, http: &HttpOptions) -> Self {
+        match self {
+            EmbeddingProvider::OpenAi(embedder) => {
+                EmbeddingProvider::OpenAi(embedder.with_http_options(http))
+            }
+            EmbeddingProvider::Cohere(embedder) => {
+                EmbeddingProvider::Cohere(embedder.with_http_options(http))
+            }
+            EmbeddingProvider::Http(embedder) => {
+                EmbeddingProvider::Http(embedder.with_http_options(http))
+            }
+        }
+    }
+
+    /// 转换为可供搜索模块使用的嵌入提供者
+    pub fn into_embedder(self) -> Arc<dyn Embedder> {
+        match self {
+            EmbeddingProvider::OpenAi(embedder) => Arc::new(embedder),
+            EmbeddingProvider::Cohere(embedder) => Arc::new(embedder),
+            EmbeddingProvider::Http(embedder) => Arc::new(embedder),
+        }
+    }
+}
+
+// 获取查询的向量嵌入（使用环境变量选择的嵌入提供者）
+pub async fn get_query_embedding(query: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    EmbeddingProvider::from_env()
+        .into_embedder()
+        .embed_one(query)
+        .await
+}
+
+// 批量获取向量嵌入（使用环境变量选择的嵌入提供者）
+pub async fn batch_get_embeddings(
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+    EmbeddingProvider::from_env()
+        .into_embedder()
+        .embed(texts)
+        .await
+}
+
+/// 把向量截断为前 `dimensions` 维并重新归一化为单位向量
+///
+/// 适用于以Matryoshka方式训练的模型（如 text-embedding-3 系列），截断后的向量仍可比较；
+/// 不长于 `dimensions` 的向量原样返回
+pub fn truncate_embedding(mut embedding: Vec<f32>, dimensions: usize) -> Vec<f32> {
+    if embedding.len() <= dimensions {
+        return embedding;
+    }
+    embedding.truncate(dimensions);
+    let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for value in embedding.iter_mut() {
+            *value /= norm;
+        }
+    }
+    embedding
+}
+
+/// 从查询向量中减去反例的平均向量（`q - weight * mean(neg)`），用于“类似X但不是Y”的语义检索
+///
+/// 查询向量和各反例先归一化为单位向量，结果同样归一化，余弦相似度仍落在 [-1, 1]；
+/// 维度与查询向量不一致的反例被忽略。没有可用反例或相减后接近零向量时返回原查询向量
+pub fn subtract_negative_examples(query: &[f32], negatives: &[Vec<f32>], weight: f32) -> Vec<f32> {
+    let Some(query_unit) = unit_vector(query) else {
+        return query.to_vec();
+    };
+    let negatives: Vec<Vec<f32>> = negatives
+        .iter()
+        .filter(|negative| negative.len() == query.len())
+        .filter_map(|negative| unit_vector(negative))
+        .collect();
+    if negatives.is_empty() || weight == 0.0 {
+        return query.to_vec();
+    }
+
+    let scale = weight / negatives.len() as f32;
+    let mut adjusted = query_unit;
+    for negative in &negatives {
+        for (value, negative_value) in adjusted.iter_mut().zip(negative) {
+            *value -= scale * negative_value;
+        }
+    }
+    let norm = adjusted.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm < 1e-3 {
+        return query.to_vec();
+    }
+    adjusted.iter().map(|v| v / norm).collect()
+}
+
+// 归一化为单位向量，零向量或空向量返回None
+fn unit_vector(vector: &[f32]) -> Option<Vec<f32>> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if vector.is_empty() || norm <= 0.0 {
+        return None;
+    }
+    Some(vector.iter().map(|v| v / norm).collect())
+}
+
+// 计算余弦相似度，两个向量长度不一致（如维度配置不同）或为空时返回0
 pub fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
     if vec1.len() != vec2.len() || vec1.is_empty() {
         return 0.0;
@@ -138,23 +839,170 @@ pub fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
     dot_product / (norm1.sqrt() * norm2.sqrt())
 }
 
+/// 向量相似度的度量方式，见 `SearchConfig::similarity_metric`
+///
+/// 各度量都换算为越大越相似的得分：余弦相似度落在 [-1, 1]，单位向量的点积与余弦相似度相同，
+/// L2距离按 `1 / (1 + 距离)` 换算到 (0, 1]。计算最终得分时向量得分统一限制在 [0, 1] 内
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SimilarityMetric {
+    /// 余弦相似度（默认），对应pgvector的 `<=>`
+    #[default]
+    Cosine,
+    /// 点积，适用于已归一化的嵌入向量，结果与余弦相似度相同但计算更快；对应pgvector的 `<#>`
+    Dot,
+    /// 欧氏（L2）距离，对应pgvector的 `<->`
+    L2,
+}
+
+impl SimilarityMetric {
+    // pgvector中对应的距离运算符，按运算结果升序排列即为相似度降序
+    pub(crate) fn operator(&self) -> &'static str {
+        match self {
+            SimilarityMetric::Cosine => "<=>",
+            SimilarityMetric::Dot => "<#>",
+            SimilarityMetric::L2 => "<->",
+        }
+    }
+
+    // 由数据库计算 `column` 与 `vector` 相似度的SQL表达式，换算方式与 `similarity` 一致
+    //
+    // pgvector的 `<#>` 返回点积的相反数
+    pub(crate) fn sql_score(&self, column: &str, vector: &str) -> String {
+        match self {
+            SimilarityMetric::Cosine => format!("(1 - ({} <=> {}))", column, vector),
+            SimilarityMetric::Dot => format!("(-({} <#> {}))", column, vector),
+            SimilarityMetric::L2 => format!("(1 / (1 + ({} <-> {})))", column, vector),
+        }
+    }
+}
+
+/// 按指定度量计算两个向量的相似度，两个向量长度不一致或为空时返回0
+pub fn similarity(metric: SimilarityMetric, vec1: &[f32], vec2: &[f32]) -> f32 {
+    if vec1.len() != vec2.len() || vec1.is_empty() {
+        return 0.0;
+    }
+
+    match metric {
+        SimilarityMetric::Cosine => cosine_similarity(vec1, vec2),
+        SimilarityMetric::Dot => vec1.iter().zip(vec2).map(|(a, b)| a * b).sum(),
+        SimilarityMetric::L2 => {
+            let distance = vec1
+                .iter()
+                .zip(vec2)
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f32>()
+                .sqrt();
+            1.0 / (1.0 + distance)
+        }
+    }
+}
+
+// 嵌入请求失败后重新请求是否可能成功：超时、连接错误、响应格式错误、429和5xx可以重试，
+// 认证失败（401）、请求无效（400）等其他4xx错误重新请求也会得到同样的结果
+fn is_retriable_embedding_error(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => {
+            status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || status == reqwest::StatusCode::REQUEST_TIMEOUT
+                || status.is_server_error()
+        }
+        None => true,
+    }
+}
+
+/// 构建crate的嵌入文本 `名称 : 描述`，超过 `max_chars` 个字符时截断
+///
+/// 优先保留完整的crate名称和描述的开头部分，避免超长描述超出嵌入模型的token上限导致整批请求失败
+pub fn embedding_text(name: &str, description: &str, max_chars: usize) -> String {
+    // 名称是crate的核心标识，应该有更大的权重
+    let text = if description.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} : {}", name, description)
+    };
+
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => text[..end].to_string(),
+        None => text,
+    }
+}
+
+// 查询crate行时读取嵌入文本的列表达式，未配置时与描述相同
+fn embedding_text_expression(config: &SearchConfig) -> String {
+    match &config.embedding_text_column {
+        Some(column) => format!("COALESCE(NULLIF({}, ''), description)", column),
+        None => "description".to_string(),
+    }
+}
+
+// 写入单个crate嵌入向量的语句，参数为 `$1` 向量、`$2` crate id；
+// 独立嵌入表中没有该crate的行时插入
+fn store_embedding_statement(table_name: &str, config: &SearchConfig) -> String {
+    match &config.embeddings_table {
+        Some(embeddings) => format!(
+            "INSERT INTO {} (id, embedding) VALUES ($2, $1)
+            ON CONFLICT (id) DO UPDATE SET embedding = EXCLUDED.embedding",
+            embeddings
+        ),
+        None => format!("UPDATE {} SET embedding = $1 WHERE id = $2", table_name),
+    }
+}
+
+// 获取一个按需嵌入的并发许可，未限制并发时返回None
+async fn acquire_permit(permits: Option<&Semaphore>) -> Option<SemaphorePermit<'_>> {
+    match permits {
+        Some(permits) => permits.acquire().await.ok(),
+        None => None,
+    }
+}
+
 /// 根据当前模式获取或创建crate的嵌入向量
 ///
+/// 嵌入文本默认为 `"{name} : {description}"`；设置 `SearchConfig::embedding_text_column` 后
+/// 改为 `"{name} : {该列内容}"`（如整理过的 `search_text` 列），该列为空时仍使用描述。
+/// 预先计算和增量更新同样遵循该设置
+///
 /// 支持两种模式：
 /// - 预先计算模式：直接从数据库读取预先计算好的向量
-/// - 搜索时计算模式：为搜索结果中的crate实时生成向量
+/// - 搜索时计算模式：为搜索结果中的crate实时生成向量，`persist_on_demand` 为true时写回数据库；
+///   `permits` 限制调用嵌入接口和写回数据库的并发数，为None时不限制
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch_or_create_embeddings(
     crates: &[RecommendCrate],
     pg_client: &PgClient,
     table_name: &str,
     mode: EmbeddingMode,
+    embedder: &dyn Embedder,
+    permits: Option<&Semaphore>,
+    persist_on_demand: bool,
+    config: &SearchConfig,
 ) -> HashMap<String, Vec<f32>> {
+    if let Err(e) = validate_identifier(table_name).and_then(|_| config.validate_identifiers()) {
+        eprintln!("{}", e);
+        return HashMap::new();
+    }
     match mode {
         EmbeddingMode::Precomputed => {
-            fetch_precomputed_embeddings(crates, pg_client, table_name).await
+            fetch_precomputed_embeddings(
+                crates,
+                pg_client,
+                table_name,
+                embedder.dimensions(),
+                config,
+            )
+            .await
         }
         EmbeddingMode::OnDemand => {
-            compute_embeddings_on_demand(crates, pg_client, table_name).await
+            compute_embeddings_on_demand(
+                crates,
+                pg_client,
+                table_name,
+                embedder,
+                permits,
+                persist_on_demand,
+                config,
+            )
+            .await
         }
     }
 }
@@ -166,38 +1014,63 @@ async fn fetch_precomputed_embeddings(
     crates: &[RecommendCrate],
     pg_client: &PgClient,
     table_name: &str,
+    dimensions: Option<usize>,
+    config: &SearchConfig,
 ) -> HashMap<String, Vec<f32>> {
-    // 步骤1: 获取所有需要的crate ID
-    let mut crate_ids = Vec::new();
-    for crate_item in crates {
-        crate_ids.push(crate_item.id.clone());
+    let id_to_embedding =
+        fetch_stored_embeddings(crates, pg_client, table_name, dimensions, config).await;
+
+    // 如果有些crate没有预先计算的向量，报告缺失情况
+    let missing_count = crates
+        .iter()
+        .filter(|c| !id_to_embedding.contains_key(&c.id))
+        .count();
+
+    if missing_count > 0 {
+        println!("警告: 有 {} 个crate缺少预先计算的嵌入向量", missing_count);
     }
 
+    id_to_embedding
+}
+
+// 读取候选crate已存储的嵌入向量，`dimensions` 不为None时忽略维度不一致的向量
+async fn fetch_stored_embeddings(
+    crates: &[RecommendCrate],
+    pg_client: &PgClient,
+    table_name: &str,
+    dimensions: Option<usize>,
+    config: &SearchConfig,
+) -> HashMap<String, Vec<f32>> {
+    let crate_ids: Vec<&str> = crates.iter().map(|c| c.id.as_str()).collect();
+
     // 查询数据库获取已有嵌入的crate
     let ids_list = crate_ids.join("','");
     let embedding_query = format!(
         "SELECT id, embedding FROM {} WHERE id IN ('{}') AND embedding IS NOT NULL",
-        table_name, ids_list
+        config.embedding_table_for(table_name),
+        ids_list
     );
 
     let mut id_to_embedding = HashMap::new();
+    let mut mismatched = 0;
 
     if let Ok(rows) = pg_client.query(&embedding_query, &[]).await {
         for row in rows {
             let id: String = row.get("id");
-            let embedding: Vector = row.get("embedding");
-            id_to_embedding.insert(id, Vec::<f32>::from(embedding));
+            let embedding = Vec::<f32>::from(row.get::<_, Vector>("embedding"));
+            if dimensions.is_some_and(|dimensions| embedding.len() != dimensions) {
+                mismatched += 1;
+                continue;
+            }
+            id_to_embedding.insert(id, embedding);
         }
     }
 
-    // 如果有些crate没有预先计算的向量，报告缺失情况
-    let missing_count = crates
-        .iter()
-        .filter(|c| !id_to_embedding.contains_key(&c.id))
-        .count();
-
-    if missing_count > 0 {
-        println!("警告: 有 {} 个crate缺少预先计算的嵌入向量", missing_count);
+    if let (Some(dimensions), true) = (dimensions, mismatched > 0) {
+        eprintln!(
+            "警告: 有 {} 个已存储的向量维度与配置的 {} 维不一致，已忽略（切换维度后需重新计算）",
+            mismatched, dimensions
+        );
     }
 
     id_to_embedding
@@ -205,50 +1078,64 @@ async fn fetch_precomputed_embeddings(
 
 /// 按需计算嵌入向量 (搜索时计算模式)
 ///
-/// 在该模式下，尝试从数据库获取向量，对于没有向量的crate会动态生成并存储
+/// 在该模式下，尝试从数据库获取向量，对于没有向量的crate会动态生成；
+/// `persist` 为false时（如只读副本）生成的向量只用于本次请求，不写回数据库
 async fn compute_embeddings_on_demand(
     crates: &[RecommendCrate],
     pg_client: &PgClient,
     table_name: &str,
+    embedder: &dyn Embedder,
+    permits: Option<&Semaphore>,
+    persist: bool,
+    config: &SearchConfig,
 ) -> HashMap<String, Vec<f32>> {
     // 收集所有需要获取嵌入的crate
     let mut crates_needing_embedding = Vec::new();
     let mut crate_id_to_index = HashMap::new();
 
-    // 步骤1: 检查数据库中哪些crate已有嵌入
+    // 步骤1: 检查数据库中哪些crate已有嵌入，维度不一致的向量视为缺失并重新生成
     let mut crate_ids = Vec::new();
     for crate_item in crates {
         crate_ids.push(crate_item.id.clone());
     }
+    let mut id_to_embedding =
+        fetch_stored_embeddings(crates, pg_client, table_name, embedder.dimensions(), config).await;
 
-    // 查询数据库获取已有嵌入的crate
-    let ids_list = crate_ids.join("','");
-    let embedding_query = format!(
-        "SELECT id, embedding FROM {} WHERE id IN ('{}') AND embedding IS NOT NULL",
-        table_name, ids_list
-    );
-
-    let mut id_to_embedding = HashMap::new();
-
-    if let Ok(rows) = pg_client.query(&embedding_query, &[]).await {
-        for row in rows {
-            let id: String = row.get("id");
-            let embedding: Vector = row.get("embedding");
-            id_to_embedding.insert(id, Vec::<f32>::from(embedding));
+    // 配置了嵌入文本列时，读取缺少嵌入的crate的该列内容
+    let mut id_to_text = HashMap::new();
+    if let Some(column) = &config.embedding_text_column {
+        let missing_ids: Vec<&String> = crate_ids
+            .iter()
+            .filter(|id| !id_to_embedding.contains_key(*id))
+            .collect();
+        if !missing_ids.is_empty() {
+            let text_query = format!(
+                "SELECT id, {} AS embedding_text FROM {} WHERE id = ANY($1)",
+                column, table_name
+            );
+            match pg_client.query(&text_query, &[&missing_ids]).await {
+                Ok(rows) => {
+                    for row in rows {
+                        if let Some(text) = row.get::<_, Option<String>>("embedding_text") {
+                            id_to_text.insert(row.get::<_, String>("id"), text);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("无法读取嵌入文本列 {}，使用描述生成嵌入: {}", column, e),
+            }
         }
     }
 
     // 步骤2: 收集需要生成嵌入的crate
+    let max_chars = config.max_embedding_chars;
     for (index, crate_item) in crates.iter().enumerate() {
         if !id_to_embedding.contains_key(&crate_item.id) {
-            // 使用名称和描述构建更有意义的嵌入文本
-            // 名称是crate的核心标识，应该有更大的权重
-            let crate_text = if crate_item.description.is_empty() {
-                // 如果没有描述，只使用名称
-                crate_item.name.clone()
-            } else {
-                format!("{} : {}", crate_item.name, crate_item.description)
-            };
+            // 使用名称和描述（或配置的嵌入文本列）构建更有意义的嵌入文本，过长时截断
+            let text = id_to_text
+                .get(&crate_item.id)
+                .filter(|text| !text.is_empty())
+                .unwrap_or(&crate_item.description);
+            let crate_text = embedding_text(&crate_item.name, text, max_chars);
             crates_needing_embedding.push(crate_text);
             crate_id_to_index.insert(crates_needing_embedding.len() - 1, index);
         }
@@ -258,29 +1145,45 @@ async fn compute_embeddings_on_demand(
     if !crates_needing_embedding.is_empty() {
         println!("批量获取 {} 个crate的嵌入", crates_needing_embedding.len());
 
-        if let Ok(embeddings) = batch_get_embeddings(&crates_needing_embedding).await {
-            // 步骤4: 保存嵌入到数据库
-            for (i, embedding) in embeddings.iter().enumerate() {
+        // 信号量不会被关闭，获取许可不会失败
+        let embeddings = {
+            let _permit = acquire_permit(permits).await;
+            embedder.embed(&crates_needing_embedding).await
+        };
+        if let Ok(embeddings) = embeddings {
+            // 步骤4: 先在内存中整理好全部结果，再用一条UPDATE语句写回数据库。
+            // 单条语句是原子的，搜索future在任意await点被丢弃时，
+            // 数据库中要么写入了这一批全部向量，要么一条都没有写入
+            let mut generated_ids = Vec::with_capacity(embeddings.len());
+            let mut generated_embeddings = Vec::with_capacity(embeddings.len());
+            for (i, embedding) in embeddings.into_iter().enumerate() {
+                // 跳过未能获取的向量
+                if embedding.is_empty() {
+                    continue;
+                }
                 if let Some(&crate_index) = crate_id_to_index.get(&i) {
-                    let crate_id = &crates[crate_index].id;
-
-                    // 保存到数据库
-                    let pg_vector = Vector::from(embedding.clone());
-                    let update_query =
-                        format!("UPDATE {} SET embedding = $1 WHERE id = $2", table_name);
+                    generated_ids.push(crates[crate_index].id.clone());
+                    generated_embeddings.push(embedding);
+                }
+            }
 
-                    match pg_client
-                        .execute(&update_query, &[&pg_vector, &crate_id])
-                        .await
-                    {
-                        Ok(_) => {
-                            // 添加到映射中
-                            id_to_embedding.insert(crate_id.clone(), embedding.clone());
-                        }
-                        Err(e) => eprintln!("无法更新crate '{}'的向量嵌入: {}", crate_id, e),
-                    }
+            if persist {
+                let _permit = acquire_permit(permits).await;
+                if let Err(e) = store_embeddings(
+                    pg_client,
+                    table_name,
+                    &generated_ids,
+                    &generated_embeddings,
+                    config,
+                )
+                .await
+                {
+                    eprintln!("无法写回 {} 个crate的向量嵌入: {}", generated_ids.len(), e);
                 }
             }
+
+            // 写回失败不影响本次搜索使用已生成的向量
+            id_to_embedding.extend(generated_ids.into_iter().zip(generated_embeddings));
         } else {
             eprintln!("批量获取嵌入失败");
         }
@@ -289,21 +1192,181 @@ async fn compute_embeddings_on_demand(
     id_to_embedding
 }
 
+// 用一条UPDATE语句批量写回嵌入向量，向量以pgvector的文本格式传入后转换
+async fn store_embeddings(
+    pg_client: &PgClient,
+    table_name: &str,
+    crate_ids: &[String],
+    embeddings: &[Vec<f32>],
+    config: &SearchConfig,
+) -> Result<u64, tokio_postgres::Error> {
+    if crate_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let vector_literals: Vec<String> = embeddings
+        .iter()
+        .map(|embedding| {
+            let values: Vec<String> = embedding.iter().map(|v| v.to_string()).collect();
+            format!("[{}]", values.join(","))
+        })
+        .collect();
+
+    let update_query = match &config.embeddings_table {
+        Some(embeddings) => format!(
+            "INSERT INTO {} (id, embedding)
+            SELECT id, embedding::vector FROM unnest($1::text[], $2::text[]) AS updates(id, embedding)
+            ON CONFLICT (id) DO UPDATE SET embedding = EXCLUDED.embedding",
+            embeddings
+        ),
+        None => format!(
+            "UPDATE {0} SET embedding = updates.embedding::vector
+            FROM unnest($1::text[], $2::text[]) AS updates(id, embedding)
+            WHERE {0}.id = updates.id",
+            table_name
+        ),
+    };
+
+    pg_client
+        .execute(&update_query, &[&crate_ids, &vector_literals])
+        .await
+}
+
+/// 预计算嵌入向量的结果汇总
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrecomputeSummary {
+    // 本次处理的crate数量（成功与失败之和，取消后未处理的crate不计入）
+    pub processed: u64,
+    // 成功写入嵌入向量的crate数量
+    pub succeeded: u64,
+    // 未能获取或写入嵌入向量的crate数量
+    pub failed: u64,
+    // 失败的crate id，可交给 `compute_embeddings_for_ids` 单独重试
+    pub failed_ids: Vec<String>,
+}
+
+impl PrecomputeSummary {
+    fn record_success(&mut self) {
+        self.processed += 1;
+        self.succeeded += 1;
+    }
+
+    fn record_failure(&mut self, crate_id: String) {
+        self.processed += 1;
+        self.failed += 1;
+        self.failed_ids.push(crate_id);
+    }
+}
+
 /// 预先计算并存储所有crate的嵌入向量
 ///
-/// 该函数适用于系统初始化或非高峰期运行，会为数据库中所有crate计算嵌入向量
+/// 该函数适用于系统初始化或非高峰期运行，会为数据库中所有crate计算嵌入向量，
+/// 返回成功与失败的数量以及失败的crate id
 /// 注意：对于大型数据库，这可能是一个耗时的操作
 pub async fn precompute_all_embeddings(
     pg_client: &PgClient,
     table_name: &str,
     batch_size: usize,
-) -> Result<u64, Box<dyn std::error::Error>> {
+    config: &SearchConfig,
+) -> Result<PrecomputeSummary, Box<dyn std::error::Error>> {
+    let embedder = EmbeddingProvider::from_env().into_embedder();
+    precompute_all_embeddings_with_control(
+        pg_client,
+        table_name,
+        batch_size,
+        embedder.as_ref(),
+        None,
+        None,
+        config,
+    )
+    .await
+}
+
+/// 预先计算所有crate的嵌入向量，支持进度回调和取消
+///
+/// 嵌入向量由 `embedder` 生成，如 `EmbeddingProvider::from_env().into_embedder()`
+///
+/// - `on_progress`：每处理完一批后以 `(已处理数, 总数)` 调用
+/// - `cancel`：每批开始前检查，置为 `true` 后停止处理并返回目前的处理结果，
+///   由于只处理 `embedding IS NULL` 的crate，之后再次调用即可从中断处继续
+pub async fn precompute_all_embeddings_with_control(
+    pg_client: &PgClient,
+    table_name: &str,
+    batch_size: usize,
+    embedder: &dyn Embedder,
+    on_progress: Option<&mut (dyn FnMut(u64, u64) + Send)>,
+    cancel: Option<&AtomicBool>,
+    config: &SearchConfig,
+) -> Result<PrecomputeSummary, Box<dyn std::error::Error>> {
+    precompute_missing_embeddings(
+        pg_client,
+        table_name,
+        batch_size,
+        embedder,
+        on_progress.map(ProgressSink::Callback),
+        cancel,
+        config,
+    )
+    .await
+}
+
+/// 预计算的进度，每处理完一批发送一次
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ProgressEvent {
+    // 已处理的crate数量（成功与失败之和），与 `PrecomputeSummary::processed` 一致
+    pub processed: u64,
+    // 需要计算嵌入向量的crate总数
+    pub total: u64,
+    // 未能获取或写入嵌入向量的crate数量
+    pub failed: u64,
+}
+
+/// 预先计算所有crate的嵌入向量，每处理完一批通过 `tx` 发送一次 [`ProgressEvent`]
+///
+/// 适用于后台任务通过状态接口对外提供实时进度和失败数量；接收端被丢弃后继续计算，
+/// 只是不再发送进度。没有需要计算的crate时不发送任何事件。
+/// 嵌入向量由 `embedder` 生成，如 `EmbeddingProvider::from_env().into_embedder()`
+pub async fn precompute_all_embeddings_with_progress(
+    pg_client: &PgClient,
+    table_name: &str,
+    batch_size: usize,
+    embedder: &dyn Embedder,
+    tx: mpsc::Sender<ProgressEvent>,
+    config: &SearchConfig,
+) -> Result<PrecomputeSummary, Box<dyn std::error::Error>> {
+    precompute_missing_embeddings(
+        pg_client,
+        table_name,
+        batch_size,
+        embedder,
+        Some(ProgressSink::Channel(&tx)),
+        None,
+        config,
+    )
+    .await
+}
+
+// 为所有缺少嵌入向量的crate计算并写回嵌入向量
+async fn precompute_missing_embeddings(
+    pg_client: &PgClient,
+    table_name: &str,
+    batch_size: usize,
+    embedder: &dyn Embedder,
+    progress: Option<ProgressSink<'_>>,
+    cancel: Option<&AtomicBool>,
+    config: &SearchConfig,
+) -> Result<PrecomputeSummary, Box<dyn std::error::Error>> {
+    validate_identifier(table_name)?;
+    config.validate_identifiers()?;
     println!("开始预计算所有crate的嵌入向量...");
 
     // 1. 获取所有没有嵌入向量的crate
     let query = format!(
-        "SELECT id, name, description FROM {} WHERE embedding IS NULL",
-        table_name
+        "SELECT {1}.id, {1}.name, {0} AS description FROM {1}{2} WHERE {3}.embedding IS NULL",
+        embedding_text_expression(config),
+        table_name,
+        config.embedding_join(table_name),
+        config.embedding_table_for(table_name)
     );
 
     let rows = pg_client.query(&query, &[]).await?;
@@ -312,59 +1375,299 @@ pub async fn precompute_all_embeddings(
     println!("找到 {} 个需要计算嵌入向量的crate", total_crates);
 
     if total_crates == 0 {
+        return Ok(PrecomputeSummary::default());
+    }
+
+    // 2. 分批计算并保存嵌入
+    let summary = embed_and_store_rows(
+        pg_client, table_name, &rows, embedder, batch_size, progress, cancel, config,
+    )
+    .await;
+
+    println!(
+        "预计算完成，成功处理 {} 个crate的嵌入向量，失败 {} 个",
+        summary.succeeded, summary.failed
+    );
+    Ok(summary)
+}
+
+/// 为自指定时间以来新增或修改过的crate重新计算嵌入向量
+///
+/// 依赖crate表中的 `updated_at` 列，适用于每日增量同步crates.io数据后保持向量最新，
+/// 无需全量重算或手动调用 `reset_all_embeddings`。
+/// 嵌入向量由 `embedder` 生成，如 `EmbeddingProvider::from_env().into_embedder()`
+pub async fn update_embeddings_since(
+    pg_client: &PgClient,
+    table_name: &str,
+    since: DateTime<Utc>,
+    embedder: &dyn Embedder,
+    config: &SearchConfig,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    validate_identifier(table_name)?;
+    config.validate_identifiers()?;
+    println!("开始更新 {} 之后变更的crate的嵌入向量...", since);
+
+    let query = format!(
+        "SELECT id, name, {} AS description FROM {} WHERE updated_at >= $1",
+        embedding_text_expression(config),
+        table_name
+    );
+
+    let rows = pg_client.query(&query, &[&since]).await?;
+    println!("找到 {} 个需要更新嵌入向量的crate", rows.len());
+
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let processed_count = embed_and_store_rows(
+        pg_client,
+        table_name,
+        &rows,
+        embedder,
+        DEFAULT_EMBEDDING_BATCH_SIZE,
+        None,
+        None,
+        config,
+    )
+    .await
+    .succeeded;
+
+    println!(
+        "增量更新完成，成功处理 {} 个crate的嵌入向量",
+        processed_count
+    );
+    Ok(processed_count)
+}
+
+/// 为指定id的crate生成并存储嵌入向量，返回成功写入的数量
+///
+/// 适用于预热热门crate等场景；已有嵌入向量的crate默认跳过，`force` 为true时重新生成。
+/// 不存在的id会被忽略
+pub async fn compute_embeddings_for_ids(
+    pg_client: &PgClient,
+    table_name: &str,
+    ids: &[String],
+    embedder: &dyn Embedder,
+    force: bool,
+    config: &SearchConfig,
+) -> Result<u64, SearchError> {
+    validate_identifier(table_name)?;
+    config.validate_identifiers()?;
+    if ids.is_empty() {
         return Ok(0);
     }
 
-    // 2. 将crate分批处理
-    let mut processed_count = 0;
+    let query = format!(
+        "SELECT {1}.id, {1}.name, {0} AS description FROM {1}{2} WHERE {1}.id = ANY($1){3}",
+        embedding_text_expression(config),
+        table_name,
+        config.embedding_join(table_name),
+        if force {
+            String::new()
+        } else {
+            format!(
+                " AND {}.embedding IS NULL",
+                config.embedding_table_for(table_name)
+            )
+        }
+    );
+    let rows = pg_client.query(&query, &[&ids]).await?;
+    println!("为 {} 个指定的crate计算嵌入向量", rows.len());
+
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    Ok(embed_and_store_rows(
+        pg_client,
+        table_name,
+        &rows,
+        embedder,
+        DEFAULT_EMBEDDING_BATCH_SIZE,
+        None,
+        None,
+        config,
+    )
+    .await
+    .succeeded)
+}
+
+// 嵌入计算进度的接收方：进度回调或进度通道
+enum ProgressSink<'a> {
+    Callback(&'a mut (dyn FnMut(u64, u64) + Send)),
+    Channel(&'a mpsc::Sender<ProgressEvent>),
+}
+
+impl ProgressSink<'_> {
+    async fn report(&mut self, event: ProgressEvent) {
+        match self {
+            ProgressSink::Callback(callback) => callback(event.processed, event.total),
+            // 接收端已关闭时忽略，不影响嵌入计算
+            ProgressSink::Channel(tx) => {
+                let _ = tx.send(event).await;
+            }
+        }
+    }
+}
+
+// 将查询到的crate行分批计算嵌入并写回数据库，返回成功与失败的汇总
+#[allow(clippy::too_many_arguments)]
+async fn embed_and_store_rows(
+    pg_client: &PgClient,
+    table_name: &str,
+    rows: &[Row],
+    embedder: &dyn Embedder,
+    batch_size: usize,
+    mut progress: Option<ProgressSink<'_>>,
+    cancel: Option<&AtomicBool>,
+    config: &SearchConfig,
+) -> PrecomputeSummary {
+    let total_crates = rows.len();
+    let mut summary = PrecomputeSummary::default();
+    let max_chars = config.max_embedding_chars;
+
+    // UPDATE语句只解析一次，整个计算过程中复用
+    let update_statement = match pg_client
+        .prepare(&store_embedding_statement(table_name, config))
+        .await
+    {
+        Ok(statement) => statement,
+        Err(e) => {
+            eprintln!("无法准备向量嵌入的更新语句: {}", e);
+            for row in rows {
+                summary.record_failure(row.get("id"));
+            }
+            return summary;
+        }
+    };
 
     for chunk in rows.chunks(batch_size) {
+        if cancel.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+            println!(
+                "嵌入计算已取消，已处理 {}/{} 个crate",
+                summary.processed, total_crates
+            );
+            break;
+        }
+
         let mut texts = Vec::with_capacity(chunk.len());
         let mut crate_ids = Vec::with_capacity(chunk.len());
 
         for row in chunk {
             let id: String = row.get("id");
-            let name: String = row.get("name");
-            let description: String = row.get("description");
-
-            // 构建嵌入文本
-            let text = if description.is_empty() {
-                name.clone()
-            } else {
-                format!("{} : {}", name, description)
-            };
+            let name = row.get::<_, Option<String>>("name").unwrap_or_default();
+            let description = row
+                .get::<_, Option<String>>("description")
+                .unwrap_or_default();
 
+            // 构建嵌入文本，过长时截断；名称和描述都为空时无法生成有意义的向量，记为失败
+            let text = embedding_text(&name, &description, max_chars);
+            if text.trim().is_empty() {
+                eprintln!("crate '{}' 没有可用于生成嵌入的文本，跳过", id);
+                summary.record_failure(id);
+                continue;
+            }
             texts.push(text);
             crate_ids.push(id);
         }
 
-        // 3. 批量获取嵌入
-        if let Ok(embeddings) = batch_get_embeddings(&texts).await {
-            // 4. 保存嵌入到数据库
-            for (i, embedding) in embeddings.iter().enumerate() {
-                let crate_id = &crate_ids[i];
-                let pg_vector = Vector::from(embedding.clone());
-                let update_query =
-                    format!("UPDATE {} SET embedding = $1 WHERE id = $2", table_name);
+        // 批量获取嵌入，整批都被跳过时不调用嵌入接口
+        let embeddings = if texts.is_empty() {
+            Ok(Vec::new())
+        } else {
+            embedder.embed(&texts).await
+        };
+        match embeddings {
+            Ok(embeddings) => {
+                // 保存嵌入到数据库
+                for (i, crate_id) in crate_ids.into_iter().enumerate() {
+                    // 未能获取的向量记为失败
+                    let Some(embedding) = embeddings.get(i).filter(|e| !e.is_empty()) else {
+                        summary.record_failure(crate_id);
+                        continue;
+                    };
+                    let pg_vector = Vector::from(embedding.clone());
+
+                    if let Err(e) = pg_client
+                        .execute(&update_statement, &[&pg_vector, &crate_id])
+                        .await
+                    {
+                        eprintln!("无法更新crate '{}'的向量嵌入: {}", crate_id, e);
+                        summary.record_failure(crate_id);
+                    } else {
+                        summary.record_success();
+                    }
+                }
 
-                if let Err(e) = pg_client
-                    .execute(&update_query, &[&pg_vector, &crate_id])
-                    .await
-                {
-                    eprintln!("无法更新crate '{}'的向量嵌入: {}", crate_id, e);
-                } else {
-                    processed_count += 1;
+                println!("已处理 {}/{} 个crate", summary.processed, total_crates);
+            }
+            Err(e) => {
+                eprintln!("批量获取嵌入失败: {}", e);
+                for crate_id in crate_ids {
+                    summary.record_failure(crate_id);
                 }
             }
+        }
+
+        if let Some(progress) = progress.as_mut() {
+            progress
+                .report(ProgressEvent {
+                    processed: summary.processed,
+                    total: total_crates as u64,
+                    failed: summary.failed,
+                })
+                .await;
+        }
+    }
+
+    summary
+}
 
-            println!("已处理 {}/{} 个crate", processed_count, total_crates);
+/// 嵌入向量覆盖情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoverageStats {
+    pub total: u64,
+    pub with_embedding: u64,
+    pub without_embedding: u64,
+}
+
+impl CoverageStats {
+    /// 已有嵌入向量的crate占比，空表时为1
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            1.0
         } else {
-            eprintln!("批量获取嵌入失败");
+            self.with_embedding as f64 / self.total as f64
         }
     }
+}
 
-    println!("预计算完成，成功处理 {} 个crate的嵌入向量", processed_count);
-    Ok(processed_count)
+/// 统计数据表中已有和缺少嵌入向量的crate数量
+///
+/// 可在预先计算模式启动前检查覆盖率，决定是否需要运行 `precompute_all_embeddings`
+pub async fn embedding_coverage(
+    pg_client: &PgClient,
+    table_name: &str,
+    config: &SearchConfig,
+) -> Result<CoverageStats, SearchError> {
+    validate_identifier(table_name)?;
+    config.validate_identifiers()?;
+    let count_query = format!(
+        "SELECT COUNT(*) AS total, COUNT({}.embedding) AS with_embedding FROM {}{}",
+        config.embedding_table_for(table_name),
+        table_name,
+        config.embedding_join(table_name)
+    );
+    let row = pg_client.query_one(&count_query, &[]).await?;
+    let total: i64 = row.get("total");
+    let with_embedding: i64 = row.get("with_embedding");
+
+    Ok(CoverageStats {
+        total: total as u64,
+        with_embedding: with_embedding as u64,
+        without_embedding: (total - with_embedding) as u64,
+    })
 }
 
 /// 重置数据库中所有crate的embedding列数据
@@ -376,13 +1679,16 @@ pub async fn precompute_all_embeddings(
 pub async fn reset_all_embeddings(
     pg_client: &PgClient,
     table_name: &str,
+    config: &SearchConfig,
 ) -> Result<u64, Box<dyn std::error::Error>> {
+    validate_identifier(table_name)?;
+    config.validate_identifiers()?;
     println!("正在清除数据库中的所有嵌入向量...");
 
     // 构建更新SQL语句
     let update_query = format!(
         "UPDATE {} SET embedding = NULL WHERE embedding IS NOT NULL",
-        table_name
+        config.embedding_table_for(table_name)
     );
 
     // 执行更新
@@ -403,8 +1709,14 @@ pub async fn reset_crate_embedding(
     pg_client: &PgClient,
     table_name: &str,
     crate_id: &str,
+    config: &SearchConfig,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    let update_query = format!("UPDATE {} SET embedding = NULL WHERE id = $1", table_name);
+    validate_identifier(table_name)?;
+    config.validate_identifiers()?;
+    let update_query = format!(
+        "UPDATE {} SET embedding = NULL WHERE id = $1",
+        config.embedding_table_for(table_name)
+    );
 
     match pg_client.execute(&update_query, &[&crate_id]).await {
         Ok(affected_rows) => {
@@ -0,0 +1,80 @@
+use std::fmt;
+
+/// 搜索模块的错误类型
+#[derive(Debug)]
+pub enum SearchError {
+    /// 数据库访问失败
+    Database(tokio_postgres::Error),
+    /// 向量嵌入接口调用失败
+    Embedding(String),
+    /// 配置无效
+    Config(String),
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::Database(e) => write!(f, "数据库错误: {}", e),
+            SearchError::Embedding(msg) => write!(f, "向量嵌入错误: {}", msg),
+            SearchError::Config(msg) => write!(f, "配置错误: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SearchError::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for SearchError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        SearchError::Database(e)
+    }
+}
+
+impl SearchError {
+    /// 是否值得在新的数据库连接上重试
+    ///
+    /// 连接已断开（后台 `connection` 任务已结束）、网络IO错误以及连接类错误
+    /// （SQLSTATE 08xxx、57P01-57P03 服务端关闭或重启）视为可重试；
+    /// SQL语法错误、表不存在等查询本身的错误以及其他变体均不可重试
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            SearchError::Database(e) => is_connection_error(e),
+            _ => false,
+        }
+    }
+}
+
+// 数据库错误是否由连接问题引起，而不是查询本身的错误
+fn is_connection_error(e: &tokio_postgres::Error) -> bool {
+    if e.is_closed() {
+        return true;
+    }
+    if let Some(code) = e.code() {
+        let code = code.code();
+        return code.starts_with("08") || matches!(code, "57P01" | "57P02" | "57P03");
+    }
+
+    let mut source = std::error::Error::source(e);
+    while let Some(inner) = source {
+        if inner.is::<std::io::Error>() {
+            return true;
+        }
+        source = inner.source();
+    }
+    false
+}
+
+// 把公开接口返回的错误中未包装的数据库错误统一转换为 `SearchError::Database`，
+// 以便调用方向下转型后用 `is_retriable` 判断是否重试；其他错误原样返回
+pub(crate) fn normalize_error(e: Box<dyn std::error::Error>) -> Box<dyn std::error::Error> {
+    match e.downcast::<tokio_postgres::Error>() {
+        Ok(e) => Box::new(SearchError::Database(*e)),
+        Err(e) => e,
+    }
+}
@@ -0,0 +1,191 @@
+use crate::search::core::{RecommendCrate, SearchModule, SearchSortCriteria};
+use crate::search::hybrid_search::HybridSearchModule;
+use crate::search::metrics::{
+    grades_from_judgments, ndcg_at_k, precision_at_k, recall, GradedJudgment,
+};
+use crate::search::traditional_search::TraditionalSearchModule;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Instant;
+
+/// 可被评估的搜索实现
+///
+/// 本crate的 `SearchModule`、`TraditionalSearchModule`、`HybridSearchModule` 均已实现，
+/// 自定义搜索实现该trait后即可用 `evaluate` 在同一数据集上比较
+#[async_trait(?Send)]
+pub trait Searcher {
+    async fn search(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>>;
+}
+
+#[async_trait(?Send)]
+impl Searcher for SearchModule<'_> {
+    async fn search(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        self.search_crate(query, sort_by).await
+    }
+}
+
+#[async_trait(?Send)]
+impl Searcher for TraditionalSearchModule<'_> {
+    async fn search(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        TraditionalSearchModule::search(self, query, sort_by).await
+    }
+}
+
+#[async_trait(?Send)]
+impl Searcher for HybridSearchModule<'_> {
+    async fn search(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        HybridSearchModule::search(self, query, sort_by).await
+    }
+}
+
+/// 一条标注过的查询：查询文本及其相关crate的分级判断
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QueryRelevance {
+    pub query: String,
+    // 查询说明，仅用于报告展示
+    #[serde(default)]
+    pub description: String,
+    // 等级大于0的crate视为相关
+    pub judgments: Vec<GradedJudgment>,
+}
+
+impl QueryRelevance {
+    /// 只标注了相关crate列表（不分级）的查询，每个crate的等级均为1
+    pub fn binary(query: impl Into<String>, relevant: &[&str]) -> Self {
+        QueryRelevance {
+            query: query.into(),
+            description: String::new(),
+            judgments: relevant
+                .iter()
+                .map(|name| GradedJudgment {
+                    crate_name: name.to_string(),
+                    grade: 1,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// 单条查询的评估结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EvaluationResult {
+    pub query: String,
+    pub result_count: usize,
+    pub precision_at_1: f64,
+    pub precision_at_3: f64,
+    pub precision_at_5: f64,
+    pub precision_at_10: f64,
+    // 全部结果相对于已标注相关crate的召回率
+    pub recall: f64,
+    pub ndcg_at_10: f64,
+    pub latency_ms: f64,
+}
+
+/// 整个数据集的评估报告，平均值只统计搜索成功的查询
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EvaluationReport {
+    pub results: Vec<EvaluationResult>,
+    // 搜索失败的查询及错误信息
+    pub failures: Vec<(String, String)>,
+    pub mean_precision_at_1: f64,
+    pub mean_precision_at_3: f64,
+    pub mean_precision_at_5: f64,
+    pub mean_precision_at_10: f64,
+    pub mean_recall: f64,
+    pub mean_ndcg_at_10: f64,
+    pub mean_latency_ms: f64,
+}
+
+/// 依次执行数据集中的每条查询，计算 P@k、召回率和 NDCG@10 并汇总平均值
+///
+/// 单条查询失败不会中断评估，错误记录在 `EvaluationReport::failures` 中
+pub async fn evaluate(
+    searcher: &dyn Searcher,
+    dataset: &[QueryRelevance],
+    sort_by: SearchSortCriteria,
+) -> EvaluationReport {
+    let mut report = EvaluationReport::default();
+
+    for case in dataset {
+        let started_at = Instant::now();
+        let results = match searcher.search(&case.query, sort_by.clone()).await {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("查询 \"{}\" 搜索失败: {}", case.query, e);
+                report.failures.push((case.query.clone(), e.to_string()));
+                continue;
+            }
+        };
+        let latency = started_at.elapsed();
+
+        report.results.push(evaluate_results(
+            case,
+            &results,
+            latency.as_secs_f64() * 1000.0,
+        ));
+    }
+
+    let count = report.results.len();
+    if count > 0 {
+        let mean = |metric: fn(&EvaluationResult) -> f64| {
+            report.results.iter().map(metric).sum::<f64>() / count as f64
+        };
+        report.mean_precision_at_1 = mean(|r| r.precision_at_1);
+        report.mean_precision_at_3 = mean(|r| r.precision_at_3);
+        report.mean_precision_at_5 = mean(|r| r.precision_at_5);
+        report.mean_precision_at_10 = mean(|r| r.precision_at_10);
+        report.mean_recall = mean(|r| r.recall);
+        report.mean_ndcg_at_10 = mean(|r| r.ndcg_at_10);
+        report.mean_latency_ms = mean(|r| r.latency_ms);
+    }
+
+    report
+}
+
+// 根据标注计算单条查询的各项指标
+fn evaluate_results(
+    case: &QueryRelevance,
+    results: &[RecommendCrate],
+    latency_ms: f64,
+) -> EvaluationResult {
+    let grades = grades_from_judgments(&case.judgments);
+    let relevant_set: HashSet<String> = case
+        .judgments
+        .iter()
+        .filter(|j| j.grade > 0)
+        .map(|j| j.crate_name.clone())
+        .collect();
+    let relevant_flags: Vec<bool> = results
+        .iter()
+        .map(|r| relevant_set.contains(&r.name))
+        .collect();
+
+    EvaluationResult {
+        query: case.query.clone(),
+        result_count: results.len(),
+        precision_at_1: precision_at_k(&relevant_flags, 1),
+        precision_at_3: precision_at_k(&relevant_flags, 3),
+        precision_at_5: precision_at_k(&relevant_flags, 5),
+        precision_at_10: precision_at_k(&relevant_flags, 10),
+        recall: recall(results, &relevant_set),
+        ndcg_at_10: ndcg_at_k(results, &grades, 10),
+        latency_ms,
+    }
+}
@@ -0,0 +1,246 @@
+use crate::search::core::{SearchModule, SearchSortCriteria};
+use crate::search::embedder::similarity;
+use crate::search::error::normalize_error;
+use crate::search::lang::{classify_query, detect_language, QueryKind, QueryLanguage};
+use crate::search::rerank::embed_query_with_retry;
+use crate::search::retrieve::{
+    primary_keywords, query_keywords, retrive_crates_with_tsquery, transfer_query_to_tsquery,
+};
+use crate::search::rewrite::{
+    extract_code_keywords, extract_keywords_with_llm, rewrite_query_with_config,
+};
+use crate::search::utils::{basic_keyword_extraction_with_ngrams, validate_identifier};
+use pgvector::Vector;
+
+/// 搜索过程的说明，用于排查查询被改写成了什么、各阶段剩余多少候选
+#[derive(Debug, Clone)]
+pub struct SearchExplanation {
+    // 原始查询
+    pub original_query: String,
+    // 检测到的查询语言
+    pub language: QueryLanguage,
+    // 是否被识别为自然语言查询
+    pub is_natural_language: bool,
+    // 查询类型
+    pub query_kind: QueryKind,
+    // 自然语言查询提取出的关键词或代码中提取出的标识符（关键词查询为None）
+    pub extracted_keywords: Option<String>,
+    // LLM（或后备方案）改写后的查询
+    pub rewritten_query: String,
+    // 参与检索的关键词列表
+    pub keywords: Vec<String>,
+    // 改写结果中的核心关键词（未分级时为空）
+    pub primary_keywords: Vec<String>,
+    // 最终执行的tsquery
+    pub tsquery: String,
+    // 排序方式
+    pub sort_by: SearchSortCriteria,
+    // 关键词检索阶段取回的候选数量
+    pub retrieved_count: usize,
+    // 截断到结果数量上限后返回的数量
+    pub result_count: usize,
+}
+
+/// 单个crate在某个查询下的诊断结果
+#[derive(Debug, Clone)]
+pub struct Diagnosis {
+    // 被诊断的crate名称
+    pub crate_name: String,
+    // 改写后的查询
+    pub rewritten_query: String,
+    // 检索使用的tsquery
+    pub tsquery: String,
+    // crate是否存在于数据表中
+    pub exists: bool,
+    // crate的tsv是否匹配tsquery，不匹配时不会进入候选集
+    pub matches_tsquery: bool,
+    // crate的ts_rank得分（crate不存在时为None）
+    pub ts_rank: Option<f32>,
+    // crate是否已有嵌入向量
+    pub has_embedding: bool,
+    // crate与查询向量的相似度（没有嵌入向量、离线或获取查询向量失败时为None）
+    pub vector_score: Option<f32>,
+}
+
+impl SearchModule<'_> {
+    /// 执行查询处理和关键词检索但不进行向量重排序，返回各步骤的中间结果
+    pub async fn explain_search(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<SearchExplanation, Box<dyn std::error::Error>> {
+        self.explain_search_inner(query, sort_by)
+            .await
+            .map_err(normalize_error)
+    }
+
+    async fn explain_search_inner(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<SearchExplanation, Box<dyn std::error::Error>> {
+        validate_identifier(&self.table_name)?;
+        let query_kind = classify_query(&self.normalize_query(query));
+        let (extracted_keywords, rewritten_query) = self.rewrite_quietly(query).await;
+        let normalized_query = self.normalize_query(query);
+        let (rewritten_query, original_terms) =
+            self.with_original_terms(&normalized_query, rewritten_query);
+
+        let tsquery = transfer_query_to_tsquery(&rewritten_query, &self.config).await?;
+        let candidates = retrive_crates_with_tsquery(
+            self.pg_client,
+            &self.table_name,
+            &rewritten_query,
+            &normalized_query,
+            &tsquery,
+            &original_terms,
+            &[],
+            &self.config,
+            None,
+            Some(&self.corpus_stats),
+        )
+        .await?;
+
+        Ok(SearchExplanation {
+            original_query: query.to_string(),
+            language: detect_language(query),
+            is_natural_language: query_kind == QueryKind::NaturalLanguage,
+            query_kind,
+            extracted_keywords,
+            keywords: query_keywords(&rewritten_query, self.config.max_keywords),
+            primary_keywords: primary_keywords(&rewritten_query, self.config.max_keywords)
+                .unwrap_or_default(),
+            rewritten_query,
+            tsquery,
+            sort_by,
+            retrieved_count: candidates.len(),
+            result_count: candidates.len().min(self.config.result_cap()),
+        })
+    }
+
+    /// 诊断指定crate为什么出现或没有出现在某个查询的结果中
+    ///
+    /// 依次检查crate是否存在、`tsv` 是否匹配生成的tsquery及其 `ts_rank`、
+    /// 是否有嵌入向量以及与查询向量的相似度
+    pub async fn diagnose(
+        &self,
+        query: &str,
+        crate_name: &str,
+    ) -> Result<Diagnosis, Box<dyn std::error::Error>> {
+        self.diagnose_inner(query, crate_name)
+            .await
+            .map_err(normalize_error)
+    }
+
+    async fn diagnose_inner(
+        &self,
+        query: &str,
+        crate_name: &str,
+    ) -> Result<Diagnosis, Box<dyn std::error::Error>> {
+        validate_identifier(&self.table_name)?;
+        let (_, rewritten_query) = self.rewrite_quietly(query).await;
+        let (rewritten_query, _) =
+            self.with_original_terms(&self.normalize_query(query), rewritten_query);
+        let tsquery = transfer_query_to_tsquery(&rewritten_query, &self.config).await?;
+
+        let mut diagnosis = Diagnosis {
+            crate_name: crate_name.to_string(),
+            rewritten_query,
+            tsquery,
+            exists: false,
+            matches_tsquery: false,
+            ts_rank: None,
+            has_embedding: false,
+            vector_score: None,
+        };
+
+        let weights = self
+            .config
+            .field_weights
+            .map(|weights| format!("{}, ", weights.ts_rank_weights()))
+            .unwrap_or_default();
+        let statement = format!(
+            "SELECT id, COALESCE(tsv @@ {0}, false) AS matches,
+            ts_rank({1}tsv, {0}) AS rank
+            FROM {2} WHERE name = $2 LIMIT 1",
+            self.config.keyword_tsquery_call("$1"),
+            weights,
+            self.table_name
+        );
+        let Some(row) = self
+            .pg_client
+            .query_opt(statement.as_str(), &[&diagnosis.tsquery, &crate_name])
+            .await?
+        else {
+            return Ok(diagnosis);
+        };
+
+        let crate_id: String = row.get("id");
+        diagnosis.exists = true;
+        diagnosis.matches_tsquery = row.get("matches");
+        diagnosis.ts_rank = row.get("rank");
+
+        // 没有embedding列（未启用pgvector）时视为没有嵌入向量
+        let embedding_statement = format!(
+            "SELECT embedding FROM {} WHERE id = $1 AND embedding IS NOT NULL",
+            self.config.embedding_table_for(&self.table_name)
+        );
+        let embedding = match self
+            .pg_client
+            .query_opt(embedding_statement.as_str(), &[&crate_id])
+            .await
+        {
+            Ok(row) => row.map(|row| Vec::<f32>::from(row.get::<_, Vector>("embedding"))),
+            Err(e) => {
+                eprintln!("无法读取crate '{}'的嵌入向量: {}", crate_name, e);
+                None
+            }
+        };
+        diagnosis.has_embedding = embedding.is_some();
+
+        // 离线模式下不调用嵌入接口
+        if let Some(embedding) = embedding.filter(|_| !self.config.offline) {
+            if let Some(query_embedding) =
+                embed_query_with_retry(&self.search_embedder(), query).await
+            {
+                diagnosis.vector_score = Some(similarity(
+                    self.config.similarity_metric,
+                    &query_embedding,
+                    &embedding,
+                ));
+            }
+        }
+
+        Ok(diagnosis)
+    }
+
+    // 与搜索相同的查询处理和改写逻辑，但不打印中间结果
+    async fn rewrite_quietly(&self, query: &str) -> (Option<String>, String) {
+        let llm_config = self.active_llm_config();
+        let query = self.normalize_query(query);
+        let query = query.as_ref();
+
+        let extracted_keywords = match classify_query(query) {
+            QueryKind::CodeOrError => {
+                Some(extract_code_keywords(query, self.config.keyword_max_ngram))
+            }
+            QueryKind::NaturalLanguage => {
+                Some(match extract_keywords_with_llm(query, &llm_config).await {
+                    Some(Ok(keywords)) => keywords,
+                    _ => basic_keyword_extraction_with_ngrams(query, self.config.keyword_max_ngram),
+                })
+            }
+            QueryKind::Keyword => None,
+        };
+        let processed_query = extracted_keywords
+            .clone()
+            .unwrap_or_else(|| query.to_string());
+
+        let rewritten_query = rewrite_query_with_config(&processed_query, &llm_config)
+            .await
+            .unwrap_or_else(|_| processed_query.clone());
+        let rewritten_query = self.normalize_query(&rewritten_query).into_owned();
+
+        (extracted_keywords, rewritten_query)
+    }
+}
@@ -0,0 +1,88 @@
+use crate::search::config::GraphBoost;
+use crate::search::core::RecommendCrate;
+use crate::search::error::SearchError;
+use crate::search::utils::validate_identifier;
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::Client as PgClient;
+
+/// 关联表中的一条边 `(a_id, b_id, weight)`
+pub type CrateEdge = (String, String, f32);
+
+// 读取候选crate两两之间的边，只返回两端都在候选中的边
+pub(crate) async fn fetch_crate_edges(
+    pg_client: &PgClient,
+    boost: &GraphBoost,
+    ids: &[&str],
+) -> Result<Vec<CrateEdge>, SearchError> {
+    validate_identifier(&boost.edge_table)?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let statement = format!(
+        "SELECT a_id::text AS a_id, b_id::text AS b_id, weight::real AS weight
+         FROM {}
+         WHERE a_id::text = ANY($1) AND b_id::text = ANY($1) AND weight > 0",
+        boost.edge_table
+    );
+    let rows = pg_client.query(&statement, &[&ids]).await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("a_id"), row.get("b_id"), row.get("weight")))
+        .collect())
+}
+
+/// 为与种子结果（最终得分最高的 `seed_count` 个crate）有边相连的crate加分
+///
+/// 边视为无向，权重按 `edges` 中最大的权重归一化；每个crate取与各种子之间最大的归一化权重，
+/// 加上 `boost.weight` 倍的该值。种子之间同样互相加分，但不会因自身得到加分。
+/// 所有加分都基于调整前的得分计算，与处理顺序无关
+pub fn apply_graph_boost(crates: &mut [RecommendCrate], edges: &[CrateEdge], boost: &GraphBoost) {
+    let max_weight = edges
+        .iter()
+        .map(|(_, _, weight)| *weight)
+        .filter(|weight| weight.is_finite())
+        .fold(0.0f32, f32::max);
+    if boost.weight <= 0.0 || boost.seed_count == 0 || max_weight <= 0.0 {
+        return;
+    }
+
+    // 按调整前的得分选出种子
+    let mut order: Vec<usize> = (0..crates.len()).collect();
+    let key = |i: &usize| {
+        let score = crates[*i].final_score;
+        if score.is_nan() {
+            f32::NEG_INFINITY
+        } else {
+            score
+        }
+    };
+    order.sort_by(|a, b| key(b).total_cmp(&key(a)));
+    let seeds: HashSet<&str> = order
+        .iter()
+        .take(boost.seed_count)
+        .map(|&i| crates[i].id.as_str())
+        .collect();
+
+    // 每个crate与种子之间最大的归一化权重
+    let mut strongest: HashMap<String, f32> = HashMap::new();
+    for (a, b, weight) in edges {
+        if a == b || !weight.is_finite() || *weight <= 0.0 {
+            continue;
+        }
+        let normalized = weight / max_weight;
+        for (node, neighbour) in [(a, b), (b, a)] {
+            if seeds.contains(neighbour.as_str()) {
+                let entry = strongest.entry(node.clone()).or_insert(0.0);
+                *entry = entry.max(normalized);
+            }
+        }
+    }
+
+    for crate_item in crates.iter_mut() {
+        if let Some(normalized) = strongest.get(&crate_item.id) {
+            crate_item.final_score += boost.weight * normalized;
+        }
+    }
+}
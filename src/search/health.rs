@@ -0,0 +1,124 @@
+use crate::search::core::SearchModule;
+use crate::search::error::SearchError;
+use crate::search::utils::validate_identifier;
+
+// 搜索依赖的数据表列
+const REQUIRED_COLUMNS: [&str; 6] = ["id", "name", "description", "keywords", "tsv", "embedding"];
+
+/// 单个子系统的检查结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubsystemStatus {
+    Ok,
+    Failed(String),
+    /// 未执行检查
+    Skipped,
+}
+
+impl SubsystemStatus {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, SubsystemStatus::Ok)
+    }
+}
+
+/// 健康检查报告
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    // crate数据表是否可查询
+    pub table: SubsystemStatus,
+    // 数据表是否包含搜索所需的列
+    pub schema: SubsystemStatus,
+    // 缺少的列名
+    pub missing_columns: Vec<String>,
+    // 向量嵌入接口是否可用
+    pub embedding: SubsystemStatus,
+}
+
+impl HealthReport {
+    /// 所有已执行的检查均通过
+    pub fn is_healthy(&self) -> bool {
+        [&self.table, &self.schema, &self.embedding]
+            .iter()
+            .all(|status| !matches!(status, SubsystemStatus::Failed(_)))
+    }
+}
+
+impl SearchModule<'_> {
+    /// 检查数据表、所需列以及向量嵌入接口是否可用
+    pub async fn health_check(&self) -> Result<HealthReport, SearchError> {
+        self.health_check_with(true).await
+    }
+
+    /// 健康检查，`ping_embedding` 为false时不调用向量嵌入接口
+    pub async fn health_check_with(
+        &self,
+        ping_embedding: bool,
+    ) -> Result<HealthReport, SearchError> {
+        validate_identifier(&self.table_name)?;
+
+        // 1. 对数据表执行一次轻量查询
+        let table = match self
+            .pg_client
+            .query(
+                format!("SELECT 1 FROM {} LIMIT 1", self.table_name).as_str(),
+                &[],
+            )
+            .await
+        {
+            Ok(_) => SubsystemStatus::Ok,
+            Err(e) => SubsystemStatus::Failed(e.to_string()),
+        };
+
+        // 2. 检查所需列是否存在，数据库不可达时直接返回错误；
+        // 嵌入向量存放在独立嵌入表时，在该表中检查 embedding 列
+        let embedding_table = self.config.embedding_table_for(&self.table_name);
+        let rows = self
+            .pg_client
+            .query(
+                "SELECT table_name::text, column_name::text FROM information_schema.columns
+                WHERE table_schema = current_schema() AND table_name = ANY($1)",
+                &[&[self.table_name.as_str(), embedding_table].as_slice()],
+            )
+            .await?;
+        let columns: Vec<(String, String)> =
+            rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+        let missing_columns: Vec<String> = REQUIRED_COLUMNS
+            .iter()
+            .filter_map(|&column| {
+                let table = if column == "embedding" {
+                    embedding_table
+                } else {
+                    self.table_name.as_str()
+                };
+                let present = columns.iter().any(|(t, c)| t == table && c == column);
+                match (present, table == self.table_name) {
+                    (true, _) => None,
+                    (false, true) => Some(column.to_string()),
+                    (false, false) => Some(format!("{}.{}", table, column)),
+                }
+            })
+            .collect();
+        let schema = if missing_columns.is_empty() {
+            SubsystemStatus::Ok
+        } else {
+            SubsystemStatus::Failed(format!("缺少列: {}", missing_columns.join(", ")))
+        };
+
+        // 3. 用很短的输入调用一次向量嵌入接口，离线模式下跳过
+        let embedding = if ping_embedding && !self.config.offline {
+            match self.embedder.embed_one("health check").await {
+                Ok(vector) if !vector.is_empty() => SubsystemStatus::Ok,
+                Ok(_) => SubsystemStatus::Failed("向量嵌入接口返回空向量".to_string()),
+                Err(e) => SubsystemStatus::Failed(e.to_string()),
+            }
+        } else {
+            SubsystemStatus::Skipped
+        };
+
+        Ok(HealthReport {
+            table,
+            schema,
+            missing_columns,
+            embedding,
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use crate::search::config::{SearchConfig, SearchOptions};
+use crate::search::core::{RecommendCrate, SearchSortCriteria};
+use crate::search::embedder::{Embedder, EmbeddingProvider};
+use crate::search::error::normalize_error;
+use crate::search::rerank::rerank_crates;
+use crate::search::traditional_search::TraditionalSearchModule;
+use std::env;
+use std::sync::Arc;
+use tokio_postgres::Client as PgClient;
+
+/// 混合搜索模块 - 使用传统多策略关键词检索保证召回，再用向量重排序进行语义排序
+pub struct HybridSearchModule<'a> {
+    pg_client: &'a PgClient,
+    table_name: String,
+    traditional: TraditionalSearchModule<'a>,
+    embedder: Arc<dyn Embedder>,
+    config: SearchConfig,
+}
+
+impl<'a> HybridSearchModule<'a> {
+    pub async fn new(pg_client: &'a PgClient) -> Self {
+        let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
+        HybridSearchModule {
+            pg_client,
+            table_name,
+            traditional: TraditionalSearchModule::new(pg_client).await,
+            embedder: EmbeddingProvider::from_env().into_embedder(),
+            config: SearchConfig::default(),
+        }
+    }
+
+    /// 设置向量嵌入提供者
+    pub fn with_embedder(mut self, embedder: impl Embedder + 'static) -> Self {
+        self.embedder = Arc::new(embedder);
+        self
+    }
+
+    /// 设置得分权重等搜索配置
+    pub fn with_config(mut self, config: SearchConfig) -> Self {
+        self.traditional = self.traditional.with_config(config.clone());
+        self.config = config;
+        self
+    }
+
+    /// 混合搜索：传统检索的候选结果经向量重排序后返回
+    pub async fn search(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        self.search_inner(query, sort_by)
+            .await
+            .map_err(normalize_error)
+    }
+
+    async fn search_inner(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        // 1. 传统多策略检索，关键词得分按命中策略的权重折算
+        let candidates: Vec<RecommendCrate> = self
+            .traditional
+            .collect_candidates(query)
+            .await?
+            .into_iter()
+            .map(|(mut crate_item, weight)| {
+                crate_item.rank *= weight;
+                crate_item
+            })
+            .collect();
+
+        println!("混合搜索候选结果数量: {}", candidates.len());
+
+        // 2. 向量重排序
+        rerank_crates(
+            candidates,
+            query,
+            sort_by,
+            self.pg_client,
+            &self.table_name,
+            self.embedder.as_ref(),
+            &self.config,
+            &SearchOptions::default(),
+        )
+        .await
+    }
+}
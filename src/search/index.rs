@@ -0,0 +1,212 @@
+use crate::search::config::SearchConfig;
+use crate::search::error::SearchError;
+use crate::search::utils::validate_identifier;
+use std::collections::HashMap;
+use tokio_postgres::Client as PgClient;
+
+/// 重新生成数据表的 `tsv` 列，返回更新的行数
+///
+/// 名称和描述分别以 A、B 权重写入（与 `FieldWeights` 的要求一致）。
+/// `ts_config` 为PostgreSQL的文本搜索配置名，如 `english`；中文等语料可使用 `simple` 避免词干化。
+/// 批量导入数据后调用即可，无需自行编写更新语句
+pub async fn rebuild_tsv(
+    pg_client: &PgClient,
+    table_name: &str,
+    ts_config: &str,
+) -> Result<u64, SearchError> {
+    validate_identifier(table_name)?;
+    let statement = format!(
+        "UPDATE {} SET tsv =
+            setweight(to_tsvector($1::text::regconfig, coalesce(name, '')), 'A') ||
+            setweight(to_tsvector($1::text::regconfig, coalesce(description, '')), 'B')",
+        table_name
+    );
+    Ok(pg_client.execute(&statement, &[&ts_config]).await?)
+}
+
+/// 检查并补全搜索所需的数据表结构
+///
+/// 要求数据表已存在并包含 `id`、`name`、`description` 列，缺少时返回配置错误。
+/// 依次完成以下步骤，已存在的部分不做改动，可重复调用：
+/// - 补上检索时读取的 `version`、`downloads`、`repository`、`keywords` 列；
+/// - 安装 `pg_trgm` 扩展并为 `name` 建立三元组索引（后备检索使用）；
+/// - 缺少 `tsv` 列时创建该列及GIN索引，并按数据库的 `default_text_search_config` 生成内容；
+/// - 安装 `vector`（pgvector）扩展，缺少 `embedding` 列时创建 `vector(dim)` 列及HNSW余弦索引；
+///   `config.embeddings_table` 不为None时改为创建该独立嵌入表 `(id text PRIMARY KEY, embedding vector(dim))`。
+///
+/// 已有 `embedding` 列的维度与 `dim` 不一致、或数据库没有可用的pgvector扩展时返回配置错误
+pub async fn ensure_schema(
+    pg_client: &PgClient,
+    table_name: &str,
+    dim: usize,
+    config: &SearchConfig,
+) -> Result<(), SearchError> {
+    validate_identifier(table_name)?;
+    config.validate_identifiers()?;
+    let exists: bool = pg_client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&table_name])
+        .await?
+        .get(0);
+    if !exists {
+        return Err(SearchError::Config(format!("数据表 {} 不存在", table_name)));
+    }
+
+    // 列名 -> 类型（含类型修饰，如 vector(1536)）
+    let rows = pg_client
+        .query(
+            "SELECT attname::text, format_type(atttypid, atttypmod)
+            FROM pg_attribute
+            WHERE attrelid = $1::text::regclass AND attnum > 0 AND NOT attisdropped",
+            &[&table_name],
+        )
+        .await?;
+    let columns: HashMap<String, String> =
+        rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+    let missing: Vec<&str> = ["id", "name", "description"]
+        .into_iter()
+        .filter(|column| !columns.contains_key(*column))
+        .collect();
+    if !missing.is_empty() {
+        return Err(SearchError::Config(format!(
+            "数据表 {} 缺少必需的列: {}",
+            table_name,
+            missing.join(", ")
+        )));
+    }
+
+    // 检索时读取的其他列，缺少时以可为空的列补上
+    for (column, column_type) in [
+        ("version", "text"),
+        ("downloads", "bigint"),
+        ("repository", "text"),
+        ("keywords", "text[]"),
+    ] {
+        if !columns.contains_key(column) {
+            pg_client
+                .batch_execute(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table_name, column, column_type
+                ))
+                .await?;
+        }
+    }
+
+    ensure_extension(pg_client, "pg_trgm").await?;
+    pg_client
+        .batch_execute(&format!(
+            "CREATE INDEX IF NOT EXISTS {0}_name_trgm_idx ON {0} USING GIN (name gin_trgm_ops)",
+            table_name
+        ))
+        .await?;
+
+    match columns.get("tsv").map(String::as_str) {
+        Some("tsvector") => {}
+        Some(other) => {
+            return Err(SearchError::Config(format!(
+                "数据表 {} 的 tsv 列类型为 {}，应为 tsvector",
+                table_name, other
+            )));
+        }
+        None => {
+            pg_client
+                .batch_execute(&format!(
+                    "ALTER TABLE {} ADD COLUMN tsv tsvector",
+                    table_name
+                ))
+                .await?;
+            let ts_config: String = pg_client
+                .query_one("SELECT current_setting('default_text_search_config')", &[])
+                .await?
+                .get(0);
+            rebuild_tsv(pg_client, table_name, &ts_config).await?;
+        }
+    }
+    pg_client
+        .batch_execute(&format!(
+            "CREATE INDEX IF NOT EXISTS {0}_tsv_idx ON {0} USING GIN (tsv)",
+            table_name
+        ))
+        .await?;
+
+    ensure_extension(pg_client, "vector").await?;
+    let expected = format!("vector({})", dim);
+    let embedding_table = config.embedding_table_for(table_name);
+    let embedding_columns = if embedding_table == table_name {
+        columns
+    } else {
+        pg_client
+            .batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (id text PRIMARY KEY, embedding {})",
+                embedding_table, expected
+            ))
+            .await?;
+        column_types(pg_client, embedding_table).await?
+    };
+    match embedding_columns.get("embedding") {
+        Some(embedding_type) if *embedding_type == expected => {}
+        Some(other) => {
+            return Err(SearchError::Config(format!(
+                "数据表 {} 的 embedding 列类型为 {}，与要求的 {} 不一致",
+                embedding_table, other, expected
+            )));
+        }
+        None => {
+            pg_client
+                .batch_execute(&format!(
+                    "ALTER TABLE {} ADD COLUMN embedding {}",
+                    embedding_table, expected
+                ))
+                .await?;
+        }
+    }
+    pg_client
+        .batch_execute(&format!(
+            "CREATE INDEX IF NOT EXISTS {0}_embedding_idx ON {0} USING hnsw (embedding vector_cosine_ops)",
+            embedding_table
+        ))
+        .await?;
+
+    Ok(())
+}
+
+// 数据表的列名 -> 类型（含类型修饰，如 vector(1536)）
+async fn column_types(
+    pg_client: &PgClient,
+    table_name: &str,
+) -> Result<HashMap<String, String>, SearchError> {
+    let rows = pg_client
+        .query(
+            "SELECT attname::text, format_type(atttypid, atttypmod)
+            FROM pg_attribute
+            WHERE attrelid = $1::text::regclass AND attnum > 0 AND NOT attisdropped",
+            &[&table_name],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+// 安装扩展；已安装时不做任何操作，数据库没有该扩展时返回配置错误
+async fn ensure_extension(pg_client: &PgClient, extension: &str) -> Result<(), SearchError> {
+    let row = pg_client
+        .query_one(
+            "SELECT
+                EXISTS (SELECT 1 FROM pg_extension WHERE extname = $1),
+                EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = $1)",
+            &[&extension],
+        )
+        .await?;
+    let (installed, available): (bool, bool) = (row.get(0), row.get(1));
+    if installed {
+        return Ok(());
+    }
+    if !available {
+        return Err(SearchError::Config(format!(
+            "数据库没有可用的 {} 扩展，请先在数据库服务器上安装",
+            extension
+        )));
+    }
+    pg_client
+        .batch_execute(&format!("CREATE EXTENSION IF NOT EXISTS {}", extension))
+        .await?;
+    Ok(())
+}
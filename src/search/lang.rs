@@ -0,0 +1,211 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// 查询语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryLanguage {
+    /// 仅包含英文字母
+    English,
+    /// 仅包含中文字符
+    Chinese,
+    /// 中英文混合
+    Mixed,
+    /// 其他（如纯数字、符号或其他语言）
+    Other,
+}
+
+/// 判断字符是否为中文（CJK统一汉字）
+pub fn is_chinese_char(c: char) -> bool {
+    ('\u{4e00}'..='\u{9fff}').contains(&c)
+}
+
+/// 判断文本中是否包含中文字符
+pub fn contains_chinese(text: &str) -> bool {
+    text.chars().any(is_chinese_char)
+}
+
+/// 检测查询的语言
+pub fn detect_language(query: &str) -> QueryLanguage {
+    let has_chinese = contains_chinese(query);
+    let has_english = query.chars().any(|c| c.is_ascii_alphabetic());
+
+    match (has_chinese, has_english) {
+        (true, true) => QueryLanguage::Mixed,
+        (true, false) => QueryLanguage::Chinese,
+        (false, true) => QueryLanguage::English,
+        (false, false) => QueryLanguage::Other,
+    }
+}
+
+/// 检测查询是否为自然语言句子，支持中英文
+pub fn is_natural_language(query: &str) -> bool {
+    // 中文特定检测
+    let has_chinese = contains_chinese(query);
+
+    // 中文自然语言特征检测
+    let chinese_question_markers = [
+        "吗",
+        "？",
+        "如何",
+        "怎么",
+        "什么",
+        "哪个",
+        "为什么",
+        "谁",
+        "何时",
+        "在哪",
+    ];
+    let contains_chinese_question = chinese_question_markers
+        .iter()
+        .any(|&marker| query.contains(marker));
+
+    // 中文句子通常更短，降低中文单词数量阈值
+    let word_count_threshold = if has_chinese { 2 } else { 3 };
+    let word_count = query.split_whitespace().count();
+
+    // 英文检测逻辑
+    let contains_question_mark = query.contains('?') || query.contains('？');
+    let contains_period = query.contains('.') || query.contains('。');
+    let contains_common_question_words = query.to_lowercase().split_whitespace().any(|word| {
+        [
+            "how", "what", "which", "where", "who", "why", "can", "could", "help", "find", "need",
+            "want", "looking",
+        ]
+        .contains(&word)
+    });
+
+    // 综合判断标准
+    word_count > word_count_threshold
+        || contains_question_mark
+        || contains_period
+        || contains_common_question_words
+        || (has_chinese && (contains_chinese_question || word_count > 1))
+}
+
+/// 查询类型，决定改写前如何预处理查询
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    /// 关键词查询，如 `http client`
+    Keyword,
+    /// 自然语言句子，先提取关键词再改写
+    NaturalLanguage,
+    /// 粘贴的代码片段或编译器错误信息，先用规则提取其中的标识符再改写
+    CodeOrError,
+}
+
+// 编译器错误信息的特征：错误码、error:/warning: 开头、panic信息
+fn error_message_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r"(?m)error\[E\d{4}\]|^\s*(error|warning)(\[[^\]]*\])?:|panicked at|-->\s*\S+:\d+",
+        )
+        .expect("错误信息正则表达式无效")
+    })
+}
+
+// 代码的特征：路径 `a::b`、箭头、属性、反引号包裹的代码、常见的Rust语法
+fn code_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r"[A-Za-z_]\w*::[A-Za-z_<]|->|=>|#!?\[|`[^`]+`|\b(fn|impl|let|use|struct|enum|dyn|pub)\s+[A-Za-z_&<]|[A-Za-z_]\w*<[A-Za-z_&'][^>]*>|[;{}]\s*$",
+        )
+        .expect("代码正则表达式无效")
+    })
+}
+
+// 反引号包裹的代码片段
+fn backtick_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"`([^`]+)`").expect("反引号正则表达式无效"))
+}
+
+// 标识符或路径，如 `serde_json::Value`、`HashMap`
+fn identifier_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"[A-Za-z_][A-Za-z0-9_]*(?:::[A-Za-z_][A-Za-z0-9_]*)*")
+            .expect("标识符正则表达式无效")
+    })
+}
+
+// 对搜索crate没有帮助的标识符：关键字、标准库路径和常见类型、错误信息中的普通单词
+const IGNORED_IDENTIFIERS: [&str; 54] = [
+    "as", "async", "await", "const", "crate", "dyn", "enum", "fn", "for", "impl", "in", "let",
+    "mut", "pub", "ref", "self", "Self", "static", "struct", "super", "trait", "type", "use",
+    "where", "std", "core", "alloc", "str", "bool", "char", "usize", "isize", "u8", "u16", "u32",
+    "u64", "i32", "i64", "f32", "f64", "String", "Vec", "Option", "Result", "Box", "Some", "None",
+    "Ok", "Err", "T", "E", "error", "warning", "note",
+];
+
+/// 判断查询类型：代码或错误信息优先，其次是自然语言句子，其余视为关键词查询
+pub fn classify_query(query: &str) -> QueryKind {
+    if is_code_or_error(query) {
+        QueryKind::CodeOrError
+    } else if is_natural_language(query) {
+        QueryKind::NaturalLanguage
+    } else {
+        QueryKind::Keyword
+    }
+}
+
+/// 检测查询是否为代码片段或编译器错误信息
+pub fn is_code_or_error(query: &str) -> bool {
+    error_message_regex().is_match(query) || code_regex().is_match(query.trim())
+}
+
+/// 从代码片段或错误信息中提取可用于搜索的标识符
+///
+/// 有反引号包裹的代码时只从其中提取（编译器错误信息中类型和路径都在反引号内），
+/// 否则从整个输入提取。路径的首段视为crate名称，其余各段只保留类型名
+/// （`reqwest::Client::new` 提取 `reqwest` 和 `Client`）；普通的小写单词只在反引号内保留。
+/// 关键字、标准库路径和 `Option`、`Vec` 等常见类型会被忽略，结果去重并保持出现顺序
+pub fn extract_code_identifiers(query: &str) -> Vec<String> {
+    let spans: Vec<&str> = backtick_regex()
+        .captures_iter(query)
+        .filter_map(|captures| captures.get(1))
+        .map(|m| m.as_str())
+        .collect();
+    let from_spans = !spans.is_empty();
+    let sources = if from_spans { spans } else { vec![query] };
+
+    let ignored: HashSet<&str> = IGNORED_IDENTIFIERS.into_iter().collect();
+    let mut seen = HashSet::new();
+    let mut identifiers = Vec::new();
+    let mut push = |identifier: &str| {
+        if !ignored.contains(identifier) && seen.insert(identifier.to_string()) {
+            identifiers.push(identifier.to_string());
+        }
+    };
+
+    for source in sources {
+        for m in identifier_regex().find_iter(source) {
+            let segments: Vec<&str> = m.as_str().split("::").collect();
+            if segments.len() > 1 {
+                // 路径：首段为crate名称，其余各段只保留类型名
+                push(segments[0]);
+                for segment in &segments[1..] {
+                    if is_type_name(segment) {
+                        push(segment);
+                    }
+                }
+            } else {
+                let identifier = segments[0];
+                // 错误信息正文中的小写单词（如 the、bound）没有意义，只保留类型名
+                if is_type_name(identifier) || from_spans && identifier.len() > 1 {
+                    push(identifier);
+                }
+            }
+        }
+    }
+
+    identifiers
+}
+
+// 首字母大写且含小写字母的标识符视为类型名，如 `HashMap`、`Serialize`
+fn is_type_name(identifier: &str) -> bool {
+    identifier.starts_with(|c: char| c.is_ascii_uppercase())
+        && identifier.chars().any(|c| c.is_ascii_lowercase())
+}
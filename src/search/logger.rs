@@ -0,0 +1,83 @@
+use crate::search::core::{SearchOutcome, SearchSortCriteria};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+
+/// 单次搜索的结构化记录，用于离线分析搜索质量
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchRecord {
+    // 搜索开始时间
+    pub timestamp: DateTime<Utc>,
+    // 原始查询
+    pub query: String,
+    // 改写后实际用于检索的查询
+    pub rewritten_query: String,
+    // 由改写后查询生成的tsquery
+    pub tsquery: String,
+    pub sort_by: SearchSortCriteria,
+    // 查询改写耗时（毫秒）
+    pub rewrite_ms: u64,
+    // 关键词检索耗时（毫秒），含后备检索和分面统计
+    pub retrieve_ms: u64,
+    // 获取向量嵌入和排序的耗时（毫秒）
+    pub rerank_ms: u64,
+    // 搜索总耗时（毫秒）
+    pub total_ms: u64,
+    pub result_count: usize,
+    // 排名第一的crate名称及得分
+    pub top_result: Option<String>,
+    pub top_score: Option<f32>,
+    pub used_llm_rewrite: bool,
+    pub used_vector_rerank: bool,
+    pub fallback_reasons: Vec<String>,
+}
+
+/// 搜索日志钩子
+///
+/// 每次搜索成功后以 `SearchRecord` 调用一次，存储格式由实现方决定
+/// （如追加JSON行到文件或发送到分析系统）。在搜索所在的任务中同步调用，
+/// 耗时的写入应由实现方自行转交后台处理
+pub trait SearchLogger: Send + Sync {
+    fn log(&self, record: SearchRecord);
+}
+
+// 检索阶段收集的中间信息，搜索结束后转换为 SearchRecord
+#[derive(Debug, Default)]
+pub(crate) struct SearchTrace {
+    pub(crate) rewritten_query: String,
+    pub(crate) tsquery: String,
+    pub(crate) rewrite: Duration,
+    pub(crate) retrieve: Duration,
+}
+
+impl SearchTrace {
+    pub(crate) fn to_record(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+        timestamp: DateTime<Utc>,
+        total: Duration,
+        outcome: &SearchOutcome,
+    ) -> SearchRecord {
+        let top = outcome.results.first();
+        SearchRecord {
+            timestamp,
+            query: query.to_string(),
+            rewritten_query: self.rewritten_query.clone(),
+            tsquery: self.tsquery.clone(),
+            sort_by,
+            rewrite_ms: self.rewrite.as_millis() as u64,
+            retrieve_ms: self.retrieve.as_millis() as u64,
+            rerank_ms: total
+                .saturating_sub(self.rewrite + self.retrieve)
+                .as_millis() as u64,
+            total_ms: total.as_millis() as u64,
+            result_count: outcome.results.len(),
+            top_result: top.map(|c| c.name.clone()),
+            top_score: top.map(|c| c.final_score),
+            used_llm_rewrite: outcome.used_llm_rewrite,
+            used_vector_rerank: outcome.used_vector_rerank,
+            fallback_reasons: outcome.fallback_reasons.clone(),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::search::core::RecommendCrate;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// 分级相关性判断：0 表示不相关，3 表示高度相关
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GradedJudgment {
+    pub crate_name: String,
+    pub grade: u8,
+}
+
+/// 将分级判断列表转换为 crate名称 -> 相关性等级 的映射
+pub fn grades_from_judgments(judgments: &[GradedJudgment]) -> HashMap<String, u8> {
+    judgments
+        .iter()
+        .map(|j| (j.crate_name.clone(), j.grade))
+        .collect()
+}
+
+/// 计算 P@k（二元相关性）
+pub fn precision_at_k(relevant_flags: &[bool], k: usize) -> f64 {
+    if relevant_flags.is_empty() || k == 0 {
+        return 0.0;
+    }
+
+    let k_actual = k.min(relevant_flags.len());
+    let relevant_count = relevant_flags
+        .iter()
+        .take(k_actual)
+        .filter(|&&is_relevant| is_relevant)
+        .count();
+
+    relevant_count as f64 / k_actual as f64
+}
+
+/// 计算 R@k：前k个结果中相关结果数占全部已标注相关crate数的比例
+pub fn recall_at_k(relevant_flags: &[bool], total_relevant: usize, k: usize) -> f64 {
+    if total_relevant == 0 {
+        return 0.0;
+    }
+
+    let found = relevant_flags
+        .iter()
+        .take(k)
+        .filter(|&&is_relevant| is_relevant)
+        .count();
+
+    found.min(total_relevant) as f64 / total_relevant as f64
+}
+
+/// 计算全部结果相对于已标注相关crate集合的召回率
+pub fn recall(results: &[RecommendCrate], relevant_set: &HashSet<String>) -> f64 {
+    if relevant_set.is_empty() {
+        return 0.0;
+    }
+
+    let found: HashSet<&str> = results
+        .iter()
+        .map(|r| r.name.as_str())
+        .filter(|name| relevant_set.contains(*name))
+        .collect();
+
+    found.len() as f64 / relevant_set.len() as f64
+}
+
+/// 按结果顺序取出每个crate的相关性等级，未标注的视为0
+pub fn grades_for_results(
+    results: &[RecommendCrate],
+    relevance_grades: &HashMap<String, u8>,
+) -> Vec<u8> {
+    results
+        .iter()
+        .map(|r| relevance_grades.get(&r.name).copied().unwrap_or(0))
+        .collect()
+}
+
+/// 计算 DCG@k，增益为 2^grade - 1，折损为 log2(位置 + 1)
+pub fn dcg_at_k(grades: &[u8], k: usize) -> f64 {
+    grades
+        .iter()
+        .take(k)
+        .enumerate()
+        .map(|(i, &grade)| (2f64.powi(grade as i32) - 1.0) / (i as f64 + 2.0).log2())
+        .sum()
+}
+
+/// 计算 NDCG@k
+///
+/// 理想排序由 `relevance_grades` 中所有标注的等级降序得到，
+/// 因此未被检索到的相关crate同样会拉低得分
+pub fn ndcg_at_k(
+    results: &[RecommendCrate],
+    relevance_grades: &HashMap<String, u8>,
+    k: usize,
+) -> f64 {
+    let mut ideal_grades: Vec<u8> = relevance_grades.values().copied().collect();
+    ideal_grades.sort_unstable_by(|a, b| b.cmp(a));
+
+    let ideal_dcg = dcg_at_k(&ideal_grades, k);
+    if ideal_dcg <= 0.0 {
+        return 0.0;
+    }
+
+    dcg_at_k(&grades_for_results(results, relevance_grades), k) / ideal_dcg
+}
@@ -1,15 +1,61 @@
+mod autocomplete;
+mod cache;
+mod config;
 mod core;
+mod error;
+mod explain;
+mod graph;
+mod health;
+mod hybrid_search;
+mod index;
+mod logger;
 mod rerank;
 mod retrieve;
 mod rewrite;
+mod similar;
+mod spelling;
+mod suggest;
 mod traditional_search;
 mod utils; // 添加新模块
 
 pub mod embedder; // 将原来的 pub mod embedding; 改为 pub mod embedder;
+pub mod evaluation; // 基于标注数据集的搜索评估
+pub mod lang; // 查询语言检测
+pub mod metrics; // 搜索质量评估指标
 
 // 重新导出公共接口
-pub use core::{RecommendCrate, SearchModule, SearchSortCriteria};
-pub use rerank::rerank_crates;
+pub use cache::{spawn_cache_warmer, QueryCache};
+pub use config::{
+    ApiFlavor, FieldWeights, GraphBoost, HttpOptions, KeywordScorer, LlmConfig, PopularityColumn,
+    QueryKindWeights, RecencyBoost, ResultFilter, RetrievalFallback, ScoreWeights, SearchConfig,
+    SearchOptions, SpellingCorrection, StrategyLimits, TermCombination, TsQuerySyntax,
+    VectorScoreSource,
+};
+pub use core::{
+    CrateGroup, RecommendCrate, SearchEvent, SearchModule, SearchModuleBuilder, SearchOutcome,
+    SearchResponse, SearchSortCriteria, StageTimings,
+};
+pub use error::SearchError;
+pub use explain::{Diagnosis, SearchExplanation};
+pub use graph::{apply_graph_boost, CrateEdge};
+pub use health::{HealthReport, SubsystemStatus};
+pub use hybrid_search::HybridSearchModule; // 导出混合搜索模块
+pub use index::{ensure_schema, rebuild_tsv};
+pub use logger::{SearchLogger, SearchRecord};
+pub use rerank::{
+    apply_feedback, apply_name_match_boost, calculate_final_score, exclude_crates,
+    filter_by_min_score, filter_by_version, group_by_family, rank_by_keyword_only,
+    reciprocal_rank_fusion, rerank_crates, sort_by_score_desc, RRF_K,
+};
 pub use retrieve::retrive_crates;
-pub use rewrite::{extract_keywords_from_query, rewrite_query};
-pub use traditional_search::TraditionalSearchModule; // 导出传统搜索模块
+pub use rewrite::{
+    extract_keywords_from_query, extract_keywords_with_config, rewrite_query,
+    rewrite_query_structured, rewrite_query_structured_with_config, rewrite_query_with_config,
+    StructuredRewrite, CRATES_IO_CATEGORIES,
+};
+pub use spelling::CrateVocabulary;
+pub use traditional_search::{prefix_match_terms, TraditionalSearchModule}; // 导出传统搜索模块
+pub use utils::{
+    basic_keyword_extraction, basic_keyword_extraction_with_ngrams, escape_like_pattern,
+    normalize_unicode, truncate, validate_identifier, word_ngrams,
+};
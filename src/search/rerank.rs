@@ -1,64 +1,732 @@
-use crate::search::core::{RecommendCrate, SearchSortCriteria};
+use crate::search::config::{RecencyBoost, SearchConfig, SearchOptions};
+use crate::search::core::{CrateGroup, RecommendCrate, SearchOutcome, SearchSortCriteria};
 use crate::search::embedder::{
-    cosine_similarity, fetch_or_create_embeddings, get_query_embedding, EmbeddingMode,
+    fetch_or_create_embeddings, similarity, Embedder, EmbeddingMode, PrecomputedPolicy,
 };
+use crate::search::error::SearchError;
+use crate::search::graph::{apply_graph_boost, fetch_crate_edges, CrateEdge};
+use chrono::{DateTime, Utc};
+use semver::{Version, VersionReq};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
 use tokio_postgres::Client as PgClient;
 
+// 查询向量获取失败后重试前的等待时间
+const QUERY_EMBEDDING_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+// 自定义排序中下载量得分为满分的下载次数
+const DOWNLOADS_FULL_SCORE: f64 = 100_000_000.0;
+
+/// 倒数排名融合（RRF）的平滑常数，排名第r的结果得分为 `1 / (RRF_K + r)`
+pub const RRF_K: f32 = 60.0;
+
 // 重新实现混合排序函数，使用批量嵌入处理
+#[allow(clippy::too_many_arguments)]
 pub async fn rerank_crates(
     crates: Vec<RecommendCrate>,
     query: &str,
     sort_criteria: SearchSortCriteria,
     pg_client: &PgClient,
     table_name: &str,
+    embedder: &dyn Embedder,
+    config: &SearchConfig,
+    options: &SearchOptions,
 ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
-    // 首先获取查询向量
-    let query_embedding = match get_query_embedding(query).await {
-        Ok(embedding) => embedding,
-        Err(e) => {
-            eprintln!("获取查询向量失败: {}", e);
-            return Ok(rank_by_keyword_only(crates));
-        }
+    rerank_crates_in_tables(
+        vec![(table_name.to_string(), crates)],
+        query,
+        sort_criteria,
+        pg_client,
+        embedder,
+        None,
+        config,
+        options,
+        &mut SearchOutcome::default(),
+    )
+    .await
+}
+
+// 对来自多个数据表的候选结果统一重排序
+//
+// 每组候选的嵌入向量从各自的数据表读取（或写回），查询向量只计算一次；
+// 同一crate出现在多个表中时只保留得分最高的一条；是否使用了向量重排序及降级原因记录到 `outcome`；
+// `permits` 限制按需生成嵌入的并发数，为None时不限制
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn rerank_crates_in_tables(
+    groups: Vec<(String, Vec<RecommendCrate>)>,
+    query: &str,
+    sort_criteria: SearchSortCriteria,
+    pg_client: &PgClient,
+    embedder: &dyn Embedder,
+    permits: Option<&Semaphore>,
+    config: &SearchConfig,
+    options: &SearchOptions,
+    outcome: &mut SearchOutcome,
+) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+    let ids: Vec<&str> = groups
+        .iter()
+        .flat_map(|(_, crates)| crates.iter().map(|c| c.id.as_str()))
+        .collect();
+    let edges = load_graph_edges(pg_client, &ids, config, outcome).await;
+
+    // 离线模式下不调用嵌入接口，直接使用仅关键词排序
+    if config.offline {
+        let crates = groups.into_iter().flat_map(|(_, crates)| crates).collect();
+        return Ok(rank_keyword_only_finalized(
+            crates, query, config, options, &edges,
+        ));
+    }
+
+    // 超过 rerank_candidate_cap 的低分候选直接丢弃，限制嵌入接口的调用次数
+    let groups = match config.rerank_candidate_cap {
+        Some(cap) => partition_candidates(groups, cap).0,
+        None => groups,
+    };
+
+    // 设置了 rerank_top_n 时只对关键词得分最高的前N个候选做向量重排序，
+    // 其余候选按关键词得分排在其后，保留分页所需的结果深度
+    let (groups, tail) = match config.rerank_top_n {
+        Some(n) => partition_candidates(groups, n),
+        None => (groups, Vec::new()),
+    };
+    let head = rerank_with_embeddings(
+        groups,
+        query,
+        sort_criteria,
+        pg_client,
+        embedder,
+        permits,
+        config,
+        options,
+        outcome,
+        &edges,
+    )
+    .await?;
+    Ok(append_keyword_ranked_tail(
+        head, tail, query, config, options, &edges,
+    ))
+}
+
+// 获取嵌入向量并按向量得分重排序；获取失败或超时时退回仅关键词排序
+#[allow(clippy::too_many_arguments)]
+async fn rerank_with_embeddings(
+    groups: Vec<(String, Vec<RecommendCrate>)>,
+    query: &str,
+    sort_criteria: SearchSortCriteria,
+    pg_client: &PgClient,
+    embedder: &dyn Embedder,
+    permits: Option<&Semaphore>,
+    config: &SearchConfig,
+    options: &SearchOptions,
+    outcome: &mut SearchOutcome,
+    edges: &[CrateEdge],
+) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+    // 获取查询向量和各组候选的嵌入向量；设置了 rerank_timeout 时超时即退回仅关键词排序，
+    // 按需生成的向量以单条UPDATE写回，超时中断不会留下部分写入
+    let embed_started_at = Instant::now();
+    let acquisition = acquire_embeddings(&groups, query, pg_client, embedder, permits, config);
+    let acquired = match config.rerank_timeout {
+        Some(limit) => match tokio::time::timeout(limit, acquisition).await {
+            Ok(acquired) => acquired,
+            Err(_) => {
+                outcome.record_embed_time(embed_started_at.elapsed());
+                let reason = format!(
+                    "获取嵌入向量超过 {} 毫秒，使用仅关键词排序",
+                    limit.as_millis()
+                );
+                eprintln!("{}", reason);
+                outcome.fallback_reasons.push(reason);
+                let crates = groups.into_iter().flat_map(|(_, crates)| crates).collect();
+                return Ok(rank_keyword_only_finalized(
+                    crates, query, config, options, edges,
+                ));
+            }
+        },
+        None => acquisition.await,
     };
+    outcome.record_embed_time(embed_started_at.elapsed());
 
-    // 获取或创建crate的嵌入向量，使用默认的OnDemand模式
-    let id_to_embedding =
-        fetch_or_create_embeddings(&crates, pg_client, table_name, EmbeddingMode::default()).await;
+    // 查询向量重试后仍获取失败时退回到仅关键词排序
+    let Some((query_embedding, embeddings, missing_count)) = acquired else {
+        eprintln!("无法获取查询向量，使用仅关键词排序");
+        outcome
+            .fallback_reasons
+            .push("无法获取查询向量，使用仅关键词排序".to_string());
+        let crates = groups.into_iter().flat_map(|(_, crates)| crates).collect();
+        return Ok(rank_keyword_only_finalized(
+            crates, query, config, options, edges,
+        ));
+    };
+    let groups_with_embeddings: Vec<_> = groups
+        .into_iter()
+        .map(|(_, crates)| crates)
+        .zip(embeddings)
+        .collect();
+
+    // 预先计算模式下有crate缺少向量时按策略报错
+    if missing_count > 0 && config.precomputed_policy == PrecomputedPolicy::Error {
+        return Err(Box::new(SearchError::Embedding(format!(
+            "有 {} 个crate缺少预先计算的嵌入向量，请先运行 precompute_all_embeddings",
+            missing_count
+        ))));
+    }
 
-    // 步骤5: 计算相似度并排序结果
+    // 预先计算模式下缺少向量（ComputeMissing 补算失败）的crate逐个退回仅关键词得分，
+    // 其余crate仍按向量得分排序
+    let keyword_only_when_missing = config.embedding_mode == EmbeddingMode::Precomputed;
     let mut enhanced_crates = Vec::new();
+    let mut without_embedding = 0;
+    let mut keyword_only = 0;
 
-    for (_, mut crate_item) in crates.into_iter().enumerate() {
-        if let Some(embedding) = id_to_embedding.get(&crate_item.id) {
-            // 计算向量相似度
-            let similarity = cosine_similarity(&query_embedding, embedding);
+    for (crates, id_to_embedding) in groups_with_embeddings {
+        // 计算相似度
+        for mut crate_item in crates.into_iter() {
+            if let Some(embedding) = id_to_embedding.get(&crate_item.id) {
+                // 计算向量相似度
+                let similarity = similarity(config.similarity_metric, &query_embedding, embedding);
 
-            // 保存向量分数
-            crate_item.vector_score = similarity;
+                // 保存向量分数
+                crate_item.vector_score = similarity;
+                if options.include_embeddings {
+                    crate_item.embedding = Some(embedding.clone());
+                }
 
-            // 计算最终得分
-            crate_item.final_score =
-                calculate_final_score(crate_item.rank, similarity, &sort_criteria);
-        } else {
-            // 如果没有获取到嵌入
-            crate_item.vector_score = 0.0;
-            crate_item.final_score = calculate_final_score(crate_item.rank, 0.0, &sort_criteria);
+                // 计算最终得分
+                crate_item.final_score = calculate_final_score(
+                    crate_item.rank,
+                    similarity,
+                    crate_item.updated_at,
+                    crate_item.downloads,
+                    crate_item.popularity,
+                    &sort_criteria,
+                    config,
+                );
+            } else if keyword_only_when_missing {
+                // 与 rank_by_keyword_only 相同，最终得分取关键词得分
+                keyword_only += 1;
+                crate_item.vector_score = 0.0;
+                crate_item.final_score = crate_item.rank;
+            } else {
+                // 如果没有获取到嵌入
+                without_embedding += 1;
+                crate_item.vector_score = 0.0;
+                crate_item.final_score = calculate_final_score(
+                    crate_item.rank,
+                    0.0,
+                    crate_item.updated_at,
+                    crate_item.downloads,
+                    crate_item.popularity,
+                    &sort_criteria,
+                    config,
+                );
+            }
+
+            enhanced_crates.push(crate_item);
         }
+    }
 
-        enhanced_crates.push(crate_item);
+    outcome.used_vector_rerank = keyword_only < enhanced_crates.len();
+    if keyword_only > 0 {
+        let reason = format!(
+            "有 {} 个crate缺少预先计算的嵌入向量，这些crate使用仅关键词得分",
+            keyword_only
+        );
+        eprintln!("{}", reason);
+        outcome.fallback_reasons.push(reason);
+    }
+    if without_embedding > 0 {
+        outcome.fallback_reasons.push(format!(
+            "有 {} 个crate未能获取嵌入向量，向量得分按0计算",
+            without_embedding
+        ));
+    }
+
+    Ok(finalize_results(
+        enhanced_crates,
+        query,
+        config,
+        options,
+        edges,
+    ))
+}
+
+// 在所有组中按关键词得分保留前 `n` 个候选（各组内保持原有顺序），返回保留的各组和其余候选
+fn partition_candidates(
+    groups: Vec<(String, Vec<RecommendCrate>)>,
+    n: usize,
+) -> (Vec<(String, Vec<RecommendCrate>)>, Vec<RecommendCrate>) {
+    let total: usize = groups.iter().map(|(_, crates)| crates.len()).sum();
+    if total <= n {
+        return (groups, Vec::new());
+    }
+
+    // 与 sort_by_score_desc 的顺序一致：NaN得分排在最后，得分相同时的顺序固定
+    let mut ranked: Vec<(usize, usize, &RecommendCrate)> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, (_, crates))| {
+            crates
+                .iter()
+                .enumerate()
+                .map(move |(index, crate_item)| (group, index, crate_item))
+        })
+        .collect();
+    ranked.sort_by(|a, b| compare_by_score_desc(a.2, b.2, |c| c.rank));
+    let kept: HashSet<(usize, usize)> = ranked
+        .into_iter()
+        .take(n)
+        .map(|(group, index, _)| (group, index))
+        .collect();
+
+    let mut rest = Vec::with_capacity(total - n);
+    let groups = groups
+        .into_iter()
+        .enumerate()
+        .map(|(group, (table_name, crates))| {
+            let mut head = Vec::new();
+            for (index, crate_item) in crates.into_iter().enumerate() {
+                if kept.contains(&(group, index)) {
+                    head.push(crate_item);
+                } else {
+                    rest.push(crate_item);
+                }
+            }
+            (table_name, head)
+        })
+        .collect();
+    (groups, rest)
+}
+
+// 把未参与向量重排序的候选按关键词得分排序后接在重排序结果之后，不与前面的结果混合排序；
+// 同样经过过滤，并与前面的结果一起受 result_limit 限制
+fn append_keyword_ranked_tail(
+    mut head: Vec<RecommendCrate>,
+    tail: Vec<RecommendCrate>,
+    query: &str,
+    config: &SearchConfig,
+    options: &SearchOptions,
+    edges: &[CrateEdge],
+) -> Vec<RecommendCrate> {
+    let cap = config.result_cap();
+    if tail.is_empty() || head.len() >= cap {
+        return head;
+    }
+
+    let seen: HashSet<String> = head.iter().map(|c| c.id.clone()).collect();
+    let tail = rank_keyword_only_finalized(tail, query, config, options, edges);
+    head.extend(tail.into_iter().filter(|c| !seen.contains(&c.id)));
+    head.truncate(cap);
+    head
+}
+
+// 获取查询向量（失败时重试一次）和每组候选的嵌入向量，返回查询向量、各组的 id -> 向量映射
+// 以及预先计算模式下缺少向量的crate数量；查询向量获取失败时返回None
+async fn acquire_embeddings(
+    groups: &[(String, Vec<RecommendCrate>)],
+    query: &str,
+    pg_client: &PgClient,
+    embedder: &dyn Embedder,
+    permits: Option<&Semaphore>,
+    config: &SearchConfig,
+) -> Option<(Vec<f32>, Vec<HashMap<String, Vec<f32>>>, usize)> {
+    let query_embedding = embed_query_with_retry(embedder, query).await?;
+
+    let mut embeddings = Vec::with_capacity(groups.len());
+    let mut missing_count = 0;
+    for (table_name, crates) in groups {
+        let id_to_embedding = match (config.embedding_mode, config.precomputed_policy) {
+            // 预先计算模式下按需补算缺失向量，与搜索时计算模式相同
+            (EmbeddingMode::OnDemand, _)
+            | (EmbeddingMode::Precomputed, PrecomputedPolicy::ComputeMissing) => {
+                fetch_or_create_embeddings(
+                    crates,
+                    pg_client,
+                    table_name,
+                    EmbeddingMode::OnDemand,
+                    embedder,
+                    permits,
+                    config.persist_on_demand,
+                    config,
+                )
+                .await
+            }
+            (EmbeddingMode::Precomputed, _) => {
+                let id_to_embedding = fetch_or_create_embeddings(
+                    crates,
+                    pg_client,
+                    table_name,
+                    EmbeddingMode::Precomputed,
+                    embedder,
+                    permits,
+                    false,
+                    config,
+                )
+                .await;
+                missing_count += crates
+                    .iter()
+                    .filter(|c| !id_to_embedding.contains_key(&c.id))
+                    .count();
+                id_to_embedding
+            }
+        };
+        embeddings.push(id_to_embedding);
+    }
+
+    Some((query_embedding, embeddings, missing_count))
+}
+
+// 使用检索阶段由数据库计算好的向量得分排序
+pub(crate) fn rank_with_vector_scores(
+    mut crates: Vec<RecommendCrate>,
+    query: &str,
+    sort_criteria: SearchSortCriteria,
+    config: &SearchConfig,
+    options: &SearchOptions,
+    edges: &[CrateEdge],
+) -> Vec<RecommendCrate> {
+    for crate_item in crates.iter_mut() {
+        crate_item.final_score = calculate_final_score(
+            crate_item.rank,
+            crate_item.vector_score,
+            crate_item.updated_at,
+            crate_item.downloads,
+            crate_item.popularity,
+            &sort_criteria,
+            config,
+        );
+    }
+    finalize_results(crates, query, config, options, edges)
+}
+
+// 仅基于关键词得分排序并完成后处理
+pub(crate) fn rank_keyword_only_finalized(
+    crates: Vec<RecommendCrate>,
+    query: &str,
+    config: &SearchConfig,
+    options: &SearchOptions,
+    edges: &[CrateEdge],
+) -> Vec<RecommendCrate> {
+    finalize_results(
+        rank_by_keyword_only(crates, usize::MAX),
+        query,
+        config,
+        options,
+        edges,
+    )
+}
+
+// 读取候选之间的共现关系，未启用共现加分时为空；读取失败时记录降级原因，本次不加分
+pub(crate) async fn load_graph_edges(
+    pg_client: &PgClient,
+    ids: &[&str],
+    config: &SearchConfig,
+    outcome: &mut SearchOutcome,
+) -> Vec<CrateEdge> {
+    let Some(boost) = &config.graph_boost else {
+        return Vec::new();
+    };
+    match fetch_crate_edges(pg_client, boost, ids).await {
+        Ok(edges) => edges,
+        Err(e) => {
+            let reason = format!("读取crate关联表失败，不使用共现加分: {}", e);
+            eprintln!("{}", reason);
+            outcome.fallback_reasons.push(reason);
+            Vec::new()
+        }
+    }
+}
+
+// 排序后处理：名称命中加分、应用相关性反馈和共现加分、按最终得分排序、去重、按最低得分过滤并截断
+fn finalize_results(
+    mut crates: Vec<RecommendCrate>,
+    query: &str,
+    config: &SearchConfig,
+    options: &SearchOptions,
+    edges: &[CrateEdge],
+) -> Vec<RecommendCrate> {
+    // crate名称与查询词相同时加分，避免语义相近的其他crate排在它前面
+    apply_name_match_boost(
+        &mut crates,
+        query,
+        config.name_match_boost,
+        config.name_prefix_boost,
+    );
+
+    // 应用用户的相关性反馈
+    apply_feedback(&mut crates, &options.feedback, config.feedback_weight);
+
+    // 与高分结果共现的crate加分
+    if let Some(boost) = &config.graph_boost {
+        apply_graph_boost(&mut crates, edges, boost);
     }
 
     // 根据最终得分排序
-    enhanced_crates.sort_by(|a, b| b.final_score.partial_cmp(&a.final_score).unwrap());
+    sort_by_score_desc(&mut crates, |c| c.final_score);
+    let mut crates = dedup_by_id(crates);
+
+    // 按排除列表、调用方的业务规则和版本要求过滤，过滤后再截断以保证结果数量
+    exclude_crates(&mut crates, &options.exclude);
+    if let Some(filter) = &options.filter {
+        filter.retain(&mut crates);
+    }
+    if let Some(version_req) = &options.version_req {
+        filter_by_version(&mut crates, version_req, options.keep_unparsable_versions);
+    }
+
+    // 去掉得分过低的长尾结果
+    if let Some(min_score) = options.min_final_score {
+        filter_by_min_score(&mut crates, min_score, options.allow_empty_results);
+    }
+
+    // 只返回前 result_limit 个结果
+    crates.truncate(config.result_cap());
+    crates
+}
+
+// 去掉名称或id在 `exclude` 中的crate，保持其余结果的顺序
+pub fn exclude_crates(crates: &mut Vec<RecommendCrate>, exclude: &[String]) {
+    if exclude.is_empty() {
+        return;
+    }
+    let exclude: HashSet<&str> = exclude.iter().map(String::as_str).collect();
+    crates.retain(|c| !exclude.contains(c.id.as_str()) && !exclude.contains(c.name.as_str()));
+}
+
+// 去掉最终得分低于阈值的结果（输入需已按得分降序排列）
+// 全部低于阈值时，除非 allow_empty 为true，否则保留得分最高的一个
+pub fn filter_by_min_score(crates: &mut Vec<RecommendCrate>, min_score: f32, allow_empty: bool) {
+    let keep = crates
+        .iter()
+        .take_while(|c| c.final_score >= min_score)
+        .count();
+    let keep = if keep == 0 && !allow_empty {
+        crates.len().min(1)
+    } else {
+        keep
+    };
+    crates.truncate(keep);
+}
+
+// 下载量得分：按对数缩放到 [0, 1]，1亿次下载为满分
+fn downloads_score(downloads: i64) -> f32 {
+    ((downloads.max(0) as f64).ln_1p() / DOWNLOADS_FULL_SCORE.ln_1p()).min(1.0) as f32
+}
+
+// 按crate家族分组：名称中第一个 `-` 之前的部分相同即视为同一家族（`_` 视同 `-`，忽略大小写）
+// 每组得分最高的crate作为主结果，各组按主结果的得分降序排列
+pub fn group_by_family(crates: &[RecommendCrate]) -> Vec<CrateGroup> {
+    let mut families: Vec<(String, Vec<RecommendCrate>)> = Vec::new();
+    for crate_item in crates {
+        let family = crate_family(&crate_item.name);
+        match families.iter_mut().find(|(name, _)| *name == family) {
+            Some((_, members)) => members.push(crate_item.clone()),
+            None => families.push((family, vec![crate_item.clone()])),
+        }
+    }
+
+    let mut primaries = Vec::with_capacity(families.len());
+    let mut related_by_id = HashMap::with_capacity(families.len());
+    for (_, mut members) in families {
+        sort_by_score_desc(&mut members, |c| c.final_score);
+        let primary = members.remove(0);
+        related_by_id.insert(primary.id.clone(), members);
+        primaries.push(primary);
+    }
+
+    sort_by_score_desc(&mut primaries, |c| c.final_score);
+    primaries
+        .into_iter()
+        .map(|primary| CrateGroup {
+            related: related_by_id.remove(&primary.id).unwrap_or_default(),
+            primary,
+        })
+        .collect()
+}
+
+// crate名称的家族前缀
+fn crate_family(name: &str) -> String {
+    let name = name.to_lowercase().replace('_', "-");
+    match name.split_once('-') {
+        Some((prefix, _)) if !prefix.is_empty() => prefix.to_string(),
+        _ => name,
+    }
+}
+
+// 只保留版本满足 `version_req` 的crate
+// 版本号缺失或不是合法的semver版本时，按 keep_unparsable 决定保留或丢弃
+pub fn filter_by_version(
+    crates: &mut Vec<RecommendCrate>,
+    version_req: &VersionReq,
+    keep_unparsable: bool,
+) {
+    crates.retain(|crate_item| {
+        match crate_item
+            .version
+            .as_deref()
+            .and_then(|version| Version::parse(version.trim()).ok())
+        {
+            Some(version) => version_req.matches(&version),
+            None => keep_unparsable,
+        }
+    });
+}
 
-    // 只返回前100个结果
-    Ok(enhanced_crates.into_iter().take(100).collect())
+// 去除重复的crate，保留排在最前（得分最高）的一条
+fn dedup_by_id(crates: Vec<RecommendCrate>) -> Vec<RecommendCrate> {
+    let mut seen = HashSet::new();
+    crates
+        .into_iter()
+        .filter(|crate_item| seen.insert(crate_item.id.clone()))
+        .collect()
+}
+
+// 获取查询向量，首次失败（如网络抖动）时短暂等待后重试一次
+pub(crate) async fn embed_query_with_retry(
+    embedder: &dyn Embedder,
+    query: &str,
+) -> Option<Vec<f32>> {
+    match embedder.embed_one(query).await {
+        Ok(embedding) => return Some(embedding),
+        Err(e) => eprintln!("获取查询向量失败，准备重试: {}", e),
+    }
+
+    tokio::time::sleep(QUERY_EMBEDDING_RETRY_DELAY).await;
+
+    match embedder.embed_one(query).await {
+        Ok(embedding) => {
+            println!("重试获取查询向量成功，继续使用向量重排序");
+            Some(embedding)
+        }
+        Err(e) => {
+            eprintln!("重试获取查询向量仍然失败: {}", e);
+            None
+        }
+    }
+}
+
+// crate名称与查询中的某个词相同时加 exact_boost，是某个查询词的前缀时加 prefix_boost
+// 比较时忽略大小写，并把 `-` 与 `_` 视为相同（与crates.io的名称规则一致）
+pub fn apply_name_match_boost(
+    crates: &mut [RecommendCrate],
+    query: &str,
+    exact_boost: f32,
+    prefix_boost: f32,
+) {
+    let normalize = |s: &str| s.to_lowercase().replace('-', "_");
+    let tokens: Vec<String> = query
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .filter(|token| !token.is_empty())
+        .map(normalize)
+        .collect();
+    if tokens.is_empty() {
+        return;
+    }
+
+    for crate_item in crates.iter_mut() {
+        let name = normalize(&crate_item.name);
+        if name.is_empty() {
+            continue;
+        }
+        if tokens.contains(&name) {
+            crate_item.final_score += exact_boost;
+        } else if tokens.iter().any(|token| token.starts_with(&name)) {
+            crate_item.final_score += prefix_boost;
+        }
+    }
+}
+
+// 根据相关性反馈（crate名称 -> [-1, 1] 的调整值）微调最终得分
+// 不把调整后的得分限制在 [0, 1] 内：名称匹配、近期更新等加分后得分可能超过1，
+// 截断会使正反馈反而降低这些crate的得分；调整结果不是有限值时保留原得分
+pub fn apply_feedback(crates: &mut [RecommendCrate], feedback: &HashMap<String, f32>, weight: f32) {
+    if feedback.is_empty() {
+        return;
+    }
+
+    for crate_item in crates.iter_mut() {
+        if let Some(&adjustment) = feedback.get(&crate_item.name) {
+            if adjustment.is_nan() {
+                continue;
+            }
+            let adjustment = adjustment.clamp(-1.0, 1.0);
+            let adjusted = crate_item.final_score + weight * adjustment;
+            if adjusted.is_finite() {
+                crate_item.final_score = adjusted;
+            }
+        }
+    }
+}
+
+// 按得分降序排序，NaN得分视为最低分排在最后，避免 partial_cmp 返回 None 导致panic
+// 得分相同时依次按下载量降序、名称升序、id升序排列，保证每次运行的顺序一致
+pub fn sort_by_score_desc<F>(crates: &mut [RecommendCrate], score: F)
+where
+    F: Fn(&RecommendCrate) -> f32,
+{
+    crates.sort_by(|a, b| compare_by_score_desc(a, b, &score));
+}
+
+// sort_by_score_desc 使用的比较函数，得分较高的crate排在前面
+fn compare_by_score_desc<F>(a: &RecommendCrate, b: &RecommendCrate, score: F) -> Ordering
+where
+    F: Fn(&RecommendCrate) -> f32,
+{
+    let key = |c: &RecommendCrate| {
+        let value = score(c);
+        if value.is_nan() {
+            f32::NEG_INFINITY
+        } else {
+            value
+        }
+    };
+    key(b)
+        .total_cmp(&key(a))
+        .then_with(|| b.downloads.cmp(&a.downloads))
+        .then_with(|| a.name.cmp(&b.name))
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+/// 用倒数排名融合（RRF）合并多个排名列表
+///
+/// 每个列表只取前 `top_k` 个结果，排名第r（从1开始）的crate得 `1 / (RRF_K + r)` 分，
+/// 按crate id累加后写入 `final_score` 并降序排列。同一crate保留首次出现时的其他字段，
+/// 在多个列表中都排名靠前的crate得分最高
+pub fn reciprocal_rank_fusion(
+    rankings: Vec<Vec<RecommendCrate>>,
+    top_k: usize,
+) -> Vec<RecommendCrate> {
+    let mut fused: Vec<RecommendCrate> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for ranking in rankings {
+        for (index, crate_item) in ranking.into_iter().take(top_k).enumerate() {
+            let score = 1.0 / (RRF_K + index as f32 + 1.0);
+            match positions.get(&crate_item.id) {
+                Some(&position) => fused[position].final_score += score,
+                None => {
+                    positions.insert(crate_item.id.clone(), fused.len());
+                    fused.push(RecommendCrate {
+                        final_score: score,
+                        ..crate_item
+                    });
+                }
+            }
+        }
+    }
+
+    sort_by_score_desc(&mut fused, |c| c.final_score);
+    fused
 }
 
 // 仅基于关键词的排序（向量检索失败时的后备方案）
-pub fn rank_by_keyword_only(mut crates: Vec<RecommendCrate>) -> Vec<RecommendCrate> {
+pub fn rank_by_keyword_only(
+    mut crates: Vec<RecommendCrate>,
+    result_limit: usize,
+) -> Vec<RecommendCrate> {
     // 根据关键词检索得分排序
-    crates.sort_by(|a, b| b.rank.partial_cmp(&a.rank).unwrap());
+    sort_by_score_desc(&mut crates, |c| c.rank);
 
     // 设置默认的向量得分和最终得分
     for crate_item in &mut crates {
@@ -66,29 +734,68 @@ pub fn rank_by_keyword_only(mut crates: Vec<RecommendCrate>) -> Vec<RecommendCra
         crate_item.final_score = crate_item.rank;
     }
 
-    crates.into_iter().take(100).collect()
+    crates.into_iter().take(result_limit).collect()
 }
 
-// 计算最终得分
+// 计算最终得分：关键词得分和向量得分按排序方式对应的权重加权
+// 向量得分先限制在 [0, 1] 内：相似度为负的crate与没有嵌入向量的crate同样按0计算，
+// 避免语义无关的crate排在缺少向量的crate之后
+// 启用近期更新加分且已知更新时间时，再加上按更新时间衰减的加分
+// `Custom` 排序额外按权重计入下载量和更新时间得分，见 SearchSortCriteria::Custom
+// 配置了热度列时，`Downloads` 排序再加上按对数归一化的热度得分，见 PopularityColumn
 pub fn calculate_final_score(
     keyword_score: f32,
     vector_score: f32,
+    updated_at: Option<DateTime<Utc>>,
+    downloads: i64,
+    popularity: Option<f64>,
     sort_criteria: &SearchSortCriteria,
+    config: &SearchConfig,
 ) -> f32 {
-    match sort_criteria {
-        SearchSortCriteria::Comprehensive => {
-            // 综合评分：关键词得分和向量得分的加权平均
-            0.6 * keyword_score + 0.4 * vector_score
-        }
-        SearchSortCriteria::Relavance => {
-            // 相关性优先：关键词得分权重更高
-            0.8 * keyword_score + 0.2 * vector_score
-        }
-        SearchSortCriteria::Downloads => {
-            // 下载量优先：这里仍然使用混合评分，但在后续处理中会优先考虑下载量
-            // 在这个简化版本中，我们暂时还是使用关键词和向量的混合得分
-            0.5 * keyword_score + 0.5 * vector_score
-            // 注意：理想情况下这里应该结合crate的下载量数据
+    // NaN同样按0计算
+    let vector_score = if vector_score.is_nan() {
+        0.0
+    } else {
+        vector_score.clamp(0.0, 1.0)
+    };
+
+    if let SearchSortCriteria::Custom {
+        keyword,
+        vector,
+        downloads: downloads_weight,
+        recency,
+    } = *sort_criteria
+    {
+        let weights = [keyword, vector, downloads_weight, recency].map(|w| w.max(0.0));
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 || !total.is_finite() {
+            return 0.0;
         }
+        let recency_score = match (config.recency_boost, updated_at) {
+            (Some(boost), Some(updated_at)) => RecencyBoost {
+                weight: 1.0,
+                ..boost
+            }
+            .score(updated_at, Utc::now()),
+            _ => 0.0,
+        };
+        let scores = [
+            keyword_score,
+            vector_score,
+            downloads_score(downloads),
+            recency_score,
+        ];
+        return weights.iter().zip(scores).map(|(w, s)| w * s).sum::<f32>() / total;
+    }
+
+    let weights = config.weights_for(sort_criteria);
+    let mut score = weights.keyword * keyword_score + weights.vector * vector_score;
+    // 近期更新加分是独立的一项，不改变关键词与向量得分的权重
+    if let (Some(boost), Some(updated_at)) = (config.recency_boost, updated_at) {
+        score += boost.score(updated_at, Utc::now());
+    }
+    if let (SearchSortCriteria::Downloads, Some(column)) = (sort_criteria, &config.popularity) {
+        score += column.weight * column.score(popularity);
     }
+    score
 }
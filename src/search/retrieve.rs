@@ -1,57 +1,864 @@
+use crate::search::config::{
+    KeywordScorer, RetrievalFallback, SearchConfig, TermCombination, TsQuerySyntax,
+};
 use crate::search::core::RecommendCrate;
-use tokio_postgres::Client as PgClient;
+use crate::search::utils::{is_sql_identifier, validate_identifier};
+use chrono::{DateTime, Utc};
+use pgvector::Vector;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client as PgClient, Row};
+
+// 排除列表不超过该数量时在检索SQL中直接排除，更长的列表只在重排序后过滤
+const MAX_SQL_EXCLUSIONS: usize = 100;
 
 pub async fn retrive_crates(
     client: &PgClient,
     table_name: &str,
     query: &str,
+    config: &SearchConfig,
+) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+    validate_identifier(table_name)?;
+    retrive_crates_with_embedding(
+        client,
+        table_name,
+        query,
+        query,
+        &[],
+        &[],
+        config,
+        None,
+        None,
+    )
+    .await
+}
+
+// BM25使用的全表统计：文档总数和平均文档长度（tsv中的词位数量）
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CorpusStats {
+    total_docs: i64,
+    avg_length: f32,
+}
+
+// 按数据表缓存的全表统计，保存在 `SearchModule` 上，首次使用BM25评分时计算，之后不再扫描全表
+pub(crate) type CorpusStatsCache = RwLock<HashMap<String, CorpusStats>>;
+
+// 检索候选crate，传入查询向量时同时由数据库计算向量相似度
+//
+// `query` 为改写后的关键词，`original_query` 为用户的原始查询，生成的tsquery有语法错误时用于重新检索
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn retrive_crates_with_embedding(
+    client: &PgClient,
+    table_name: &str,
+    query: &str,
+    original_query: &str,
+    original_terms: &[String],
+    exclude: &[String],
+    config: &SearchConfig,
+    query_embedding: Option<&[f32]>,
+    corpus_stats: Option<&CorpusStatsCache>,
 ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
     // 处理关键词
-    let tsquery = transfer_query_to_tsquery(query).await?;
+    let tsquery = transfer_query_to_tsquery(query, config).await?;
 
     println!("执行PostgreSQL查询: {}", tsquery);
 
-    let statement = format!(
-        "SELECT {0}.id, {0}.name, {0}.description, ts_rank({0}.tsv, to_tsquery($1)) AS rank
-        FROM {0}
-        WHERE {0}.tsv @@ to_tsquery($1)
-        ORDER BY rank DESC
-        LIMIT 200",
-        table_name
+    retrive_crates_with_tsquery(
+        client,
+        table_name,
+        query,
+        original_query,
+        &tsquery,
+        original_terms,
+        exclude,
+        config,
+        query_embedding,
+        corpus_stats,
+    )
+    .await
+}
+
+// 使用已生成的tsquery检索候选crate，不向标准输出打印
+//
+// 传入查询向量时，由数据库按 `similarity_metric` 计算相似度（如余弦相似度 `1 - (embedding <=> 查询向量)`）并填入 `vector_score`，
+// 没有嵌入向量的crate相似度为0；`original_terms` 非空时命中原始查询词的得分按
+// `original_terms_weight` 额外加权；`exclude` 中的crate（名称或id）在列表不长时由SQL直接排除，
+// 使其不占用候选数量上限。`tsquery` 有语法错误时改用 `plainto_tsquery(original_query)` 重新检索；
+// 使用BM25评分时全表统计缓存在 `corpus_stats` 中，未提供缓存时每次检索重新统计
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn retrive_crates_with_tsquery(
+    client: &PgClient,
+    table_name: &str,
+    query: &str,
+    original_query: &str,
+    tsquery: &str,
+    original_terms: &[String],
+    exclude: &[String],
+    config: &SearchConfig,
+    query_embedding: Option<&[f32]>,
+    corpus_stats: Option<&CorpusStatsCache>,
+) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+    let candidate_limit = config.candidate_limit as i64;
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![&tsquery, &candidate_limit];
+
+    // 设置了字段权重时，名称（A）和描述（B）的命中按各自权重计分
+    let weights = config
+        .field_weights
+        .map(|weights| format!("{}, ", weights.ts_rank_weights()))
+        .unwrap_or_default();
+
+    // 根据打分方式选择排序表达式，BM25在取回候选后于Rust中重新打分
+    let rank_of = |tsquery_call: &str| match config.keyword_scorer {
+        KeywordScorer::TsRank | KeywordScorer::Bm25 { .. } => {
+            format!("ts_rank({}{}.tsv, {})", weights, table_name, tsquery_call)
+        }
+        KeywordScorer::TsRankCd { norm } => format!(
+            "ts_rank_cd({}{}.tsv, {}, {})",
+            weights, table_name, tsquery_call, norm
+        ),
+    };
+
+    // 改写结果区分了核心关键词时，命中核心关键词的得分按 primary_keyword_boost 额外加权；
+    // 保留了原始查询词时，命中原始查询词的得分按 original_terms_weight 额外加权
+    let primary_tsquery = primary_tsquery(query, config);
+    let original_tsquery = match config.original_terms_weight {
+        Some(weight) if !original_terms.is_empty() => Some((
+            join_keywords(original_terms, false, config.tsquery_syntax),
+            weight,
+        )),
+        _ => None,
+    };
+    let mut boosted = Vec::new();
+    if let Some(primary) = &primary_tsquery {
+        params.push(primary);
+        boosted.push((config.primary_keyword_boost, params.len()));
+    }
+    if let Some((original, weight)) = &original_tsquery {
+        params.push(original);
+        boosted.push((*weight, params.len()));
+    }
+    let rank_expression = if boosted.is_empty() {
+        rank_of(&config.keyword_tsquery_call("$1"))
+    } else {
+        let mut numerator = rank_of(&config.keyword_tsquery_call("$1"));
+        let mut denominator = String::from("1");
+        for (weight, param) in &boosted {
+            numerator.push_str(&format!(
+                " + {} * {}",
+                weight,
+                rank_of(&config.keyword_tsquery_call(&format!("${}", param)))
+            ));
+            denominator.push_str(&format!(" + {}", weight));
+        }
+        format!("(({}) / ({}))::real", numerator, denominator)
+    };
+
+    let query_vector = query_embedding.map(|embedding| Vector::from(embedding.to_vec()));
+    let (vector_expression, join) =
+        push_vector_score(&mut params, table_name, query_vector.as_ref(), config);
+    let exclusion = push_exclusion(&mut params, table_name, &exclude);
+    let statement = keyword_retrieval_statement(
+        table_name,
+        &rank_expression,
+        &config.keyword_tsquery_call("$1"),
+        &vector_expression,
+        &join,
+        &exclusion,
+        config,
+    );
+
+    // tsquery语法错误时使用的语句：原始查询交给 plainto_tsquery 解析，不再区分核心关键词和原始查询词
+    let mut plain_params: Vec<&(dyn ToSql + Sync)> = vec![&original_query, &candidate_limit];
+    let plain_tsquery = config.tsquery_call("plainto_tsquery", "$1");
+    let (plain_vector_expression, plain_join) =
+        push_vector_score(&mut plain_params, table_name, query_vector.as_ref(), config);
+    let plain_exclusion = push_exclusion(&mut plain_params, table_name, &exclude);
+    let plain_statement = keyword_retrieval_statement(
+        table_name,
+        &rank_of(&plain_tsquery),
+        &plain_tsquery,
+        &plain_vector_expression,
+        &plain_join,
+        &plain_exclusion,
+        config,
     );
-    let rows = client.query(statement.as_str(), &[&tsquery]).await?;
+
+    let rows = query_keyword_statement(
+        client,
+        &statement,
+        &params,
+        Some((&plain_statement, &plain_params)),
+        config,
+    )
+    .await?;
     let mut recommend_crates = Vec::<RecommendCrate>::new();
+    let mut doc_lengths = Vec::with_capacity(rows.len());
 
     for row in rows.iter() {
         let id: Option<String> = row.get("id");
         let name: Option<String> = row.get("name");
         let description: Option<String> = row.get("description");
+        let version: Option<String> = row.get("version");
+        let downloads: Option<i64> = row.get("downloads");
+        let repository: Option<String> = row.get("repository");
+        let keywords: Option<Vec<String>> = row.get("keywords");
+        let updated_at: Option<DateTime<Utc>> = row.get("updated_at");
+        let metadata = parse_metadata(row.get("metadata"));
+        let popularity: Option<f64> = row.get("popularity");
         let rank: Option<f32> = row.get("rank");
+        let vector_score: Option<f32> = row.get("vector_score");
+        let doc_length: Option<i32> = row.get("doc_length");
+        doc_lengths.push(doc_length.unwrap_or(0) as f32);
 
         recommend_crates.push(RecommendCrate {
             id: id.unwrap_or_default(),
             name: name.unwrap_or_default(),
             description: description.unwrap_or_default(),
             rank: rank.unwrap_or(0.0),
-            vector_score: 0.0, // 初始化为0，稍后会更新
-            final_score: 0.0,  // 初始化为0，稍后会更新
+            vector_score: vector_score.unwrap_or(0.0), // 未由数据库计算时为0，稍后会更新
+            final_score: 0.0,                          // 初始化为0，稍后会更新
+            version,
+            downloads: downloads.unwrap_or(0),
+            repository,
+            keywords: keywords.unwrap_or_default(),
+            updated_at,
+            metadata,
+            popularity,
+            embedding: None,
         });
     }
 
+    if let KeywordScorer::Bm25 { k1, b } = config.keyword_scorer {
+        apply_bm25_scores(
+            client,
+            table_name,
+            query,
+            &mut recommend_crates,
+            &doc_lengths,
+            corpus_stats,
+            config,
+            k1,
+            b,
+        )
+        .await?;
+    }
+
     Ok(recommend_crates)
 }
 
-async fn transfer_query_to_tsquery(
+// 关键词检索语句，`tsquery_call` 为匹配条件中的tsquery表达式
+fn keyword_retrieval_statement(
+    table_name: &str,
+    rank_expression: &str,
+    tsquery_call: &str,
+    vector_expression: &str,
+    join: &str,
+    exclusion: &str,
+    config: &SearchConfig,
+) -> String {
+    format!(
+        "SELECT {0}.id, {0}.name, {0}.description, {0}.version, {0}.downloads, {0}.repository,
+        {0}.keywords, {3}, {5}, {6}, {1} AS rank, {2} AS vector_score,
+        length({0}.tsv) AS doc_length
+        FROM {0}{7}
+        WHERE {0}.tsv @@ {4}{8}
+        ORDER BY rank DESC
+        LIMIT $2",
+        table_name,
+        rank_expression,
+        vector_expression,
+        updated_at_column(table_name, config),
+        tsquery_call,
+        metadata_column(table_name, config),
+        popularity_column(table_name, config),
+        join,
+        exclusion
+    )
+}
+
+// 由数据库计算向量相似度的表达式和连接子句，向量存放在独立嵌入表时连接该表；
+// 传入查询向量时把向量追加到 `params`，否则相似度为0
+fn push_vector_score<'a>(
+    params: &mut Vec<&'a (dyn ToSql + Sync)>,
+    table_name: &str,
+    query_vector: Option<&'a Vector>,
+    config: &SearchConfig,
+) -> (String, String) {
+    match query_vector {
+        Some(vector) => {
+            params.push(vector);
+            (
+                format!(
+                    "COALESCE({}, 0)::real",
+                    config.similarity_metric.sql_score(
+                        &format!("{}.embedding", config.embedding_table_for(table_name)),
+                        &format!("${}", params.len())
+                    )
+                ),
+                config.embedding_join(table_name),
+            )
+        }
+        None => ("0::real".to_string(), String::new()),
+    }
+}
+
+// 排除列表不超过 MAX_SQL_EXCLUSIONS 时，把排除列表追加到 `params` 并返回按名称或id排除crate的SQL条件；
+// 排除列表为空或过长时返回空字符串，由重排序后的过滤排除
+fn push_exclusion<'a>(
+    params: &mut Vec<&'a (dyn ToSql + Sync)>,
+    table_name: &str,
+    exclude: &'a &'a [String],
+) -> String {
+    if exclude.is_empty() || exclude.len() > MAX_SQL_EXCLUSIONS {
+        return String::new();
+    }
+    params.push(exclude);
+    format!(
+        " AND {0}.id <> ALL(${1}) AND {0}.name <> ALL(${1})",
+        table_name,
+        params.len()
+    )
+}
+
+// 依次尝试配置的后备检索方式，返回第一个有结果的方式及其结果
+//
+// 使用原始查询而不是改写后的关键词，后备结果没有由数据库计算的向量得分；
+// `exclude` 与关键词检索相同，列表不长时在SQL中排除
+pub(crate) async fn retrive_crates_with_fallbacks(
+    client: &PgClient,
+    table_name: &str,
+    original_query: &str,
+    exclude: &[String],
+    config: &SearchConfig,
+) -> Result<Option<(RetrievalFallback, Vec<RecommendCrate>)>, Box<dyn std::error::Error>> {
+    if original_query.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let candidate_limit = config.candidate_limit as i64;
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![&original_query, &candidate_limit];
+    let exclusion = push_exclusion(&mut params, table_name, &exclude);
+    for &fallback in &config.retrieval_fallbacks {
+        let rows = match fallback {
+            RetrievalFallback::WebSearch => {
+                let statement = format!(
+                    "SELECT id, name, description, version, downloads, repository, keywords, {1}, {3}, {4},
+                    ts_rank(tsv, {2}) AS rank
+                    FROM {0}
+                    WHERE tsv @@ {2}{5}
+                    ORDER BY rank DESC
+                    LIMIT $2",
+                    table_name,
+                    updated_at_column(table_name, config),
+                    config.tsquery_call("websearch_to_tsquery", "$1"),
+                    metadata_column(table_name, config),
+                    popularity_column(table_name, config),
+                    exclusion
+                );
+                match client.query(statement.as_str(), &params).await {
+                    Ok(rows) => rows,
+                    Err(_) => {
+                        // 如果websearch_to_tsquery不可用，回退到plainto_tsquery
+                        let statement =
+                            statement.replace("websearch_to_tsquery", "plainto_tsquery");
+                        client.query(statement.as_str(), &params).await?
+                    }
+                }
+            }
+            RetrievalFallback::Trigram => {
+                let statement = format!(
+                    "SELECT id, name, description, version, downloads, repository, keywords, {1}, {2}, {3},
+                    GREATEST(similarity(name, $1), word_similarity($1, description))::real AS rank
+                    FROM {0}
+                    WHERE (name % $1 OR $1 <% description){4}
+                    ORDER BY rank DESC
+                    LIMIT $2",
+                    table_name,
+                    updated_at_column(table_name, config),
+                    metadata_column(table_name, config),
+                    popularity_column(table_name, config),
+                    exclusion
+                );
+                // 未安装pg_trgm时跳过该方式，不影响搜索
+                match client.query(statement.as_str(), &params).await {
+                    Ok(rows) => rows,
+                    Err(e) => {
+                        eprintln!("三元组相似度检索失败（是否已安装pg_trgm扩展？）: {}", e);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if !rows.is_empty() {
+            let crates = rows
+                .iter()
+                .map(|row| RecommendCrate {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    description: row
+                        .get::<_, Option<String>>("description")
+                        .unwrap_or_default(),
+                    rank: row.get::<_, Option<f32>>("rank").unwrap_or(0.0),
+                    version: row.get("version"),
+                    downloads: row.get::<_, Option<i64>>("downloads").unwrap_or(0),
+                    repository: row.get("repository"),
+                    keywords: row
+                        .get::<_, Option<Vec<String>>>("keywords")
+                        .unwrap_or_default(),
+                    updated_at: row.get("updated_at"),
+                    metadata: parse_metadata(row.get("metadata")),
+                    popularity: row.get("popularity"),
+                    ..Default::default()
+                })
+                .collect();
+            return Ok(Some((fallback, crates)));
+        }
+    }
+
+    Ok(None)
+}
+
+// 只有启用近期更新加分时才读取 `updated_at` 列，未启用时不要求表中存在该列
+fn updated_at_column(table_name: &str, config: &SearchConfig) -> String {
+    if config.recency_boost.is_some() {
+        format!("{}.updated_at", table_name)
+    } else {
+        "NULL::timestamptz AS updated_at".to_string()
+    }
+}
+
+// 配置了热度列时读取该列（转换为双精度），未配置或列名不合法时为NULL
+fn popularity_column(table_name: &str, config: &SearchConfig) -> String {
+    match &config.popularity {
+        Some(popularity) if is_sql_identifier(&popularity.column) => format!(
+            "{}.{}::double precision AS popularity",
+            table_name, popularity.column
+        ),
+        Some(popularity) => {
+            eprintln!("忽略不合法的热度列名: {}", popularity.column);
+            "NULL::double precision AS popularity".to_string()
+        }
+        None => "NULL::double precision AS popularity".to_string(),
+    }
+}
+
+// 把 metadata_columns 中的列合并为一个JSON对象读取，未配置时为NULL；不合法的列名被忽略
+fn metadata_column(table_name: &str, config: &SearchConfig) -> String {
+    let pairs: Vec<String> = config
+        .metadata_columns
+        .iter()
+        .filter(|column| {
+            let valid = is_sql_identifier(column);
+            if !valid {
+                eprintln!("忽略不合法的元数据列名: {}", column);
+            }
+            valid
+        })
+        .map(|column| format!("'{1}', {0}.{1}", table_name, column))
+        .collect();
+    if pairs.is_empty() {
+        "NULL::text AS metadata".to_string()
+    } else {
+        format!(
+            "jsonb_strip_nulls(jsonb_build_object({}))::text AS metadata",
+            pairs.join(", ")
+        )
+    }
+}
+
+// 解析 metadata_column 读取的JSON对象
+fn parse_metadata(metadata: Option<String>) -> HashMap<String, serde_json::Value> {
+    metadata
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+// 统计与检索条件相同（`tsv @@ to_tsquery`）的全部匹配crate数量，不受候选数量上限限制；
+// `tsquery` 有语法错误时与检索相同，改用 `plainto_tsquery(original_query)` 统计
+pub(crate) async fn count_matches(
+    client: &PgClient,
+    table_name: &str,
+    tsquery: &str,
+    original_query: &str,
+    exclude: &[String],
+    config: &SearchConfig,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let statement_for = |tsquery_call: &str, exclusion: &str| {
+        format!(
+            "SELECT COUNT(*) FROM {0} WHERE {0}.tsv @@ {1}{2}",
+            table_name, tsquery_call, exclusion
+        )
+    };
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![&tsquery];
+    let exclusion = push_exclusion(&mut params, table_name, &exclude);
+    let statement = statement_for(&config.keyword_tsquery_call("$1"), &exclusion);
+    let mut plain_params: Vec<&(dyn ToSql + Sync)> = vec![&original_query];
+    let plain_exclusion = push_exclusion(&mut plain_params, table_name, &exclude);
+    let plain_statement = statement_for(
+        &config.tsquery_call("plainto_tsquery", "$1"),
+        &plain_exclusion,
+    );
+
+    let rows = query_keyword_statement(
+        client,
+        &statement,
+        &params,
+        Some((&plain_statement, &plain_params)),
+        config,
+    )
+    .await?;
+    let count: i64 = rows.first().map(|row| row.get(0)).unwrap_or(0);
+    Ok(count as u64)
+}
+
+// 统计与检索条件相同（`tsv @@ to_tsquery`）的全部匹配crate在各分类和关键词下的数量
+//
+// 同一crate的分类与关键词合并去重后计数，每个值最多计一次；
+// `tsquery` 有语法错误时与检索相同，改用 `plainto_tsquery(original_query)` 统计
+pub(crate) async fn count_facets(
+    client: &PgClient,
+    table_name: &str,
+    tsquery: &str,
+    original_query: &str,
+    exclude: &[String],
+    config: &SearchConfig,
+) -> Result<HashMap<String, usize>, Box<dyn std::error::Error>> {
+    let statement_for = |tsquery_call: &str, exclusion: &str| {
+        format!(
+            "SELECT facet, COUNT(*) AS count FROM (
+                SELECT DISTINCT {0}.id, unnest(
+                    COALESCE({0}.categories, '{{}}') || COALESCE({0}.keywords, '{{}}')
+                ) AS facet
+                FROM {0}
+                WHERE {0}.tsv @@ {1}{2}
+            ) AS facets
+            GROUP BY facet",
+            table_name, tsquery_call, exclusion
+        )
+    };
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![&tsquery];
+    let exclusion = push_exclusion(&mut params, table_name, &exclude);
+    let statement = statement_for(&config.keyword_tsquery_call("$1"), &exclusion);
+    let mut plain_params: Vec<&(dyn ToSql + Sync)> = vec![&original_query];
+    let plain_exclusion = push_exclusion(&mut plain_params, table_name, &exclude);
+    let plain_statement = statement_for(
+        &config.tsquery_call("plainto_tsquery", "$1"),
+        &plain_exclusion,
+    );
+
+    let rows = query_keyword_statement(
+        client,
+        &statement,
+        &params,
+        Some((&plain_statement, &plain_params)),
+        config,
+    )
+    .await?;
+    let mut facets = HashMap::with_capacity(rows.len());
+    for row in rows {
+        let facet: String = row.get("facet");
+        let count: i64 = row.get("count");
+        facets.insert(facet, count as usize);
+    }
+    Ok(facets)
+}
+
+// 用BM25得分替换候选结果的关键词得分
+//
+// 文档总数、平均文档长度和各词的文档频率来自全表统计，词频和文档长度在Rust中
+// 对 name + description 分词后计算；查询词按前缀匹配，与tsquery的 `:*` 一致。
+// BM25得分没有上界，这里除以候选集中的最大值归一化到 [0, 1]，以便与向量得分加权
+#[allow(clippy::too_many_arguments)]
+async fn apply_bm25_scores(
+    client: &PgClient,
+    table_name: &str,
+    query: &str,
+    crates: &mut [RecommendCrate],
+    doc_lengths: &[f32],
+    corpus_stats: Option<&CorpusStatsCache>,
+    config: &SearchConfig,
+    k1: f32,
+    b: f32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let terms = query_terms(query, config.max_keywords);
+    if terms.is_empty() || crates.is_empty() {
+        return Ok(());
+    }
+
+    let stats = match corpus_stats.and_then(|cache| cache.read().unwrap().get(table_name).copied())
+    {
+        Some(stats) => stats,
+        None => {
+            let stats = fetch_corpus_stats(client, table_name).await?;
+            if let Some(cache) = corpus_stats {
+                cache.write().unwrap().insert(table_name.to_string(), stats);
+            }
+            stats
+        }
+    };
+    let avg_length = stats.avg_length.max(1.0);
+
+    // 一次查询取出所有查询词的文档频率，每个词的计数仍可使用tsv索引
+    let term_queries: Vec<String> = terms
+        .iter()
+        .map(|term| match config.tsquery_syntax {
+            TsQuerySyntax::ToTsQuery => format!("{}:*", term),
+            TsQuerySyntax::WebSearch => term.clone(),
+        })
+        .collect();
+    let df_statement = format!(
+        "SELECT term_query, (SELECT COUNT(*) FROM {0} WHERE {0}.tsv @@ {1}) AS doc_freq
+        FROM unnest($1::text[]) AS term_query",
+        table_name,
+        config.keyword_tsquery_call("term_query")
+    );
+    let rows =
+        query_keyword_statement(client, &df_statement, &[&term_queries], None, config).await?;
+    let doc_freqs: HashMap<String, i64> = rows
+        .iter()
+        .map(|row| (row.get("term_query"), row.get("doc_freq")))
+        .collect();
+    let mut idf = HashMap::new();
+    for (term, term_query) in terms.iter().zip(&term_queries) {
+        let n = stats.total_docs as f32;
+        let df = doc_freqs.get(term_query).copied().unwrap_or(0) as f32;
+        idf.insert(term.clone(), ((n - df + 0.5) / (df + 0.5) + 1.0).ln());
+    }
+
+    let mut max_score = 0.0f32;
+    // 文档长度与平均长度同样取tsv中的词位数量，词频按名称和描述中的词计算
+    for (crate_item, &doc_length) in crates.iter_mut().zip(doc_lengths) {
+        let document = format!("{} {}", crate_item.name, crate_item.description).to_lowercase();
+        let tokens: Vec<&str> = document
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        let mut score = 0.0;
+        for term in &terms {
+            let term_freq = tokens
+                .iter()
+                .filter(|token| token.starts_with(term.as_str()))
+                .count() as f32;
+            if term_freq == 0.0 {
+                continue;
+            }
+            let norm = k1 * (1.0 - b + b * doc_length / avg_length);
+            score += idf[term] * term_freq * (k1 + 1.0) / (term_freq + norm);
+        }
+
+        crate_item.rank = score;
+        max_score = max_score.max(score);
+    }
+
+    if max_score > 0.0 {
+        for crate_item in crates.iter_mut() {
+            crate_item.rank /= max_score;
+        }
+    }
+
+    Ok(())
+}
+
+// 全表统计：文档总数和平均文档长度
+async fn fetch_corpus_stats(
+    client: &PgClient,
+    table_name: &str,
+) -> Result<CorpusStats, Box<dyn std::error::Error>> {
+    let statement = format!(
+        "SELECT COUNT(*) AS total, COALESCE(AVG(length(tsv)), 0)::real AS avg_length FROM {}",
+        table_name
+    );
+    let row = client.query_one(statement.as_str(), &[]).await?;
+    Ok(CorpusStats {
+        total_docs: row.get("total"),
+        avg_length: row.get("avg_length"),
+    })
+}
+
+// 从逗号分隔的关键词中取出参与BM25计算的单个词
+fn query_terms(keywords_str: &str, max_keywords: usize) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for kw in query_keywords(keywords_str, max_keywords) {
+        for word in kw.split(|c: char| !c.is_alphanumeric()) {
+            let word = word.trim().to_lowercase();
+            if !word.is_empty() && !terms.contains(&word) {
+                terms.push(word);
+            }
+        }
+    }
+    terms
+}
+
+// 从逗号分隔的改写结果中取出参与检索的关键词
+//
+// 改写结果可以用分号分为两级：分号之前为核心关键词，之后为同义词和相关词，
+// 核心关键词排在前面优先占用名额。
+// 空关键词（如末尾多余的逗号）和重复关键词不占用 `max_keywords` 名额
+pub(crate) fn query_keywords(keywords_str: &str, max_keywords: usize) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+    for kw in keywords_str.split([',', ';', '；']) {
+        if keywords.len() >= max_keywords {
+            break;
+        }
+        let kw = kw
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        if !kw.is_empty() && !keywords.contains(&kw) {
+            keywords.push(kw);
+        }
+    }
+    keywords
+}
+
+// 取出分号之前的核心关键词，改写结果没有分级时返回None
+pub(crate) fn primary_keywords(keywords_str: &str, max_keywords: usize) -> Option<Vec<String>> {
+    let (primary, _) = keywords_str.split_once([';', '；'])?;
+    let keywords = query_keywords(primary, max_keywords);
+    if keywords.is_empty() {
+        None
+    } else {
+        Some(keywords)
+    }
+}
+
+// 把原始查询词并入改写结果：放在核心关键词之后、同义词之前，使其优先占用 `max_keywords` 名额；
+// 改写结果没有分级时，改写的关键词整体作为核心关键词
+pub(crate) fn merge_original_terms(rewritten: &str, original_terms: &[String]) -> String {
+    if original_terms.is_empty() {
+        return rewritten.to_string();
+    }
+    let original = original_terms.join(", ");
+    match rewritten.split_once([';', '；']) {
+        Some((primary, synonyms)) => format!("{}; {}, {}", primary, original, synonyms),
+        None => format!("{}; {}", rewritten, original),
+    }
+}
+
+// 只包含核心关键词的tsquery，用于给命中核心关键词的crate加权
+pub(crate) fn primary_tsquery(keywords_str: &str, config: &SearchConfig) -> Option<String> {
+    primary_keywords(keywords_str, config.max_keywords)
+        .map(|keywords| join_keywords(&keywords, false, config.tsquery_syntax))
+}
+
+pub(crate) async fn transfer_query_to_tsquery(
     keywords_str: &str,
+    config: &SearchConfig,
 ) -> Result<String, Box<dyn std::error::Error>> {
     // 处理关键词
-    let keywords: Vec<&str> = keywords_str.split(',').collect();
-    let mut processed_terms = Vec::new();
+    let keywords = query_keywords(keywords_str, config.max_keywords);
+    let syntax = config.tsquery_syntax;
+
+    Ok(match config.term_combination {
+        TermCombination::Or => join_keywords(&keywords, false, syntax),
+        TermCombination::And => join_keywords(&keywords, true, syntax),
+        // 核心关键词必须全部命中，或者命中任一同义词；未分级时所有关键词都视为核心关键词
+        TermCombination::PrimaryAnd => match primary_keywords(keywords_str, config.max_keywords) {
+            Some(primary) => {
+                // 网页搜索语法不支持括号，但其中AND的优先级同样高于or
+                let required = match syntax {
+                    TsQuerySyntax::ToTsQuery => format!("({})", build_tsquery(&primary, " & ")),
+                    TsQuerySyntax::WebSearch => build_websearch_query(&primary, " "),
+                };
+                let synonyms: Vec<String> = keywords
+                    .into_iter()
+                    .filter(|kw| !primary.contains(kw))
+                    .collect();
+                if synonyms.is_empty() {
+                    required
+                } else {
+                    match syntax {
+                        TsQuerySyntax::ToTsQuery => {
+                            format!("{} | {}", required, build_tsquery(&synonyms, " | "))
+                        }
+                        TsQuerySyntax::WebSearch => format!(
+                            "{} or {}",
+                            required,
+                            build_websearch_query(&synonyms, " or ")
+                        ),
+                    }
+                }
+            }
+            None => join_keywords(&keywords, true, syntax),
+        },
+    })
+}
+
+// 按 `tsquery_syntax` 连接各关键词，`all` 为true时要求全部命中，否则命中任一即可
+fn join_keywords(keywords: &[String], all: bool, syntax: TsQuerySyntax) -> String {
+    match (syntax, all) {
+        (TsQuerySyntax::ToTsQuery, true) => build_tsquery(keywords, " & "),
+        (TsQuerySyntax::ToTsQuery, false) => build_tsquery(keywords, " | "),
+        (TsQuerySyntax::WebSearch, true) => build_websearch_query(keywords, " "),
+        (TsQuerySyntax::WebSearch, false) => build_websearch_query(keywords, " or "),
+    }
+}
 
-    for kw in keywords.iter().take(6) {
-        // 限制为前6个关键词以提高性能
-        let term = kw.trim().to_lowercase();
+// 用 ` ` （AND）或 ` or ` 连接各关键词，生成交给 websearch_to_tsquery 解析的文本
+//
+// 网页搜索语法中多个词之间默认为AND且优先级高于or，多词关键词不需要额外处理，
+// 例如 "http client" 和 "reqwest" 得到 `http client or reqwest`；
+// 关键词中的引号会被视为短语语法，这里去掉以免与其他关键词配对
+fn build_websearch_query(keywords: &[String], operator: &str) -> String {
+    keywords
+        .iter()
+        .map(|kw| {
+            kw.replace('"', " ")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|kw| !kw.is_empty())
+        .collect::<Vec<_>>()
+        .join(operator)
+}
+
+// 执行使用 `keyword_tsquery_call` 生成的语句
+//
+// 使用网页搜索语法时，websearch_to_tsquery 不可用（PostgreSQL 11 之前）则回退到 plainto_tsquery；
+// 拼接生成的tsquery语法错误时（如关键词中含有括号、冒号），执行调用方另外构建的 `plain_fallback`
+// 语句（以 plainto_tsquery 解析原始查询，对任意文本都不会报错），没有提供时返回原错误
+async fn query_keyword_statement(
+    client: &PgClient,
+    statement: &str,
+    params: &[&(dyn ToSql + Sync)],
+    plain_fallback: Option<(&str, &[&(dyn ToSql + Sync)])>,
+    config: &SearchConfig,
+) -> Result<Vec<Row>, tokio_postgres::Error> {
+    match client.query(statement, params).await {
+        Ok(rows) => Ok(rows),
+        // 只在函数不存在时回退，连接断开等其他错误原样返回，保留 `SearchError::is_retriable` 的判断
+        Err(e)
+            if config.tsquery_syntax == TsQuerySyntax::WebSearch
+                && e.code() == Some(&SqlState::UNDEFINED_FUNCTION) =>
+        {
+            eprintln!("websearch_to_tsquery 不可用，回退到 plainto_tsquery: {}", e);
+            let statement = statement.replace("websearch_to_tsquery", "plainto_tsquery");
+            client.query(statement.as_str(), params).await
+        }
+        Err(e) => match plain_fallback {
+            Some((plain_statement, plain_params)) if e.code() == Some(&SqlState::SYNTAX_ERROR) => {
+                eprintln!("tsquery 语法错误，改用 plainto_tsquery 检索原始查询: {}", e);
+                client.query(plain_statement, plain_params).await
+            }
+            _ => Err(e),
+        },
+    }
+}
+
+// 用指定的操作符（` | ` 或 ` & `）连接各关键词
+//
+// tsquery中 `&` 的优先级高于 `|`，关键词内部的 `&` 不需要加括号；
+// 与其他部分用 `|` 组合的 `&` 连接结果由调用方加括号
+fn build_tsquery(keywords: &[String], operator: &str) -> String {
+    let mut processed_terms = Vec::new();
 
+    for term in keywords {
         // 如果关键词包含空格，则将空格替换为&（AND操作符）
         // 例如："http client" => "http & client"
         let processed_term = term.replace(" ", " & ");
@@ -60,7 +867,5 @@ async fn transfer_query_to_tsquery(
         processed_terms.push(format!("{}:*", processed_term));
     }
 
-    // 使用OR操作符连接所有处理后的术语
-    let tsquery = processed_terms.join(" | ");
-    Ok(tsquery)
+    processed_terms.join(operator)
 }
@@ -1,208 +1,414 @@
-use crate::search::utils::{basic_keyword_extraction, Message, RequestBody, ResponseBody};
-use reqwest::Client;
-use std::env;
-
-// 处理查询，判断是否为自然语言并相应地处理
-pub async fn process_query(query: &str) -> String {
-    // 检测是否为自然语言查询
-    let is_natural_language = is_natural_language_query(query);
-
-    if is_natural_language {
-        println!("检测到自然语言查询: {}", query);
-        // 如果是自然语言查询，先提取关键词
-        match extract_keywords_from_query(query).await {
-            Ok(keywords) => {
-                println!("从自然语言中提取的关键词: {}", keywords);
-                keywords
-            }
-            Err(e) => {
-                eprintln!("提取关键词失败: {}", e);
-                query.to_string() // 提取失败则使用原始查询
-            }
+use crate::search::config::{LlmConfig, ResultFilter};
+use crate::search::core::{RecommendCrate, SearchOutcome};
+use crate::search::lang::{classify_query, contains_chinese, extract_code_identifiers, QueryKind};
+use crate::search::utils::{
+    basic_keyword_extraction, basic_keyword_extraction_with_ngrams, Message, RequestBody,
+    ResponseBody,
+};
+use serde::{Deserialize, Serialize};
+
+/// crates.io的分类（slug），结构化改写只保留其中的分类
+pub const CRATES_IO_CATEGORIES: &[&str] = &[
+    "accessibility",
+    "aerospace",
+    "algorithms",
+    "api-bindings",
+    "asynchronous",
+    "authentication",
+    "caching",
+    "command-line-interface",
+    "command-line-utilities",
+    "compilers",
+    "compression",
+    "computer-vision",
+    "concurrency",
+    "config",
+    "cryptography",
+    "cryptography::cryptocurrencies",
+    "data-structures",
+    "database",
+    "database-implementations",
+    "date-and-time",
+    "development-tools",
+    "development-tools::build-utils",
+    "development-tools::debugging",
+    "development-tools::procedural-macro-helpers",
+    "development-tools::testing",
+    "email",
+    "embedded",
+    "emulators",
+    "encoding",
+    "external-ffi-bindings",
+    "filesystem",
+    "finance",
+    "game-development",
+    "game-engines",
+    "games",
+    "graphics",
+    "gui",
+    "hardware-support",
+    "internationalization",
+    "localization",
+    "mathematics",
+    "memory-management",
+    "multimedia",
+    "multimedia::audio",
+    "multimedia::images",
+    "multimedia::video",
+    "network-programming",
+    "no-std",
+    "os",
+    "parser-implementations",
+    "parsing",
+    "rendering",
+    "rust-patterns",
+    "science",
+    "simulation",
+    "template-engine",
+    "text-editors",
+    "text-processing",
+    "value-formatting",
+    "virtualization",
+    "visualization",
+    "wasm",
+    "web-programming",
+    "web-programming::http-client",
+    "web-programming::http-server",
+    "web-programming::websocket",
+];
+
+/// 结构化的查询改写结果：搜索关键词及预测的crates.io分类
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StructuredRewrite {
+    pub keywords: Vec<String>,
+    // 只包含 CRATES_IO_CATEGORIES 中的分类，LLM不可用或回复无法解析时为空
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+impl StructuredRewrite {
+    /// 按预测分类过滤结果的条件，可用作 `SearchOptions::filter`；没有预测分类时返回None
+    ///
+    /// 分类从 `RecommendCrate::metadata` 的 `categories` 中读取，需要在
+    /// `SearchConfig::metadata_columns` 中加入 `categories` 列，否则所有crate都会被过滤掉。
+    /// crate属于任一预测分类或其子分类即保留
+    pub fn category_filter(&self) -> Option<ResultFilter> {
+        if self.categories.is_empty() {
+            return None;
         }
-    } else {
-        query.to_string() // 如果是常规查询，直接使用原始查询
+        let categories = self.categories.clone();
+        Some(ResultFilter::new(move |crate_item: &RecommendCrate| {
+            let Some(serde_json::Value::Array(values)) = crate_item.metadata.get("categories")
+            else {
+                return false;
+            };
+            values
+                .iter()
+                .filter_map(|value| value.as_str())
+                .any(|slug| {
+                    categories.iter().any(|category| {
+                        slug == category
+                            || slug
+                                .strip_prefix(category.as_str())
+                                .is_some_and(|rest| rest.starts_with("::"))
+                    })
+                })
+        }))
     }
 }
 
-// 检测查询是否为自然语言句子，支持中英文
-pub fn is_natural_language_query(query: &str) -> bool {
-    // 中文特定检测
-    let contains_chinese = query.chars().any(|c| '\u{4e00}' <= c && c <= '\u{9fff}');
-
-    // 中文自然语言特征检测
-    let chinese_question_markers = [
-        "吗",
-        "？",
-        "如何",
-        "怎么",
-        "什么",
-        "哪个",
-        "为什么",
-        "谁",
-        "何时",
-        "在哪",
-    ];
-    let contains_chinese_question = chinese_question_markers
-        .iter()
-        .any(|&marker| query.contains(marker));
-
-    // 中文句子通常更短，降低中文单词数量阈值
-    let word_count_threshold = if contains_chinese { 2 } else { 3 };
-    let word_count = query.split_whitespace().count();
-
-    // 英文检测逻辑
-    let contains_question_mark = query.contains('?') || query.contains('？');
-    let contains_period = query.contains('.') || query.contains('。');
-    let contains_common_question_words = query.to_lowercase().split_whitespace().any(|word| {
-        [
-            "how", "what", "which", "where", "who", "why", "can", "could", "help", "find", "need",
-            "want", "looking",
-        ]
-        .contains(&word)
-    });
-
-    // 综合判断标准
-    word_count > word_count_threshold
-        || contains_question_mark
-        || contains_period
-        || contains_common_question_words
-        || (contains_chinese && (contains_chinese_question || word_count > 1))
-}
-
-// 从自然语言查询中提取关键词
+// 从自然语言查询中提取关键词（使用环境变量中的LLM配置）
 pub async fn extract_keywords_from_query(
     query: &str,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    // 检查是否配置了OpenAI API密钥
-    if let Ok(api_key) = env::var("OPENAI_API_KEY") {
-        if !api_key.is_empty() {
-            let client = Client::new();
-            let open_ai_chat_url = env::var("OPEN_AI_CHAT_URL")
-                .unwrap_or_else(|_| "https://api.openai.com/v1/chat/completions".to_string());
-
-            // 检测查询语言，确定使用中文还是英文提示
-            let is_chinese_query = query.chars().any(|c| '\u{4e00}' <= c && c <= '\u{9fff}');
-
-            // 根据查询语言选择合适的系统提示
-            let system_prompt = if is_chinese_query {
-                "你是一个专门从中文自然语言查询中提取Rust软件包关键词的专家。请分析用户的问题，识别与Rust生态系统相关的核心概念和功能需求。返回逗号分隔的关键词列表，关键词可以是英文技术术语或中文概念。技术术语优先使用英文。"
-            } else {
-                "你是一个从自然语言查询中提取Rust软件包关键词的专家。请分析用户的问题，识别与Rust生态系统相关的核心概念和功能需求。仅返回逗号分隔的英文关键词列表。"
-            };
-
-            // 构建消息 - 专门针对从自然语言中提取关键词
-            let messages = vec![
-                Message {
-                    role: "system".to_string(),
-                    content: system_prompt.to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: format!(
-                        "从以下查询中提取用于搜索Rust包的关键词（返回逗号分隔的列表）: {}",
-                        query
-                    ),
-                },
-            ];
-
-            let request_body = RequestBody {
-                model: "gpt-3.5-turbo".to_string(),
-                messages,
-                temperature: 0.3,
-                max_tokens: 100,
-            };
+    extract_keywords_with_config(query, &LlmConfig::from_env()).await
+}
 
-            match client
-                .post(&open_ai_chat_url)
-                .header("Content-Type", "application/json")
-                .header("Authorization", format!("Bearer {}", api_key))
-                .json(&request_body)
-                .send()
-                .await
-            {
-                Ok(response) => {
-                    if let Ok(response_body) = response.json::<ResponseBody>().await {
-                        if !response_body.choices.is_empty() {
-                            return Ok(response_body.choices[0].message.content.trim().to_string());
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("访问OpenAI API提取关键词失败: {}", e);
-                }
-            }
-        }
+// 使用指定的LLM配置从自然语言查询中提取关键词
+pub async fn extract_keywords_with_config(
+    query: &str,
+    llm_config: &LlmConfig,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match extract_keywords_with_llm(query, llm_config).await {
+        Some(Ok(keywords)) => return Ok(keywords),
+        Some(Err(e)) => eprintln!("访问OpenAI API提取关键词失败: {}", e),
+        None => {}
     }
 
     // 后备方案：使用简单的关键词提取
     Ok(basic_keyword_extraction(query))
 }
 
+// 改写查询（使用环境变量中的LLM配置）
 pub async fn rewrite_query(query: &str) -> Result<String, Box<dyn std::error::Error>> {
-    // 检查是否配置了OpenAI API密钥
-    if let Ok(api_key) = env::var("OPENAI_API_KEY") {
-        if !api_key.is_empty() {
-            let client = Client::new();
-            // 从环境变量获取自定义API端点
-            let open_ai_chat_url = env::var("OPEN_AI_CHAT_URL")
-                .unwrap_or_else(|_| "https://api.openai.com/v1/chat/completions".to_string());
-
-            // 检测查询语言
-            let is_chinese_query = query.chars().any(|c| '\u{4e00}' <= c && c <= '\u{9fff}');
-
-            // 根据查询语言选择合适的系统提示
-            let system_prompt = if is_chinese_query {
-                "你是一个专门改写Rust软件包查询的助手，精通中英文。请分析用户的中文输入并生成适合在crates.io搜索引擎中使用的关键词。将输入转换为相关技术术语和同义词的列表。技术术语优先使用英文。例如，'HTTP客户端'应生成'http client, reqwest, http request, web client'等。返回逗号分隔的关键词列表，不要添加解释。"
-            } else {
-                "你是一个专门改写Rust软件包查询的助手。分析输入并生成适合在crates.io搜索引擎中使用的关键词。无论输入是关键词还是自然语言问题，都将其转换为相关技术术语和同义词的列表。返回逗号分隔的英文关键词列表，不要添加解释。"
-            };
+    rewrite_query_with_config(query, &LlmConfig::from_env()).await
+}
 
-            // 构建消息
-            let messages = vec![
-                Message {
-                    role: "system".to_string(),
-                    content: system_prompt.to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: format!("生成以下内容的Rust包关键词列表（以逗号分隔）: {}", query),
-                },
-            ];
-
-            let request_body = RequestBody {
-                model: "gpt-3.5-turbo".to_string(),
-                messages,
-                temperature: 0.3,
-                max_tokens: 150,
-            };
+// 使用指定的LLM配置改写查询
+pub async fn rewrite_query_with_config(
+    query: &str,
+    llm_config: &LlmConfig,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match rewrite_with_llm(query, llm_config).await {
+        Some(Ok(rewritten)) => return Ok(rewritten),
+        Some(Err(e)) => eprintln!("访问OpenAI API失败: {}", e),
+        None => {}
+    }
+
+    // 后备方案：简单的查询增强
+    Ok(basic_query_enhancement(query))
+}
+
+/// 结构化改写查询（使用环境变量中的LLM配置），见 `rewrite_query_structured_with_config`
+pub async fn rewrite_query_structured(
+    query: &str,
+) -> Result<StructuredRewrite, Box<dyn std::error::Error>> {
+    rewrite_query_structured_with_config(query, &LlmConfig::from_env()).await
+}
+
+/// 使用指定的LLM配置结构化改写查询，同时返回关键词和预测的crates.io分类
+///
+/// 要求LLM以JSON回复 `{"keywords": [...], "categories": [...]}`，不在已知分类中的分类被丢弃；
+/// 回复无法解析为JSON时把回复按逗号和分号拆分为关键词，不返回分类；
+/// 未启用LLM或请求失败时使用基础的查询增强，同样不返回分类
+pub async fn rewrite_query_structured_with_config(
+    query: &str,
+    llm_config: &LlmConfig,
+) -> Result<StructuredRewrite, Box<dyn std::error::Error>> {
+    match rewrite_structured_with_llm(query, llm_config).await {
+        Some(Ok(content)) => {
+            return Ok(parse_structured_rewrite(&content).unwrap_or_else(|| {
+                eprintln!("无法解析LLM返回的结构化改写结果，只使用关键词: {}", content);
+                StructuredRewrite {
+                    keywords: split_keywords(&content),
+                    categories: Vec::new(),
+                }
+            }));
+        }
+        Some(Err(e)) => eprintln!("访问OpenAI API失败: {}", e),
+        None => {}
+    }
+
+    // 后备方案：简单的查询增强
+    Ok(StructuredRewrite {
+        keywords: split_keywords(&basic_query_enhancement(query)),
+        categories: Vec::new(),
+    })
+}
+
+// 解析LLM回复中的JSON对象（允许包在代码块或说明文字中），过滤未知分类；没有关键词时视为解析失败
+fn parse_structured_rewrite(content: &str) -> Option<StructuredRewrite> {
+    let start = content.find('{')?;
+    let end = content.rfind('}')?;
+    let mut rewrite: StructuredRewrite = serde_json::from_str(content.get(start..=end)?).ok()?;
+
+    rewrite.keywords = rewrite
+        .keywords
+        .iter()
+        .map(|keyword| keyword.trim().to_string())
+        .filter(|keyword| !keyword.is_empty())
+        .collect();
+    if rewrite.keywords.is_empty() {
+        return None;
+    }
+
+    let mut categories = Vec::new();
+    for category in &rewrite.categories {
+        let category = category.trim().to_lowercase();
+        if CRATES_IO_CATEGORIES.contains(&category.as_str()) && !categories.contains(&category) {
+            categories.push(category);
+        }
+    }
+    rewrite.categories = categories;
+    Some(rewrite)
+}
+
+// 把逗号或分号分隔的关键词列表拆分为关键词
+fn split_keywords(text: &str) -> Vec<String> {
+    text.split([',', ';', '，', '；'])
+        .map(|keyword| keyword.trim().to_string())
+        .filter(|keyword| !keyword.is_empty())
+        .collect()
+}
 
-            // 发送请求
-            match client
-                .post(&open_ai_chat_url)
-                .header("Content-Type", "application/json")
-                .header("Authorization", format!("Bearer {}", api_key))
-                .json(&request_body)
-                .send()
-                .await
-            {
-                Ok(response) => {
-                    // 解析响应
-                    if let Ok(response_body) = response.json::<ResponseBody>().await {
-                        if !response_body.choices.is_empty() {
-                            return Ok(response_body.choices[0].message.content.trim().to_string());
-                        }
-                    }
+// 处理并改写查询，同时记录是否实际使用了LLM改写以及降级原因
+//
+// 未启用LLM或未配置密钥属于主动选择，不计入降级原因；
+// 退回基本关键词提取时生成最多 `max_ngram` 个单词的词组
+pub(crate) async fn prepare_query(
+    query: &str,
+    llm_config: &LlmConfig,
+    max_ngram: usize,
+    outcome: &mut SearchOutcome,
+) -> String {
+    let processed_query = match classify_query(query) {
+        QueryKind::CodeOrError => {
+            println!("检测到代码或错误信息: {}", query);
+            let keywords = extract_code_keywords(query, max_ngram);
+            println!("从代码中提取的标识符: {}", keywords);
+            keywords
+        }
+        QueryKind::NaturalLanguage => {
+            println!("检测到自然语言查询: {}", query);
+            match extract_keywords_with_llm(query, llm_config).await {
+                Some(Ok(keywords)) => {
+                    println!("从自然语言中提取的关键词: {}", keywords);
+                    keywords
                 }
-                Err(e) => {
-                    eprintln!("访问OpenAI API失败: {}", e);
+                Some(Err(e)) => {
+                    eprintln!("提取关键词失败: {}", e);
+                    outcome
+                        .fallback_reasons
+                        .push(format!("LLM关键词提取失败: {}", e));
+                    basic_keyword_extraction_with_ngrams(query, max_ngram)
                 }
+                None => basic_keyword_extraction_with_ngrams(query, max_ngram),
             }
         }
+        QueryKind::Keyword => query.to_string(),
+    };
+
+    // 使用处理后的查询进行改写
+    match rewrite_with_llm(&processed_query, llm_config).await {
+        Some(Ok(rewritten)) => {
+            outcome.used_llm_rewrite = true;
+            rewritten
+        }
+        Some(Err(e)) => {
+            eprintln!("查询改写失败: {}", e);
+            outcome
+                .fallback_reasons
+                .push(format!("LLM查询改写失败: {}", e));
+            basic_query_enhancement(&processed_query)
+        }
+        None => basic_query_enhancement(&processed_query),
     }
+}
 
-    // 后备方案：简单的查询增强
-    Ok(basic_query_enhancement(query))
+// 从代码片段或错误信息中提取的标识符，逗号分隔；提取不到时退回基本关键词提取
+pub(crate) fn extract_code_keywords(query: &str, max_ngram: usize) -> String {
+    let identifiers = extract_code_identifiers(query);
+    if identifiers.is_empty() {
+        basic_keyword_extraction_with_ngrams(query, max_ngram)
+    } else {
+        identifiers.join(", ")
+    }
+}
+
+// 调用LLM提取关键词，未启用LLM时返回None
+pub(crate) async fn extract_keywords_with_llm(
+    query: &str,
+    llm_config: &LlmConfig,
+) -> Option<Result<String, Box<dyn std::error::Error>>> {
+    // 检查是否配置了OpenAI API密钥
+    let api_key = llm_config.active_api_key()?;
+
+    // 根据查询语言选择合适的系统提示
+    let system_prompt = if contains_chinese(query) {
+        "你是一个专门从中文自然语言查询中提取Rust软件包关键词的专家。请分析用户的问题，识别与Rust生态系统相关的核心概念和功能需求。返回逗号分隔的关键词列表，关键词可以是英文技术术语或中文概念。技术术语优先使用英文。"
+    } else {
+        "你是一个从自然语言查询中提取Rust软件包关键词的专家。请分析用户的问题，识别与Rust生态系统相关的核心概念和功能需求。仅返回逗号分隔的英文关键词列表。"
+    };
+
+    // 专门针对从自然语言中提取关键词
+    let user_prompt = format!(
+        "从以下查询中提取用于搜索Rust包的关键词（返回逗号分隔的列表）: {}",
+        query
+    );
+
+    Some(chat_completion(llm_config, api_key, system_prompt, user_prompt, 100).await)
+}
+
+// 调用LLM改写查询，未启用LLM时返回None
+async fn rewrite_with_llm(
+    query: &str,
+    llm_config: &LlmConfig,
+) -> Option<Result<String, Box<dyn std::error::Error>>> {
+    // 检查是否配置了OpenAI API密钥
+    let api_key = llm_config.active_api_key()?;
+
+    // 根据查询语言选择合适的系统提示
+    let system_prompt = if contains_chinese(query) {
+        "你是一个专门改写Rust软件包查询的助手，精通中英文。请分析用户的中文输入并生成适合在crates.io搜索引擎中使用的关键词。将输入转换为相关技术术语和同义词的列表。技术术语优先使用英文。先列出最能代表用户需求的核心关键词，再用分号与同义词和相关词分隔。例如，'HTTP客户端'应生成'http client, reqwest; http request, web client'。返回这种格式的关键词列表，不要添加解释。"
+    } else {
+        "你是一个专门改写Rust软件包查询的助手。分析输入并生成适合在crates.io搜索引擎中使用的关键词。无论输入是关键词还是自然语言问题，都将其转换为相关技术术语和同义词的列表。先列出最能代表用户需求的核心关键词，再用分号与同义词和相关词分隔，例如'http client, reqwest; http request, web client'。返回这种格式的英文关键词列表，不要添加解释。"
+    };
+
+    let user_prompt = format!(
+        "生成以下内容的Rust包关键词列表（逗号分隔，核心关键词与同义词之间用分号分隔）: {}",
+        query
+    );
+
+    Some(chat_completion(llm_config, api_key, system_prompt, user_prompt, 150).await)
+}
+
+// 调用LLM同时生成关键词和分类（JSON），未启用LLM时返回None
+async fn rewrite_structured_with_llm(
+    query: &str,
+    llm_config: &LlmConfig,
+) -> Option<Result<String, Box<dyn std::error::Error>>> {
+    let api_key = llm_config.active_api_key()?;
+
+    let system_prompt = format!(
+        "你是一个专门改写Rust软件包查询的助手，精通中英文。分析输入并生成适合在crates.io搜索引擎中使用的英文关键词（相关技术术语和同义词），\
+        同时从以下crates.io分类中选出最符合用户需求的0到3个分类: {}。\
+        只返回JSON对象，格式为 {{\"keywords\": [\"关键词\"], \"categories\": [\"分类\"]}}，不要添加解释。",
+        CRATES_IO_CATEGORIES.join(", ")
+    );
+    let user_prompt = format!("生成以下内容的Rust包关键词和分类: {}", query);
+
+    Some(chat_completion(llm_config, api_key, &system_prompt, user_prompt, 200).await)
+}
+
+// 发送Chat Completions请求，返回第一条回复的内容；`max_tokens` 为配置未指定时的默认值
+async fn chat_completion(
+    llm_config: &LlmConfig,
+    api_key: &str,
+    system_prompt: &str,
+    user_prompt: String,
+    max_tokens: u32,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let client = llm_config.http.build_client();
+
+    // 构建消息
+    let messages = vec![
+        Message {
+            role: "system".to_string(),
+            content: system_prompt.to_string(),
+        },
+        Message {
+            role: "user".to_string(),
+            content: user_prompt,
+        },
+    ];
+
+    let request_body = RequestBody {
+        model: llm_config.model.clone(),
+        messages,
+        temperature: llm_config.temperature,
+        max_tokens: llm_config.max_tokens.unwrap_or(max_tokens),
+        seed: llm_config.seed,
+    };
+
+    // 发送请求
+    let request = client
+        .post(llm_config.chat_endpoint())
+        .header("Content-Type", "application/json");
+    let response_body = llm_config
+        .flavor
+        .authorize(request, api_key)
+        .json(&request_body)
+        .send()
+        .await?
+        .json::<ResponseBody>()
+        .await?;
+
+    // 解析响应
+    response_body
+        .choices
+        .first()
+        .map(|choice| choice.message.content.trim().to_string())
+        .ok_or_else(|| "LLM未返回任何结果".into())
 }
 
 pub fn basic_query_enhancement(query: &str) -> String {
@@ -210,7 +416,7 @@ pub fn basic_query_enhancement(query: &str) -> String {
     let query = query.trim().to_lowercase();
 
     // 对于中文查询，直接返回，不进行停用词处理
-    if query.chars().any(|c| '\u{4e00}' <= c && c <= '\u{9fff}') {
+    if contains_chinese(&query) {
         return query;
     }
 
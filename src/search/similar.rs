@@ -0,0 +1,138 @@
+use crate::search::core::{RecommendCrate, SearchModule};
+use crate::search::embedder::{embedding_text, subtract_negative_examples};
+use crate::search::error::SearchError;
+use crate::search::utils::validate_identifier;
+use pgvector::Vector;
+use tokio_postgres::Row;
+
+impl SearchModule<'_> {
+    /// 查找与指定crate语义相近的crate（“类似tokio的crate”）
+    ///
+    /// 使用该crate已存储的嵌入向量做pgvector最近邻查询，结果不含该crate本身，
+    /// `vector_score` 和 `final_score` 均为按 `SearchConfig::similarity_metric` 计算的相似度。该crate没有嵌入向量时用嵌入接口即时生成
+    /// （不写回数据库，离线模式下返回错误）；crate不存在时返回空列表
+    pub async fn find_similar(
+        &self,
+        crate_name: &str,
+        limit: usize,
+    ) -> Result<Vec<RecommendCrate>, SearchError> {
+        self.find_similar_with_negatives(crate_name, &[], limit)
+            .await
+    }
+
+    /// 查找与指定crate语义相近、但不像 `negative` 中各crate的crate（“类似async-std但不是tokio”）
+    ///
+    /// 从种子向量中减去反例的平均向量（系数为 `SearchConfig::negative_example_weight`，
+    /// 见 `subtract_negative_examples`）后再做最近邻查询，反例本身不出现在结果中。
+    /// 反例的嵌入向量与种子相同方式获取，不存在的反例被忽略；结果的 `vector_score` 为与调整后向量的相似度
+    pub async fn find_similar_with_negatives(
+        &self,
+        crate_name: &str,
+        negative: &[String],
+        limit: usize,
+    ) -> Result<Vec<RecommendCrate>, SearchError> {
+        validate_identifier(&self.table_name)?;
+        // 向量存放在独立嵌入表时连接该表读取
+        let embedding_table = self.config.embedding_table_for(&self.table_name);
+        let join = self.config.embedding_join(&self.table_name);
+        let statement = format!(
+            "SELECT {0}.id, {0}.name, {0}.description, {1}.embedding FROM {0}{2}
+            WHERE {0}.name = $1 LIMIT 1",
+            self.table_name, embedding_table, join
+        );
+        let Some(seed) = self.pg_client.query_opt(&statement, &[&crate_name]).await? else {
+            return Ok(Vec::new());
+        };
+        let mut excluded_ids: Vec<String> = vec![seed.get("id")];
+        let seed_embedding = self.stored_or_computed_embedding(&seed).await?;
+
+        let query_embedding = if negative.is_empty() {
+            Vector::from(seed_embedding)
+        } else {
+            let statement = format!(
+                "SELECT {0}.id, {0}.name, {0}.description, {1}.embedding FROM {0}{2}
+                WHERE {0}.name = ANY($1)",
+                self.table_name, embedding_table, join
+            );
+            let mut negative_embeddings = Vec::new();
+            for row in self.pg_client.query(&statement, &[&negative]).await? {
+                negative_embeddings.push(self.stored_or_computed_embedding(&row).await?);
+                excluded_ids.push(row.get("id"));
+            }
+            Vector::from(subtract_negative_examples(
+                &seed_embedding,
+                &negative_embeddings,
+                self.config.negative_example_weight,
+            ))
+        };
+
+        let metric = self.config.similarity_metric;
+        let statement = format!(
+            "SELECT {0}.id, {0}.name, {0}.description, {0}.version, {0}.downloads,
+            {0}.repository, {0}.keywords, {3}::real AS vector_score
+            FROM {0}{2}
+            WHERE {1}.embedding IS NOT NULL AND {0}.id <> ALL($2)
+            ORDER BY {1}.embedding {4} $1
+            LIMIT $3",
+            self.table_name,
+            embedding_table,
+            join,
+            metric.sql_score(&format!("{}.embedding", embedding_table), "$1"),
+            metric.operator()
+        );
+        let rows = self
+            .pg_client
+            .query(
+                &statement,
+                &[&query_embedding, &excluded_ids, &(limit as i64)],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let vector_score: f32 = row.get::<_, Option<f32>>("vector_score").unwrap_or(0.0);
+                RecommendCrate {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    description: row
+                        .get::<_, Option<String>>("description")
+                        .unwrap_or_default(),
+                    vector_score,
+                    final_score: vector_score,
+                    version: row.get("version"),
+                    downloads: row.get::<_, Option<i64>>("downloads").unwrap_or(0),
+                    repository: row.get("repository"),
+                    keywords: row
+                        .get::<_, Option<Vec<String>>>("keywords")
+                        .unwrap_or_default(),
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
+
+    // 读取crate已存储的嵌入向量，没有时用嵌入接口即时生成（不写回数据库，离线模式下返回错误）
+    async fn stored_or_computed_embedding(&self, row: &Row) -> Result<Vec<f32>, SearchError> {
+        let name: String = row.get("name");
+        if let Some(embedding) = row.get::<_, Option<Vector>>("embedding") {
+            return Ok(embedding.to_vec());
+        }
+        if self.config.offline {
+            return Err(SearchError::Embedding(format!(
+                "crate {} 没有嵌入向量，离线模式下无法生成",
+                name
+            )));
+        }
+        let text = embedding_text(
+            &name,
+            &row.get::<_, Option<String>>("description")
+                .unwrap_or_default(),
+            self.config.max_embedding_chars,
+        );
+        self.embedder
+            .embed_one(&text)
+            .await
+            .map_err(|e| SearchError::Embedding(e.to_string()))
+    }
+}
@@ -0,0 +1,124 @@
+use crate::search::error::SearchError;
+use crate::search::utils::{levenshtein, validate_identifier};
+use std::collections::HashSet;
+use tokio_postgres::Client as PgClient;
+
+// 参与纠正的最短单词长度（字符数），过短的词与很多名称只差一两个字符，纠正容易出错
+const MIN_CORRECTION_LENGTH: usize = 4;
+
+/// 已知crate名称词表，用于在改写前纠正查询中的拼写错误
+///
+/// 名称按小写比较；编辑距离相同时优先纠正为词表中靠前的名称，
+/// 从数据表加载时即下载量更高的名称
+#[derive(Debug, Clone, Default)]
+pub struct CrateVocabulary {
+    names: Vec<String>,
+    known: HashSet<String>,
+}
+
+impl CrateVocabulary {
+    /// 由名称列表构建词表，重复的名称只保留第一次出现的位置
+    pub fn new<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut vocabulary = CrateVocabulary::default();
+        for name in names {
+            let name = name.as_ref().trim().to_lowercase();
+            if !name.is_empty() && vocabulary.known.insert(name.clone()) {
+                vocabulary.names.push(name);
+            }
+        }
+        vocabulary
+    }
+
+    /// 从数据表加载crate名称，按下载量降序最多加载 `limit` 个，为None时加载全部名称
+    pub async fn load(
+        pg_client: &PgClient,
+        table_name: &str,
+        limit: Option<usize>,
+    ) -> Result<Self, SearchError> {
+        validate_identifier(table_name)?;
+        // LIMIT NULL 等同于不限制数量
+        let limit = limit.map(|limit| limit.min(i64::MAX as usize) as i64);
+        let statement = format!(
+            "SELECT name FROM {} WHERE name IS NOT NULL ORDER BY downloads DESC NULLS LAST, name LIMIT $1",
+            table_name
+        );
+        let rows = pg_client.query(statement.as_str(), &[&limit]).await?;
+        Ok(CrateVocabulary::new(
+            rows.iter().map(|row| row.get::<_, String>("name")),
+        ))
+    }
+
+    /// 词表中的名称数量
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// 是否为已知的crate名称（不区分大小写）
+    pub fn contains(&self, name: &str) -> bool {
+        self.known.contains(&name.to_lowercase())
+    }
+
+    /// 纠正查询中的拼写错误：把与已知名称编辑距离不超过 `max_edit_distance` 的词替换为该名称
+    ///
+    /// 本身就是已知名称的词、短于4个字符的词，以及含有字母、数字、`-`、`_` 以外字符的词
+    /// （如中文、代码片段）保持不变。没有需要纠正的词时原样返回查询，
+    /// 否则单词之间的空白规范为单个空格
+    pub fn correct(&self, query: &str, max_edit_distance: usize) -> String {
+        if max_edit_distance == 0 || self.is_empty() {
+            return query.to_string();
+        }
+
+        let mut corrected = false;
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(|word| match self.nearest(word, max_edit_distance) {
+                Some(name) => {
+                    corrected = true;
+                    name.to_string()
+                }
+                None => word.to_string(),
+            })
+            .collect();
+
+        if corrected {
+            words.join(" ")
+        } else {
+            query.to_string()
+        }
+    }
+
+    // 与单词最接近且编辑距离不超过上限的名称，单词无需纠正时返回None
+    fn nearest(&self, word: &str, max_edit_distance: usize) -> Option<&str> {
+        let word = word.to_lowercase();
+        let length = word.chars().count();
+        if length < MIN_CORRECTION_LENGTH
+            || !word
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            || self.known.contains(&word)
+        {
+            return None;
+        }
+
+        let mut best: Option<(usize, &str)> = None;
+        for name in &self.names {
+            // 长度相差超过上限时编辑距离必然超过上限，跳过计算
+            if name.chars().count().abs_diff(length) > max_edit_distance {
+                continue;
+            }
+            let distance = levenshtein(&word, name);
+            if distance <= max_edit_distance && best.is_none_or(|(best, _)| distance < best) {
+                best = Some((distance, name));
+            }
+        }
+        best.map(|(_, name)| name)
+    }
+}
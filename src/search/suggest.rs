@@ -0,0 +1,109 @@
+use crate::search::core::SearchModule;
+use crate::search::retrieve::{count_matches, transfer_query_to_tsquery};
+use crate::search::utils::{levenshtein, validate_identifier};
+
+// 最多返回的建议数量
+const MAX_SUGGESTIONS: usize = 3;
+
+// 建议的crate名称与查询之间允许的最大编辑距离
+const MAX_EDIT_DISTANCE: usize = 3;
+
+// 未安装pg_trgm时，按下载量取样比较的crate名称数量
+const NAME_SAMPLE_SIZE: i64 = 5000;
+
+impl SearchModule<'_> {
+    /// 查询没有任何匹配时，按编辑距离给出最接近的crate名称（“您是不是要找 reqwest？”）
+    ///
+    /// 查询本身能检索到结果时返回空列表。候选名称优先用pg_trgm的 `similarity` 取回，
+    /// 未安装pg_trgm时改为比较下载量最高的一批crate名称；出错时同样返回空列表
+    pub async fn suggest(&self, query: &str) -> Vec<String> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        match self.has_matches(&query).await {
+            Ok(true) => return Vec::new(),
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("检查查询是否有匹配结果失败: {}", e);
+                return Vec::new();
+            }
+        }
+
+        let candidates = match self.suggestion_candidates(&query).await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                eprintln!("获取拼写建议候选失败: {}", e);
+                return Vec::new();
+            }
+        };
+
+        // 按编辑距离排序，距离相同时保持候选原有的顺序（相似度或下载量）
+        let mut scored: Vec<(usize, String)> = candidates
+            .into_iter()
+            .filter(|name| name.to_lowercase() != query)
+            .map(|name| (levenshtein(&query, &name.to_lowercase()), name))
+            .filter(|(distance, _)| *distance <= MAX_EDIT_DISTANCE)
+            .collect();
+        scored.sort_by_key(|(distance, _)| *distance);
+
+        let mut suggestions: Vec<String> = Vec::new();
+        for (_, name) in scored {
+            if !suggestions.contains(&name) {
+                suggestions.push(name);
+            }
+            if suggestions.len() >= MAX_SUGGESTIONS {
+                break;
+            }
+        }
+        suggestions
+    }
+
+    // 原始查询能否检索到任何crate
+    async fn has_matches(&self, query: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        validate_identifier(&self.table_name)?;
+        let tsquery = transfer_query_to_tsquery(query, &self.config).await?;
+        if tsquery.is_empty() {
+            return Ok(false);
+        }
+        Ok(count_matches(
+            self.pg_client,
+            &self.table_name,
+            &tsquery,
+            query,
+            &[],
+            &self.config,
+        )
+        .await?
+            > 0)
+    }
+
+    // 取回可能的拼写建议候选名称
+    async fn suggestion_candidates(
+        &self,
+        query: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let statement = format!(
+            "SELECT name FROM {} WHERE name % $1 ORDER BY similarity(name, $1) DESC LIMIT 20",
+            self.table_name
+        );
+        match self.pg_client.query(statement.as_str(), &[&query]).await {
+            Ok(rows) => return Ok(rows.iter().map(|row| row.get("name")).collect()),
+            Err(e) => eprintln!(
+                "三元组相似度查询失败（是否已安装pg_trgm扩展？），改为取样比较: {}",
+                e
+            ),
+        }
+
+        let statement = format!(
+            "SELECT name FROM {} ORDER BY downloads DESC NULLS LAST LIMIT $1",
+            self.table_name
+        );
+        let rows = self
+            .pg_client
+            .query(statement.as_str(), &[&NAME_SAMPLE_SIZE])
+            .await?;
+        Ok(rows.iter().map(|row| row.get("name")).collect())
+    }
+}
@@ -1,11 +1,22 @@
+use crate::search::config::{SearchConfig, StrategyLimits};
 use crate::search::core::{RecommendCrate, SearchSortCriteria};
+use crate::search::error::normalize_error;
+use crate::search::lang::{detect_language, QueryLanguage};
+use crate::search::rerank::{calculate_final_score, sort_by_score_desc};
+use crate::search::utils::{escape_like_pattern, validate_identifier, word_ngrams};
 use std::env;
 use tokio_postgres::Client as PgClient;
 
+// 默认参与前缀匹配的最短词长（字符数）
+const DEFAULT_PREFIX_MIN_CHARS: usize = 2;
+
 /// 传统搜索模块 - 不使用任何LLM技术，完全基于关键词匹配和经典排序算法
 pub struct TraditionalSearchModule<'a> {
     pg_client: &'a PgClient,
     table_name: String,
+    config: SearchConfig,
+    strategy_limits: Option<StrategyLimits>,
+    prefix_min_chars: usize,
 }
 
 impl<'a> TraditionalSearchModule<'a> {
@@ -14,15 +25,69 @@ impl<'a> TraditionalSearchModule<'a> {
         TraditionalSearchModule {
             pg_client,
             table_name,
+            config: SearchConfig::default(),
+            strategy_limits: None,
+            prefix_min_chars: DEFAULT_PREFIX_MIN_CHARS,
         }
     }
 
+    /// 设置候选数量和结果数量上限等搜索配置
+    ///
+    /// 未通过 `with_strategy_limits` 设置时，各检索策略的候选上限按 `candidate_limit` 折算：
+    /// 精确匹配取1/4，前缀匹配和全文搜索取3/4，补充的高级全文搜索取全部；
+    /// 排序后返回的结果数量由 `result_limit` 控制，为None时返回全部排序结果
+    pub fn with_config(mut self, config: SearchConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 分别设置各检索策略的候选数量上限
+    pub fn with_strategy_limits(mut self, strategy_limits: StrategyLimits) -> Self {
+        self.strategy_limits = Some(strategy_limits);
+        self
+    }
+
+    /// 设置参与前缀匹配的最短词长（按字符计，默认2），更短的词不生成前缀匹配项
+    pub fn with_prefix_min_chars(mut self, prefix_min_chars: usize) -> Self {
+        self.prefix_min_chars = prefix_min_chars;
+        self
+    }
+
     /// 传统搜索函数 - 使用多种经典IR技术而不是LLM
     pub async fn search(
         &self,
         query: &str,
         sort_by: SearchSortCriteria,
     ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        self.search_inner(query, sort_by)
+            .await
+            .map_err(normalize_error)
+    }
+
+    async fn search_inner(
+        &self,
+        query: &str,
+        sort_by: SearchSortCriteria,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        // 1-2. 查询预处理并执行多种搜索策略
+        let all_results = self.collect_candidates(query).await?;
+
+        // 3. 结果排序
+        let mut final_results = self.rank_results(all_results, sort_by);
+
+        // 4. 只返回前 result_limit 个结果
+        final_results.truncate(self.config.result_cap());
+
+        Ok(final_results)
+    }
+
+    /// 收集多种检索策略的候选结果，返回 (crate, 策略权重)，不做最终排序
+    pub(crate) async fn collect_candidates(
+        &self,
+        query: &str,
+    ) -> Result<Vec<(RecommendCrate, f32)>, Box<dyn std::error::Error>> {
+        validate_identifier(&self.table_name)?;
+
         // 1. 查询预处理
         let processed_queries = self.preprocess_query(query);
         println!("传统处理后的查询: {:?}", processed_queries);
@@ -81,15 +146,7 @@ impl<'a> TraditionalSearchModule<'a> {
             }
         }
 
-        // 3. 结果排序
-        let mut final_results = self.rank_results(all_results, sort_by);
-
-        // 4. 只返回前100个结果
-        if final_results.len() > 100 {
-            final_results.truncate(100);
-        }
-
-        Ok(final_results)
+        Ok(all_results)
     }
 
     /// 改进的查询预处理 - 返回多个可能的查询变体
@@ -106,8 +163,9 @@ impl<'a> TraditionalSearchModule<'a> {
         let query = original_query.to_lowercase();
 
         // 检测查询语言
-        let has_chinese = query.chars().any(|c| '\u{4e00}' <= c && c <= '\u{9fff}');
-        let has_english = query.chars().any(|c| c.is_ascii_alphabetic());
+        let language = detect_language(&query);
+        let has_chinese = matches!(language, QueryLanguage::Chinese | QueryLanguage::Mixed);
+        let has_english = matches!(language, QueryLanguage::English | QueryLanguage::Mixed);
 
         // 停用词列表
         let english_stopwords = [
@@ -228,24 +286,12 @@ impl<'a> TraditionalSearchModule<'a> {
                 query_variants.push(cleaned);
             }
 
-            // 添加关键词组合变体，特别是对于短语查询
+            // 添加关键词组合变体（二词和三词组合），特别是对于短语查询
             let words: Vec<&str> = processed.split_whitespace().collect();
             if words.len() >= 2 {
-                // 二词组合
-                for i in 0..words.len() - 1 {
-                    let bigram = format!("{} {}", words[i], words[i + 1]);
-                    if !query_variants.contains(&bigram) {
-                        query_variants.push(bigram);
-                    }
-                }
-
-                // 如果原始查询很长，添加三词组合
-                if words.len() >= 3 {
-                    for i in 0..words.len() - 2 {
-                        let trigram = format!("{} {} {}", words[i], words[i + 1], words[i + 2]);
-                        if !query_variants.contains(&trigram) {
-                            query_variants.push(trigram);
-                        }
+                for ngram in word_ngrams(&words, 3) {
+                    if !query_variants.contains(&ngram) {
+                        query_variants.push(ngram);
                     }
                 }
 
@@ -287,24 +333,33 @@ impl<'a> TraditionalSearchModule<'a> {
         let statement = format!(
             "SELECT id, name, description, 
                    (CASE 
-                     WHEN name ILIKE $1 THEN 1.0
-                     WHEN name ILIKE $2 THEN 0.9
-                     WHEN description ILIKE $1 THEN 0.8
+                     WHEN name ILIKE $1 ESCAPE '\\' THEN 1.0
+                     WHEN name ILIKE $2 ESCAPE '\\' THEN 0.9
+                     WHEN description ILIKE $1 ESCAPE '\\' THEN 0.8
                      ELSE 0.7
-                   END) AS rank
+                   END)::real AS rank
              FROM {}
-             WHERE name ILIKE $2 OR description ILIKE $2
+             WHERE name ILIKE $2 ESCAPE '\\' OR description ILIKE $2 ESCAPE '\\'
              ORDER BY rank DESC
-             LIMIT 50",
+             LIMIT $3",
             self.table_name
         );
 
-        let exact_pattern = format!("{}%", query); // 前缀匹配
-        let contains_pattern = format!("%{}%", query); // 包含匹配
+        // 用户输入中的 % 和 _ 按字面匹配
+        let escaped_query = escape_like_pattern(query);
+        let exact_pattern = format!("{}%", escaped_query); // 前缀匹配
+        let contains_pattern = format!("%{}%", escaped_query); // 包含匹配
 
         let rows = self
             .pg_client
-            .query(&statement, &[&exact_pattern, &contains_pattern])
+            .query(
+                &statement,
+                &[
+                    &exact_pattern,
+                    &contains_pattern,
+                    &(self.strategy_limits().exact as i64),
+                ],
+            )
             .await?;
 
         let mut results = Vec::new();
@@ -322,6 +377,7 @@ impl<'a> TraditionalSearchModule<'a> {
                 rank,
                 vector_score: 0.0, // 不使用向量得分
                 final_score: rank,
+                ..Default::default()
             });
         }
 
@@ -338,21 +394,8 @@ impl<'a> TraditionalSearchModule<'a> {
             return Ok(Vec::new());
         }
 
-        // 将查询分解为单词并构建tsquery
-        let words: Vec<&str> = query.split_whitespace().collect();
-        if words.is_empty() {
-            return Ok(Vec::new());
-        }
-
         // 为中英文混合查询准备前缀匹配查询
-        let mut prefix_terms = Vec::new();
-
-        // 前缀匹配
-        for word in &words {
-            if word.len() >= 2 {
-                prefix_terms.push(format!("{}:*", word));
-            }
-        }
+        let prefix_terms = prefix_match_terms(query, self.prefix_min_chars);
 
         // 如果没有有效的词项，返回空结果
         if prefix_terms.is_empty() {
@@ -364,15 +407,22 @@ impl<'a> TraditionalSearchModule<'a> {
 
         // 执行搜索
         let statement = format!(
-            "SELECT id, name, description, ts_rank(tsv, to_tsquery($1)) AS rank
-             FROM {}
-             WHERE tsv @@ to_tsquery($1)
+            "SELECT id, name, description, ts_rank(tsv, {1}) AS rank
+             FROM {0}
+             WHERE tsv @@ {1}
              ORDER BY rank DESC
-             LIMIT 150",
-            self.table_name
+             LIMIT $2",
+            self.table_name,
+            self.config.tsquery_call("to_tsquery", "$1")
         );
 
-        let rows = self.pg_client.query(&statement, &[&tsquery]).await?;
+        let rows = self
+            .pg_client
+            .query(
+                &statement,
+                &[&tsquery, &(self.strategy_limits().prefix as i64)],
+            )
+            .await?;
 
         let mut results = Vec::new();
 
@@ -389,6 +439,7 @@ impl<'a> TraditionalSearchModule<'a> {
                 rank,
                 vector_score: 0.0,
                 final_score: rank,
+                ..Default::default()
             });
         }
 
@@ -407,27 +458,32 @@ impl<'a> TraditionalSearchModule<'a> {
 
         // 使用websearch_to_tsquery，对用户输入更友好
         let statement = format!(
-            "SELECT id, name, description, ts_rank(tsv, websearch_to_tsquery($1)) AS rank
-             FROM {}
-             WHERE tsv @@ websearch_to_tsquery($1)
+            "SELECT id, name, description, ts_rank(tsv, {1}) AS rank
+             FROM {0}
+             WHERE tsv @@ {1}
              ORDER BY rank DESC
-             LIMIT 150",
-            self.table_name
+             LIMIT $2",
+            self.table_name,
+            self.config.tsquery_call("websearch_to_tsquery", "$1")
         );
 
-        let rows = match self.pg_client.query(&statement, &[&query]).await {
+        let limit = self.strategy_limits().fulltext as i64;
+        let rows = match self.pg_client.query(&statement, &[&query, &limit]).await {
             Ok(r) => r,
             Err(_) => {
                 // 如果websearch_to_tsquery不可用，回退到plainto_tsquery
                 let fallback_statement = format!(
-                    "SELECT id, name, description, ts_rank(tsv, plainto_tsquery($1)) AS rank
-                     FROM {}
-                     WHERE tsv @@ plainto_tsquery($1)
+                    "SELECT id, name, description, ts_rank(tsv, {1}) AS rank
+                     FROM {0}
+                     WHERE tsv @@ {1}
                      ORDER BY rank DESC
-                     LIMIT 150",
-                    self.table_name
+                     LIMIT $2",
+                    self.table_name,
+                    self.config.tsquery_call("plainto_tsquery", "$1")
                 );
-                self.pg_client.query(&fallback_statement, &[&query]).await?
+                self.pg_client
+                    .query(&fallback_statement, &[&query, &limit])
+                    .await?
             }
         };
 
@@ -446,6 +502,7 @@ impl<'a> TraditionalSearchModule<'a> {
                 rank,
                 vector_score: 0.0,
                 final_score: rank,
+                ..Default::default()
             });
         }
 
@@ -464,25 +521,37 @@ impl<'a> TraditionalSearchModule<'a> {
         // 对长句子使用更宽松的全文搜索
         let statement = format!(
             "SELECT id, name, description, 
-                    ts_rank(tsv, phraseto_tsquery($1)) * 0.6 AS rank
-             FROM {}
+                    (ts_rank(tsv, {1}) * 0.6)::real AS rank
+             FROM {0}
              WHERE 
-                tsv @@ phraseto_tsquery($1) OR
-                name ILIKE $2 OR
-                description ILIKE $2
+                tsv @@ {1} OR
+                name ILIKE $2 ESCAPE '\\' OR
+                description ILIKE $2 ESCAPE '\\'
              ORDER BY rank DESC
-             LIMIT 200",
-            self.table_name
+             LIMIT $3",
+            self.table_name,
+            self.config.tsquery_call("phraseto_tsquery", "$1")
         );
 
         let pattern = format!(
             "%{}%",
-            query.split_whitespace().collect::<Vec<_>>().join("%")
+            query
+                .split_whitespace()
+                .map(escape_like_pattern)
+                .collect::<Vec<_>>()
+                .join("%")
         );
 
         let rows = self
             .pg_client
-            .query(&statement, &[&query, &pattern])
+            .query(
+                &statement,
+                &[
+                    &query,
+                    &pattern,
+                    &(self.strategy_limits().advanced_fulltext as i64),
+                ],
+            )
             .await?;
 
         let mut results = Vec::new();
@@ -500,12 +569,19 @@ impl<'a> TraditionalSearchModule<'a> {
                 rank,
                 vector_score: 0.0,
                 final_score: rank,
+                ..Default::default()
             });
         }
 
         Ok(results)
     }
 
+    // 各检索策略的候选数量上限，未设置时按 candidate_limit 折算
+    fn strategy_limits(&self) -> StrategyLimits {
+        self.strategy_limits
+            .unwrap_or_else(|| StrategyLimits::from_candidate_limit(self.config.candidate_limit))
+    }
+
     /// 对搜索结果进行排序
     fn rank_results(
         &self,
@@ -530,14 +606,37 @@ impl<'a> TraditionalSearchModule<'a> {
                     crate_item.final_score = crate_item.rank * weight * 0.8;
                     // 注意：理想情况下应结合下载量数据
                 }
+                SearchSortCriteria::Custom { .. } => {
+                    // 传统搜索没有向量得分，按0计算
+                    crate_item.final_score = calculate_final_score(
+                        crate_item.rank * weight,
+                        0.0,
+                        crate_item.updated_at,
+                        crate_item.downloads,
+                        crate_item.popularity,
+                        &sort_criteria,
+                        &self.config,
+                    );
+                }
             }
 
             final_results.push(crate_item);
         }
 
         // 根据最终得分排序
-        final_results.sort_by(|a, b| b.final_score.partial_cmp(&a.final_score).unwrap());
+        sort_by_score_desc(&mut final_results, |c| c.final_score);
 
         final_results
     }
 }
+
+/// 把查询按空白分词，为不短于 `min_chars` 个字符的词生成tsquery前缀匹配项 `词:*`
+///
+/// 长度按字符而不是字节计算，单个汉字与单个英文字母同样算一个字符
+pub fn prefix_match_terms(query: &str, min_chars: usize) -> Vec<String> {
+    query
+        .split_whitespace()
+        .filter(|word| word.chars().count() >= min_chars)
+        .map(|word| format!("{}:*", word))
+        .collect()
+}
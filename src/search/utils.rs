@@ -1,8 +1,12 @@
+use crate::search::error::SearchError;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
+use std::sync::OnceLock;
+use unicode_normalization::UnicodeNormalization;
 
 #[derive(Serialize)]
 pub struct Message {
@@ -16,6 +20,8 @@ pub struct RequestBody {
     pub messages: Vec<Message>,
     pub temperature: f32,
     pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -33,24 +39,132 @@ pub struct ResponseBody {
     pub choices: Vec<ResponseChoice>,
 }
 
+// 是否为可以直接拼接进SQL的标识符（`^[A-Za-z_][A-Za-z0-9_]*$`）
+pub(crate) fn is_sql_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 校验表名或列名可以安全地拼接进SQL
+///
+/// 只接受匹配 `^[A-Za-z_][A-Za-z0-9_]*$` 的名称，否则返回 `SearchError::Config`
+pub fn validate_identifier(name: &str) -> Result<(), SearchError> {
+    if is_sql_identifier(name) {
+        Ok(())
+    } else {
+        Err(SearchError::Config(format!(
+            "不合法的表名或列名: {:?}，只允许字母、数字和下划线且不能以数字开头",
+            name
+        )))
+    }
+}
+
+/// 对文本做Unicode NFKC规范化：全角字符转为半角（"ＨＴＴＰ" -> "HTTP"），
+/// 组合字符合并为预组合形式（"cafe\u{301}" -> "café"），不改变大小写
+pub fn normalize_unicode(text: &str) -> String {
+    text.nfkc().collect()
+}
+
+/// 转义LIKE/ILIKE模式中的通配符 `%`、`_` 和转义符 `\`，使用户输入按字面匹配
+///
+/// 生成的模式需配合 `ESCAPE '\'` 使用
+pub fn escape_like_pattern(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// 按字符数截断文本，超出 `max_chars` 个字符时保留前 `max_chars` 个字符并追加 `...`
+///
+/// 按字符而不是字节切分，截断中文等多字节字符时不会panic
+pub fn truncate(s: &str, max_chars: usize) -> String {
+    match s.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &s[..end]),
+        None => s.to_string(),
+    }
+}
+
+// 两个字符串之间的Levenshtein编辑距离（按字符计算）
+pub(crate) fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+// 关键词提取默认生成的最长词组（单词数），与 `SearchConfig::keyword_max_ngram` 的默认值一致
+pub(crate) const DEFAULT_KEYWORD_MAX_NGRAM: usize = 2;
+
 // 基本的关键词提取（无需OpenAI API）
+//
+// 除单个关键词外，还生成最多两个相邻关键词组成的词组，如 "http client"；
+// 需要其他长度时使用 `basic_keyword_extraction_with_ngrams`
 pub fn basic_keyword_extraction(query: &str) -> String {
+    basic_keyword_extraction_with_ngrams(query, DEFAULT_KEYWORD_MAX_NGRAM)
+}
+
+// 基本的关键词提取，`max_ngram` 为生成词组的最大单词数，单个关键词排在词组之前
+pub fn basic_keyword_extraction_with_ngrams(query: &str, max_ngram: usize) -> String {
     let query = query.to_lowercase();
 
-    // 从文件中读取停用词
-    let stop_words = load_stop_words();
+    // 停用词只在首次使用时加载
+    let stop_words = cached_stop_words();
 
     // 分割查询并移除停用词
     let keywords: Vec<String> = query
         .split(|c: char| !c.is_alphanumeric() && c != '_') // 分割非字母数字和下划线的字符
-        .filter(|word| {
-            !word.is_empty() && !stop_words.contains(&word.to_string()) && word.len() > 2
-        }) // 移除空字符串、停用词和极短单词
+        .filter(|word| !word.is_empty() && !stop_words.contains(*word) && word.len() > 2) // 移除空字符串、停用词和极短单词
         .map(|word| word.to_string())
         .collect();
 
+    let words: Vec<&str> = keywords.iter().map(String::as_str).collect();
+    let mut terms = keywords.clone();
+    for ngram in word_ngrams(&words, max_ngram) {
+        if !terms.contains(&ngram) {
+            terms.push(ngram);
+        }
+    }
+
     // 返回逗号分隔的关键词
-    keywords.join(", ")
+    terms.join(", ")
+}
+
+/// 相邻单词组成的词组，按长度从2到 `max_n` 依次生成，同一长度内保持原有顺序
+///
+/// 例如 `["async", "http", "client"]` 在 `max_n` 为3时生成
+/// `"async http"`、`"http client"`、`"async http client"`
+pub fn word_ngrams(words: &[&str], max_n: usize) -> Vec<String> {
+    let mut ngrams = Vec::new();
+    for n in 2..=max_n.min(words.len()) {
+        for window in words.windows(n) {
+            ngrams.push(window.join(" "));
+        }
+    }
+    ngrams
+}
+
+// 进程内缓存的停用词集合，首次调用时从文件（或默认列表）加载
+fn cached_stop_words() -> &'static HashSet<String> {
+    static STOP_WORDS: OnceLock<HashSet<String>> = OnceLock::new();
+    STOP_WORDS.get_or_init(|| load_stop_words().into_iter().collect())
 }
 
 // 加载停用词列表
@@ -63,7 +177,7 @@ pub fn load_stop_words() -> Vec<String> {
         let reader = BufReader::new(file);
         let stop_words: Vec<String> = reader
             .lines()
-            .filter_map(Result::ok)
+            .map_while(Result::ok)
             .filter(|line| !line.trim().is_empty() && !line.starts_with("//"))
             .map(|line| line.trim().to_string())
             .collect();
@@ -120,3 +234,15 @@ pub fn load_stop_words() -> Vec<String> {
         "crate".to_string(),
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like_pattern() {
+        assert_eq!(escape_like_pattern("100%_complete"), "100\\%\\_complete");
+        assert_eq!(escape_like_pattern("a\\b"), "a\\\\b");
+        assert_eq!(escape_like_pattern("serde json"), "serde json");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::search::rebuild_tsv;
 use std::env;
 use tokio_postgres::Client as PgClient;
 
@@ -9,7 +10,7 @@ impl<'a> SearchPrepare<'a> {
     pub async fn new(pg_client: &'a PgClient) -> Self {
         let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
         SearchPrepare {
-            pg_client: pg_client,
+            pg_client,
             table_name,
         }
     }
@@ -72,13 +73,7 @@ impl<'a> SearchPrepare<'a> {
 
     // 功能四：设置tsv为crates中name属性和description属性的全文搜索tsvector
     pub async fn set_tsv_column(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let query = format!(
-            "UPDATE {} SET tsv = 
-                setweight(to_tsvector('english', coalesce(name, '')), 'A') || 
-                setweight(to_tsvector('english', coalesce(description, '')), 'B')",
-            self.table_name
-        );
-        self.pg_client.execute(&query, &[]).await?;
+        rebuild_tsv(self.pg_client, &self.table_name, "english").await?;
         Ok(())
     }
 
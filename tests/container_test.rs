@@ -0,0 +1,140 @@
+//! 基于testcontainers的集成测试：启动带pgvector的PostgreSQL容器，写入少量fixture crate，
+//! 用 `MockEmbedder` 代替嵌入接口，不依赖手动准备的数据库和API密钥。
+//!
+//! 需要本机可用的Docker，默认不编译，通过 `cargo test --features testcontainers` 运行
+use cratespro_search::search::embedder::MockEmbedder;
+use cratespro_search::search::{
+    ensure_schema, rerank_crates, retrive_crates, SearchConfig, SearchOptions, SearchSortCriteria,
+    TraditionalSearchModule,
+};
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use testcontainers_modules::testcontainers::{ContainerAsync, ImageExt};
+use tokio_postgres::{Client as PgClient, NoTls};
+
+// fixture表名与 TraditionalSearchModule 未设置 TABLE_NAME 时的默认表名一致
+const TABLE_NAME: &str = "crates";
+const DIMENSIONS: usize = 64;
+
+// 启动PostgreSQL容器并写入fixture crate，返回容器（需保持存活）和已完成 ensure_schema 的连接
+async fn start_postgres() -> Result<(ContainerAsync<Postgres>, PgClient), Box<dyn std::error::Error>>
+{
+    let container = Postgres::default()
+        .with_name("pgvector/pgvector")
+        .with_tag("pg16")
+        .start()
+        .await?;
+    let (pg_client, connection) = tokio_postgres::connect(
+        &format!(
+            "host={} port={} user=postgres password=postgres dbname=postgres",
+            container.get_host().await?,
+            container.get_host_port_ipv4(5432).await?
+        ),
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 只建必需的列，其余列、tsv 和 embedding 由 ensure_schema 补上
+    pg_client
+        .batch_execute(
+            "CREATE TABLE crates (id text PRIMARY KEY, name text NOT NULL, description text);
+            INSERT INTO crates (id, name, description) VALUES
+                ('1', 'serde', 'A generic serialization framework'),
+                ('2', 'serde_json', 'A JSON serialization file format'),
+                ('3', 'tokio', 'An event-driven, non-blocking I/O platform for asynchronous applications'),
+                ('4', 'reqwest', 'Higher level HTTP client library'),
+                ('5', 'hyper', 'A fast and correct HTTP library'),
+                ('6', 'clap', 'A simple to use, efficient, and full-featured command line argument parser')",
+        )
+        .await?;
+    ensure_schema(&pg_client, TABLE_NAME, DIMENSIONS, &SearchConfig::default()).await?;
+    // downloads 列由 ensure_schema 补上
+    pg_client
+        .batch_execute("UPDATE crates SET downloads = 1000 * id::bigint")
+        .await?;
+
+    Ok((container, pg_client))
+}
+
+#[tokio::test]
+async fn test_retrive_crates_in_container() -> Result<(), Box<dyn std::error::Error>> {
+    let (_container, pg_client) = start_postgres().await?;
+
+    let results = retrive_crates(
+        &pg_client,
+        TABLE_NAME,
+        "serialization",
+        &SearchConfig::default(),
+    )
+    .await?;
+    let names: Vec<&str> = results.iter().map(|c| c.name.as_str()).collect();
+    assert!(names.contains(&"serde"));
+    assert!(names.contains(&"serde_json"));
+    assert!(!names.contains(&"tokio"));
+
+    // 前缀匹配：http 同时命中 reqwest 和 hyper
+    let results = retrive_crates(&pg_client, TABLE_NAME, "http", &SearchConfig::default()).await?;
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|c| c.rank > 0.0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rerank_with_mock_embedder_in_container() -> Result<(), Box<dyn std::error::Error>> {
+    let (_container, pg_client) = start_postgres().await?;
+    let config = SearchConfig::default();
+    let embedder = MockEmbedder::new(DIMENSIONS);
+
+    let candidates = retrive_crates(&pg_client, TABLE_NAME, "http", &config).await?;
+    let results = rerank_crates(
+        candidates,
+        "http client",
+        SearchSortCriteria::Relavance,
+        &pg_client,
+        TABLE_NAME,
+        &embedder,
+        &config,
+        &SearchOptions::default(),
+    )
+    .await?;
+
+    // 描述中含有 "client" 的reqwest与查询向量更接近
+    assert_eq!(results[0].name, "reqwest");
+    assert!(results.iter().all(|c| c.vector_score > 0.0));
+
+    // 按需生成的向量已写回数据库
+    let stored: i64 = pg_client
+        .query_one(
+            "SELECT count(*) FROM crates WHERE embedding IS NOT NULL",
+            &[],
+        )
+        .await?
+        .get(0);
+    assert_eq!(stored, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_traditional_search_in_container() -> Result<(), Box<dyn std::error::Error>> {
+    let (_container, pg_client) = start_postgres().await?;
+
+    let module = TraditionalSearchModule::new(&pg_client).await;
+    let results = module
+        .search("tokio", SearchSortCriteria::Comprehensive)
+        .await?;
+    assert_eq!(results[0].name, "tokio");
+
+    let results = module
+        .search("command line parser", SearchSortCriteria::Relavance)
+        .await?;
+    assert_eq!(results[0].name, "clap");
+
+    Ok(())
+}
@@ -0,0 +1,419 @@
+use cratespro_search::search::embedder::{
+    cosine_similarity, embedding_text, similarity, subtract_negative_examples, truncate_embedding,
+    CohereEmbedder, Embedder, HttpEmbedder, MockEmbedder, OpenAiEmbedder, SimilarityMetric,
+};
+use cratespro_search::search::{ApiFlavor, HttpOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+// 启动按顺序响应请求的HTTP服务，每个连接返回一个响应，返回服务地址和收到的请求头、请求体
+async fn serve_with_headers(
+    responses: Vec<&'static str>,
+) -> (String, mpsc::UnboundedReceiver<(String, serde_json::Value)>) {
+    serve_with_status(responses.into_iter().map(|body| (200, body)).collect()).await
+}
+
+// 与 serve_with_headers 相同，但每个响应可指定HTTP状态码
+async fn serve_with_status(
+    responses: Vec<(u16, &'static str)>,
+) -> (String, mpsc::UnboundedReceiver<(String, serde_json::Value)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        for (status, response_body) in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 4096];
+
+            // 读取请求头和按 Content-Length 指定长度的请求体
+            let (headers, body) = loop {
+                let n = socket.read(&mut chunk).await.unwrap();
+                buffer.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&buffer).to_string();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if buffer.len() >= header_end + 4 + content_length {
+                        break (
+                            text[..header_end].to_lowercase(),
+                            buffer[header_end + 4..header_end + 4 + content_length].to_vec(),
+                        );
+                    }
+                }
+            };
+
+            let _ = tx.send((headers, serde_json::from_slice(&body).unwrap()));
+            let response = format!(
+                "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                response_body.len(),
+                response_body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    (format!("http://{}", addr), rx)
+}
+
+// 只关心请求体的服务
+async fn serve(
+    responses: Vec<&'static str>,
+) -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+    let (url, mut requests) = serve_with_headers(responses).await;
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some((_, body)) = requests.recv().await {
+            let _ = tx.send(body);
+        }
+    });
+    (url, rx)
+}
+
+async fn serve_once(
+    response_body: &'static str,
+) -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+    serve(vec![response_body]).await
+}
+
+#[tokio::test]
+async fn test_http_embedder_with_wildcard_path() {
+    let (url, mut request) =
+        serve_once(r#"{"data":[{"embedding":[1.0,0.0]},{"embedding":[0.0,1.0]}]}"#).await;
+
+    let embedder = HttpEmbedder::new(url, "input", "data.*.embedding")
+        .request_template(serde_json::json!({ "model": "voyage-3" }));
+    let embeddings = embedder
+        .embed(&["serde".to_string(), "tokio".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    let body = request.recv().await.unwrap();
+    assert_eq!(body["model"], "voyage-3");
+    assert_eq!(body["input"], serde_json::json!(["serde", "tokio"]));
+}
+
+#[tokio::test]
+async fn test_http_embedder_with_nested_input_and_array_path() {
+    let (url, mut request) = serve_once(r#"{"result":{"embeddings":[[0.5,0.5]]}}"#).await;
+
+    let embedder = HttpEmbedder::new(url, "payload.texts", "result.embeddings");
+    let embeddings = embedder.embed(&["clap".to_string()]).await.unwrap();
+
+    assert_eq!(embeddings, vec![vec![0.5, 0.5]]);
+    let body = request.recv().await.unwrap();
+    assert_eq!(body["payload"]["texts"], serde_json::json!(["clap"]));
+}
+
+#[tokio::test]
+async fn test_cohere_embedder_uses_query_input_type() {
+    let (url, mut request) = serve_once(r#"{"id":"1","embeddings":[[0.1,0.2,0.3]]}"#).await;
+
+    let embedder = CohereEmbedder::new("test-key", "embed-english-v3.0").with_url(url);
+    let embedding = embedder.embed_one("http client").await.unwrap();
+
+    assert_eq!(embedding, vec![0.1, 0.2, 0.3]);
+    let body = request.recv().await.unwrap();
+    assert_eq!(body["input_type"], "search_query");
+    assert_eq!(body["texts"], serde_json::json!(["http client"]));
+}
+
+#[tokio::test]
+async fn test_openai_embedder_refetches_missing_vectors() {
+    // 第一次响应缺少索引1，重新请求时只发送缺失的文本
+    let (url, mut request) = serve(vec![
+        r#"{"data":[{"embedding":[0.0,1.0],"index":2},{"embedding":[1.0,0.0],"index":0}]}"#,
+        r#"{"data":[{"embedding":[0.5,0.5],"index":0}]}"#,
+    ])
+    .await;
+
+    let embedder = OpenAiEmbedder::new("test-key", url, "text-embedding-3-small");
+    let texts = vec!["serde".to_string(), "tokio".to_string(), "clap".to_string()];
+    let embeddings = embedder.embed(&texts).await.unwrap();
+
+    assert_eq!(
+        embeddings,
+        vec![vec![1.0, 0.0], vec![0.5, 0.5], vec![0.0, 1.0]]
+    );
+    assert_eq!(
+        request.recv().await.unwrap()["input"]
+            .as_array()
+            .unwrap()
+            .len(),
+        3
+    );
+    assert_eq!(
+        request.recv().await.unwrap()["input"],
+        serde_json::json!(["tokio"])
+    );
+}
+
+#[tokio::test]
+async fn test_openai_embedder_does_not_retry_client_errors() {
+    let (url, mut requests) = serve_with_status(vec![
+        (401, r#"{"error":{"message":"Incorrect API key provided"}}"#),
+        (200, r#"{"data":[{"embedding":[1.0,0.0],"index":0}]}"#),
+    ])
+    .await;
+
+    let embedder = OpenAiEmbedder::new("bad-key", url, "text-embedding-3-small");
+    let error = embedder.embed(&["serde".to_string()]).await.unwrap_err();
+
+    // 返回状态码错误，而不是解析响应体的错误，且不会重新请求
+    let status = error
+        .downcast_ref::<reqwest::Error>()
+        .and_then(reqwest::Error::status);
+    assert_eq!(status, Some(reqwest::StatusCode::UNAUTHORIZED));
+    assert!(requests.recv().await.is_some());
+    assert!(requests.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_openai_embedder_retries_server_errors() {
+    let (url, mut requests) = serve_with_status(vec![
+        (503, r#"{"error":{"message":"overloaded"}}"#),
+        (200, r#"{"data":[{"embedding":[1.0,0.0],"index":0}]}"#),
+    ])
+    .await;
+
+    let embedder = OpenAiEmbedder::new("test-key", url, "text-embedding-3-small");
+    let embeddings = embedder.embed(&["serde".to_string()]).await.unwrap();
+
+    assert_eq!(embeddings, vec![vec![1.0, 0.0]]);
+    assert!(requests.recv().await.is_some());
+    assert!(requests.recv().await.is_some());
+}
+
+#[tokio::test]
+async fn test_openai_embedder_leaves_unrecoverable_vectors_empty() {
+    let (url, _request) = serve(vec![
+        r#"{"data":[{"embedding":[1.0,0.0],"index":0}]}"#,
+        r#"{"data":[]}"#,
+    ])
+    .await;
+
+    let embedder = OpenAiEmbedder::new("test-key", url, "text-embedding-3-small");
+    let texts = vec!["serde".to_string(), "tokio".to_string()];
+    let embeddings = embedder.embed(&texts).await.unwrap();
+
+    // 无法获取的向量保留为空，不会把后面的向量错位到前面的文本上
+    assert_eq!(embeddings, vec![vec![1.0, 0.0], Vec::new()]);
+}
+
+#[tokio::test]
+async fn test_http_options_send_user_agent_and_headers() {
+    let (url, mut requests) = serve_with_headers(vec![r#"{"data":[{"embedding":[1.0]}]}"#]).await;
+
+    let http = HttpOptions {
+        user_agent: Some("cratespro-search-test/1.0".to_string()),
+        headers: vec![
+            ("api-key".to_string(), "gateway-secret".to_string()),
+            ("invalid header".to_string(), "ignored".to_string()),
+        ],
+        ..Default::default()
+    };
+    let embedder = HttpEmbedder::new(url, "input", "data.*.embedding").with_http_options(&http);
+    embedder.embed(&["serde".to_string()]).await.unwrap();
+
+    let (headers, _) = requests.recv().await.unwrap();
+    assert!(headers.contains("user-agent: cratespro-search-test/1.0"));
+    assert!(headers.contains("api-key: gateway-secret"));
+}
+
+#[tokio::test]
+async fn test_injected_http_client_is_used() {
+    let (url, mut requests) = serve_with_headers(vec![
+        r#"{"data":[{"embedding":[1.0]}]}"#,
+        r#"{"id":"1","embeddings":[[1.0]]}"#,
+    ])
+    .await;
+
+    let mut default_headers = reqwest::header::HeaderMap::new();
+    default_headers.insert("x-proxy-token", "shared".parse().unwrap());
+    let client = reqwest::Client::builder()
+        .default_headers(default_headers)
+        .build()
+        .unwrap();
+
+    // 设置了客户端时忽略 user_agent 和 headers
+    let http = HttpOptions {
+        user_agent: Some("ignored-agent".to_string()),
+        client: Some(client.clone()),
+        ..Default::default()
+    };
+    let embedder =
+        HttpEmbedder::new(url.clone(), "input", "data.*.embedding").with_http_options(&http);
+    embedder.embed(&["serde".to_string()]).await.unwrap();
+    let (headers, _) = requests.recv().await.unwrap();
+    assert!(headers.contains("x-proxy-token: shared"));
+    assert!(!headers.contains("ignored-agent"));
+
+    let embedder = CohereEmbedder::new("test-key", "embed-english-v3.0")
+        .with_url(url)
+        .with_client(client);
+    embedder.embed(&["serde".to_string()]).await.unwrap();
+    let (headers, _) = requests.recv().await.unwrap();
+    assert!(headers.contains("x-proxy-token: shared"));
+}
+
+#[tokio::test]
+async fn test_openai_embedder_with_azure_flavor() {
+    let (url, mut requests) =
+        serve_with_headers(vec![r#"{"data":[{"embedding":[0.5,0.5],"index":0}]}"#]).await;
+
+    let embedder = OpenAiEmbedder::new("azure-key", format!("{}/", url), "text-embedding-3-small")
+        .with_flavor(ApiFlavor::Azure {
+            deployment: "embedding-deploy".to_string(),
+            api_version: "2024-02-01".to_string(),
+        });
+    let embeddings = embedder.embed(&["serde".to_string()]).await.unwrap();
+
+    assert_eq!(embeddings, vec![vec![0.5, 0.5]]);
+    let (headers, _) = requests.recv().await.unwrap();
+    assert!(headers.starts_with(
+        "post /openai/deployments/embedding-deploy/embeddings?api-version=2024-02-01 "
+    ));
+    assert!(headers.contains("api-key: azure-key"));
+    assert!(!headers.contains("authorization:"));
+}
+
+#[tokio::test]
+async fn test_openai_embedder_with_dimensions() {
+    // 代理服务忽略了 dimensions 参数，返回的向量在本地截断并归一化
+    let (url, mut request) = serve(vec![
+        r#"{"data":[{"embedding":[3.0,4.0,12.0],"index":0}]}"#,
+        r#"{"data":[{"embedding":[1.0,0.0,0.0],"index":0}]}"#,
+    ])
+    .await;
+
+    let embedder =
+        OpenAiEmbedder::new("test-key", url.clone(), "text-embedding-3-small").with_dimensions(2);
+    assert_eq!(embedder.dimensions(), Some(2));
+    let embeddings = embedder.embed(&["serde".to_string()]).await.unwrap();
+
+    assert_eq!(embeddings, vec![vec![0.6, 0.8]]);
+    assert_eq!(request.recv().await.unwrap()["dimensions"], 2);
+
+    // 未设置维度时不发送该参数，也不截断
+    let embedder = OpenAiEmbedder::new("test-key", url, "text-embedding-3-small");
+    assert_eq!(embedder.dimensions(), None);
+    let embeddings = embedder.embed(&["serde".to_string()]).await.unwrap();
+    assert_eq!(embeddings, vec![vec![1.0, 0.0, 0.0]]);
+    assert!(request.recv().await.unwrap().get("dimensions").is_none());
+}
+
+#[test]
+fn test_truncate_embedding() {
+    assert_eq!(truncate_embedding(vec![3.0, 4.0, 5.0], 2), vec![0.6, 0.8]);
+    // 不长于目标维度时原样返回
+    assert_eq!(truncate_embedding(vec![3.0, 4.0], 4), vec![3.0, 4.0]);
+    assert_eq!(truncate_embedding(vec![0.0, 0.0, 1.0], 2), vec![0.0, 0.0]);
+
+    // 长度不一致的向量相似度为0
+    assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]), 0.0);
+}
+
+#[test]
+fn test_similarity_metrics() {
+    let a = [0.6, 0.8];
+    let b = [1.0, 0.0];
+
+    // 单位向量的点积与余弦相似度相同
+    let cosine = similarity(SimilarityMetric::Cosine, &a, &b);
+    assert!((cosine - 0.6).abs() < 1e-5);
+    assert!((similarity(SimilarityMetric::Dot, &a, &b) - cosine).abs() < 1e-5);
+    assert!((similarity(SimilarityMetric::Dot, &[3.0, 4.0], &b) - 3.0).abs() < 1e-5);
+
+    // L2距离换算到 (0, 1]，相同向量为1，距离越远得分越低
+    assert!((similarity(SimilarityMetric::L2, &a, &a) - 1.0).abs() < 1e-5);
+    let near = similarity(SimilarityMetric::L2, &a, &b);
+    let far = similarity(SimilarityMetric::L2, &a, &[-1.0, 0.0]);
+    assert!(near > far && far > 0.0);
+
+    // 长度不一致或为空的向量相似度为0
+    for metric in [
+        SimilarityMetric::Cosine,
+        SimilarityMetric::Dot,
+        SimilarityMetric::L2,
+    ] {
+        assert_eq!(similarity(metric, &[1.0, 0.0], &[1.0, 0.0, 0.0]), 0.0);
+        assert_eq!(similarity(metric, &[], &[]), 0.0);
+    }
+}
+
+#[test]
+fn test_subtract_negative_examples() {
+    // 种子介于两个方向之间，减去反例方向后偏向另一个方向
+    let seed = vec![3.0, 3.0, 0.0];
+    let negative = vec![1.0, 0.0, 0.0];
+    let other = [0.0, 1.0, 0.0];
+    let adjusted = subtract_negative_examples(&seed, std::slice::from_ref(&negative), 0.5);
+    let norm: f32 = adjusted.iter().map(|v| v * v).sum::<f32>().sqrt();
+    assert!((norm - 1.0).abs() < 1e-5);
+    assert!(cosine_similarity(&adjusted, &negative) < cosine_similarity(&seed, &negative));
+    assert!(cosine_similarity(&adjusted, &other) > cosine_similarity(&seed, &other));
+
+    // 没有可用反例（维度不一致）或相减后为零向量时返回原向量
+    assert_eq!(
+        subtract_negative_examples(&seed, &[vec![1.0, 0.0]], 0.5),
+        seed
+    );
+    assert_eq!(subtract_negative_examples(&seed, &[], 0.5), seed);
+    assert_eq!(
+        subtract_negative_examples(&seed, std::slice::from_ref(&seed), 1.0),
+        seed
+    );
+}
+
+#[test]
+fn test_embedding_text_truncates_long_description() {
+    assert_eq!(embedding_text("serde", "", 100), "serde");
+    assert_eq!(
+        embedding_text("serde", "serialization framework", 100),
+        "serde : serialization framework"
+    );
+
+    // 保留名称和描述开头，按字符截断，不会切在多字节字符中间
+    let description = "高性能序列化框架".repeat(10_000);
+    let text = embedding_text("serde", &description, 20);
+    assert_eq!(text.chars().count(), 20);
+    assert!(text.starts_with("serde : 高性能序列化框架"));
+}
+
+#[tokio::test]
+async fn test_mock_embedder_is_deterministic() {
+    let embedder = MockEmbedder::default().with_vector("pinned", vec![0.0, 1.0]);
+    let texts = vec![
+        "reqwest : higher level HTTP client library".to_string(),
+        "ureq : simple safe HTTP client".to_string(),
+        "serde : serialization framework".to_string(),
+        "pinned".to_string(),
+    ];
+
+    let first = embedder.embed(&texts).await.unwrap();
+    let second = MockEmbedder::default()
+        .with_vector("pinned", vec![0.0, 1.0])
+        .embed(&texts)
+        .await
+        .unwrap();
+    assert_eq!(first, second);
+    assert_eq!(first[0].len(), 64);
+    assert_eq!(first[3], vec![0.0, 1.0]);
+
+    // 共享词越多相似度越高
+    let query = embedder.embed_one("http client").await.unwrap();
+    let http_similarity = cosine_similarity(&query, &first[1]);
+    let serde_similarity = cosine_similarity(&query, &first[2]);
+    assert!(http_similarity > serde_similarity);
+    assert!((cosine_similarity(&first[0], &first[0]) - 1.0).abs() < 1e-5);
+}
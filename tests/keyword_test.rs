@@ -0,0 +1,79 @@
+use cratespro_search::search::{
+    basic_keyword_extraction_with_ngrams, normalize_unicode, prefix_match_terms, truncate,
+    validate_identifier, word_ngrams, SearchError,
+};
+
+#[test]
+fn test_word_ngrams() {
+    let words = ["async", "http", "client"];
+    assert_eq!(
+        word_ngrams(&words, 3),
+        vec!["async http", "http client", "async http client"]
+    );
+    assert_eq!(word_ngrams(&words, 2), vec!["async http", "http client"]);
+    assert!(word_ngrams(&words, 1).is_empty());
+    assert!(word_ngrams(&["serde"], 3).is_empty());
+}
+
+#[test]
+fn test_basic_keyword_extraction_with_ngrams() {
+    // 停用词被移除后，相邻的关键词组成词组
+    assert_eq!(
+        basic_keyword_extraction_with_ngrams("a fast http client for the web", 2),
+        "fast, http, client, web, fast http, http client, client web"
+    );
+    assert_eq!(
+        basic_keyword_extraction_with_ngrams("fast http client", 1),
+        "fast, http, client"
+    );
+}
+
+#[test]
+fn test_truncate_multibyte() {
+    // 第3个字节落在第一个汉字之后，按字节切分会panic
+    assert_eq!(truncate("高性能HTTP客户端", 3), "高性能...");
+    assert_eq!(truncate("高性能", 3), "高性能");
+    assert_eq!(truncate("serde", 10), "serde");
+    assert_eq!(truncate("", 0), "");
+}
+
+#[test]
+fn test_validate_identifier() {
+    for name in ["crates", "_staging", "crates_2024", "Crates"] {
+        assert!(validate_identifier(name).is_ok(), "{}", name);
+    }
+    for name in [
+        "",
+        "2024_crates",
+        "crates; DROP TABLE crates",
+        "public.crates",
+        "crates\"",
+    ] {
+        assert!(
+            matches!(validate_identifier(name), Err(SearchError::Config(_))),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_prefix_match_terms_count_chars() {
+    // 单个汉字只算一个字符（虽然占3个字节），默认最短2个字符时不参与前缀匹配
+    assert!(prefix_match_terms("库", 2).is_empty());
+    assert_eq!(
+        prefix_match_terms("异步 库 io a", 2),
+        vec!["异步:*", "io:*"]
+    );
+
+    // 最短长度设为1时单字词也参与
+    assert_eq!(prefix_match_terms("库 a", 1), vec!["库:*", "a:*"]);
+    assert_eq!(prefix_match_terms("http client", 5), vec!["client:*"]);
+}
+
+#[test]
+fn test_normalize_unicode() {
+    assert_eq!(normalize_unicode("ＨＴＴＰ　ｃｌｉｅｎｔ"), "HTTP client");
+    assert_eq!(normalize_unicode("cafe\u{301}"), "café");
+    assert_eq!(normalize_unicode("异步 Runtime"), "异步 Runtime");
+}
@@ -0,0 +1,82 @@
+use cratespro_search::search::lang::{
+    classify_query, detect_language, extract_code_identifiers, is_natural_language, QueryKind,
+    QueryLanguage,
+};
+
+#[test]
+fn test_detect_language() {
+    assert_eq!(detect_language("http client"), QueryLanguage::English);
+    assert_eq!(detect_language("命令行参数解析"), QueryLanguage::Chinese);
+    assert_eq!(detect_language("json 序列化"), QueryLanguage::Mixed);
+    assert_eq!(detect_language("123 !?"), QueryLanguage::Other);
+}
+
+#[test]
+fn test_is_natural_language() {
+    assert!(!is_natural_language("http client"));
+    assert!(!is_natural_language("serde"));
+    assert!(is_natural_language("how to parse json in rust"));
+    assert!(is_natural_language(
+        "I need a crate for handling HTTP requests"
+    ));
+    assert!(is_natural_language("如何解析JSON数据"));
+    assert!(is_natural_language("我需要一个好用的日志库？"));
+}
+
+#[test]
+fn test_classify_query() {
+    assert_eq!(classify_query("http client"), QueryKind::Keyword);
+    assert_eq!(
+        classify_query("how to parse json in rust"),
+        QueryKind::NaturalLanguage
+    );
+    assert_eq!(
+        classify_query(
+            "error[E0277]: the trait bound `Config: serde::Deserialize<'_>` is not satisfied"
+        ),
+        QueryKind::CodeOrError
+    );
+    assert_eq!(
+        classify_query("thread 'main' panicked at 'called `Result::unwrap()` on an `Err` value'"),
+        QueryKind::CodeOrError
+    );
+    assert_eq!(
+        classify_query("let client = reqwest::Client::new();"),
+        QueryKind::CodeOrError
+    );
+    assert_eq!(
+        classify_query("fn handler(req: HttpRequest) -> impl Responder"),
+        QueryKind::CodeOrError
+    );
+}
+
+#[test]
+fn test_extract_code_identifiers() {
+    // 编译器错误信息只从反引号内提取
+    assert_eq!(
+        extract_code_identifiers(
+            "error[E0277]: the trait bound `Config: serde::Deserialize<'_>` is not satisfied"
+        ),
+        vec!["Config", "serde", "Deserialize"]
+    );
+    assert_eq!(
+        extract_code_identifiers(
+            "error[E0599]: no method named `block_on` found for struct `tokio::runtime::Runtime` in the current scope"
+        ),
+        vec!["block_on", "tokio", "Runtime"]
+    );
+    // 没有反引号时只保留路径首段和类型名，忽略标准库类型和关键字
+    assert_eq!(
+        extract_code_identifiers(
+            "let map: HashMap<String, Vec<u8>> = serde_json::from_str(&data)?;"
+        ),
+        vec!["HashMap", "serde_json"]
+    );
+    assert_eq!(
+        extract_code_identifiers(
+            "use std::sync::Arc; let rt = tokio::runtime::Builder::new_multi_thread();"
+        ),
+        vec!["Arc", "tokio", "Builder"]
+    );
+    assert!(extract_code_identifiers("fn main() {}").is_empty());
+}
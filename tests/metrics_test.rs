@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use cratespro_search::search::evaluation::{evaluate, QueryRelevance, Searcher};
+use cratespro_search::search::metrics::{
+    dcg_at_k, ndcg_at_k, precision_at_k, recall, recall_at_k, GradedJudgment,
+};
+use cratespro_search::search::{RecommendCrate, SearchSortCriteria};
+use std::collections::{HashMap, HashSet};
+
+fn crates(names: &[&str]) -> Vec<RecommendCrate> {
+    names
+        .iter()
+        .map(|name| RecommendCrate {
+            id: name.to_string(),
+            name: name.to_string(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[test]
+fn test_precision_at_k() {
+    let flags = [true, false, true, false];
+    assert_eq!(precision_at_k(&flags, 1), 1.0);
+    assert_eq!(precision_at_k(&flags, 4), 0.5);
+    // k大于结果数时按实际结果数计算
+    assert_eq!(precision_at_k(&flags[..2], 10), 0.5);
+    assert_eq!(precision_at_k(&[], 5), 0.0);
+}
+
+#[test]
+fn test_recall_at_k() {
+    let flags = [true, false, true, false];
+    assert_eq!(recall_at_k(&flags, 4, 1), 0.25);
+    assert_eq!(recall_at_k(&flags, 4, 4), 0.5);
+    // 没有已标注的相关crate时召回率为0
+    assert_eq!(recall_at_k(&flags, 0, 4), 0.0);
+}
+
+#[test]
+fn test_recall_against_relevant_set() {
+    let relevant_set: HashSet<String> = ["reqwest", "hyper", "ureq"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+    let results = crates(&["reqwest", "serde", "hyper", "reqwest"]);
+    assert!((recall(&results, &relevant_set) - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(recall(&results, &HashSet::new()), 0.0);
+}
+
+#[test]
+fn test_dcg_at_k() {
+    // 3 / log2(2) + 1 / log2(3)
+    let expected = 7.0 + 1.0 / 3f64.log2();
+    assert!((dcg_at_k(&[3, 1], 2) - expected).abs() < 1e-9);
+    assert_eq!(dcg_at_k(&[3, 1], 0), 0.0);
+}
+
+#[test]
+fn test_ndcg_prefers_best_crate_first() {
+    let grades: HashMap<String, u8> = [("reqwest", 3), ("ureq", 2), ("hyper", 1)]
+        .iter()
+        .map(|(name, grade)| (name.to_string(), *grade))
+        .collect();
+
+    let ideal = crates(&["reqwest", "ureq", "hyper"]);
+    let swapped = crates(&["ureq", "reqwest", "hyper"]);
+    let missing = crates(&["serde", "ureq", "hyper"]);
+
+    assert!((ndcg_at_k(&ideal, &grades, 3) - 1.0).abs() < 1e-9);
+    assert!(ndcg_at_k(&swapped, &grades, 3) < 1.0);
+    assert!(ndcg_at_k(&missing, &grades, 3) < ndcg_at_k(&swapped, &grades, 3));
+    assert_eq!(ndcg_at_k(&ideal, &HashMap::new(), 3), 0.0);
+}
+
+// 按查询返回固定结果的搜索实现，未知查询返回错误
+struct FixedSearcher(HashMap<&'static str, Vec<&'static str>>);
+
+#[async_trait(?Send)]
+impl Searcher for FixedSearcher {
+    async fn search(
+        &self,
+        query: &str,
+        _sort_by: SearchSortCriteria,
+    ) -> Result<Vec<RecommendCrate>, Box<dyn std::error::Error>> {
+        let names = self.0.get(query).ok_or("未知查询")?;
+        Ok(crates(names))
+    }
+}
+
+#[tokio::test]
+async fn test_evaluate_dataset() {
+    let searcher = FixedSearcher(HashMap::from([
+        ("http client", vec!["reqwest", "hyper", "serde"]),
+        ("json", vec!["toml", "serde_json"]),
+    ]));
+    let dataset = vec![
+        QueryRelevance::binary("http client", &["reqwest", "hyper", "ureq", "isahc"]),
+        QueryRelevance {
+            query: "json".to_string(),
+            description: "分级标注".to_string(),
+            judgments: vec![GradedJudgment {
+                crate_name: "serde_json".to_string(),
+                grade: 3,
+            }],
+        },
+        QueryRelevance::binary("unknown", &["tokio"]),
+    ];
+
+    let report = evaluate(&searcher, &dataset, SearchSortCriteria::Relavance).await;
+
+    // 失败的查询单独记录，不计入平均值
+    assert_eq!(report.results.len(), 2);
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].0, "unknown");
+
+    let http = &report.results[0];
+    assert_eq!(http.precision_at_1, 1.0);
+    assert!((http.precision_at_3 - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(http.recall, 0.5);
+
+    let json = &report.results[1];
+    assert_eq!(json.precision_at_1, 0.0);
+    assert_eq!(json.recall, 1.0);
+    assert!((json.ndcg_at_10 - 1.0 / 3f64.log2()).abs() < 1e-9);
+
+    assert_eq!(report.mean_precision_at_1, 0.5);
+    assert_eq!(report.mean_recall, 0.75);
+}
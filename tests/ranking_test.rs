@@ -0,0 +1,465 @@
+use chrono::{DateTime, Duration, Utc};
+use cratespro_search::search::{
+    apply_feedback, apply_graph_boost, apply_name_match_boost, calculate_final_score,
+    exclude_crates, filter_by_min_score, filter_by_version, group_by_family, rank_by_keyword_only,
+    reciprocal_rank_fusion, sort_by_score_desc, GraphBoost, PopularityColumn, RecencyBoost,
+    RecommendCrate, SearchConfig, SearchSortCriteria, RRF_K,
+};
+use semver::VersionReq;
+use std::collections::HashMap;
+
+fn scored_crate(name: &str, final_score: f32) -> RecommendCrate {
+    RecommendCrate {
+        id: name.to_string(),
+        name: name.to_string(),
+        final_score,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_feedback_boosts_and_clamps_adjustments() {
+    let mut crates = [
+        scored_crate("reqwest", 0.5),
+        scored_crate("hyper", 0.95),
+        scored_crate("ureq", 0.1),
+        scored_crate("isahc", f32::MAX),
+    ];
+
+    let mut feedback = HashMap::new();
+    feedback.insert("reqwest".to_string(), 0.5);
+    feedback.insert("hyper".to_string(), 5.0); // 超出范围的调整值会被截断到1
+    feedback.insert("ureq".to_string(), -1.0);
+    feedback.insert("isahc".to_string(), 1.0);
+
+    apply_feedback(&mut crates[3..], &feedback, f32::MAX);
+
+    // 调整结果溢出为无穷大时保留原得分
+    assert_eq!(crates[3].final_score, f32::MAX);
+
+    apply_feedback(&mut crates[..3], &feedback, 0.2);
+    assert!((crates[0].final_score - 0.6).abs() < 1e-6);
+    assert!((crates[1].final_score - 1.15).abs() < 1e-6);
+    assert!((crates[2].final_score + 0.1).abs() < 1e-6);
+}
+
+#[test]
+fn test_feedback_does_not_lower_name_match_boost() {
+    let mut crates = vec![scored_crate("reqwest", 1.0), scored_crate("hyper", 1.1)];
+    // 名称与查询词完全相同的reqwest加分后超过1
+    apply_name_match_boost(&mut crates, "reqwest", 0.3, 0.1);
+    let boosted = crates[0].final_score;
+    assert!(boosted > 1.0);
+
+    let mut feedback = HashMap::new();
+    feedback.insert("reqwest".to_string(), 1.0);
+    apply_feedback(&mut crates, &feedback, 0.2);
+
+    // 正反馈不会降低加分后的得分，仍排在未加分的hyper之前
+    assert!(crates[0].final_score > boosted);
+    assert!(crates[0].final_score > crates[1].final_score);
+}
+
+#[test]
+fn test_nan_scores_sort_without_panic() {
+    let mut crates = vec![
+        scored_crate("a", 0.3),
+        scored_crate("b", f32::NAN),
+        scored_crate("c", 0.9),
+        scored_crate("d", f32::NAN),
+        scored_crate("e", 0.1),
+    ];
+
+    sort_by_score_desc(&mut crates, |c| c.final_score);
+
+    let names: Vec<&str> = crates.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(&names[..3], &["c", "a", "e"]);
+    assert!(crates[3].final_score.is_nan() && crates[4].final_score.is_nan());
+}
+
+#[test]
+fn test_tied_scores_sort_by_downloads_then_name() {
+    let tied = |name: &str, downloads: i64| RecommendCrate {
+        rank: 0.5,
+        downloads,
+        ..scored_crate(name, 0.5)
+    };
+    let crates = vec![
+        tied("ureq", 100),
+        tied("reqwest", 1000),
+        tied("isahc", 100),
+        RecommendCrate {
+            rank: 0.8,
+            ..scored_crate("hyper", 0.8)
+        },
+        tied("attohttpc", 100),
+    ];
+
+    // 得分相同时下载量高的在前，下载量也相同时按名称升序，与输入顺序无关
+    let expected = ["hyper", "reqwest", "attohttpc", "isahc", "ureq"];
+    for input in [crates.clone(), crates.iter().rev().cloned().collect()] {
+        let mut sorted = input.clone();
+        sort_by_score_desc(&mut sorted, |c| c.final_score);
+        let names: Vec<&str> = sorted.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, expected);
+
+        let ranked = rank_by_keyword_only(input, 100);
+        let names: Vec<&str> = ranked.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, expected);
+    }
+}
+
+#[test]
+fn test_keyword_only_ranking_with_nan_rank() {
+    let crates = vec![
+        RecommendCrate {
+            rank: f32::NAN,
+            ..scored_crate("bad", 0.0)
+        },
+        RecommendCrate {
+            rank: 0.4,
+            ..scored_crate("good", 0.0)
+        },
+    ];
+
+    let ranked = rank_by_keyword_only(crates, 100);
+    assert_eq!(ranked[0].name, "good");
+    assert_eq!(ranked.len(), 2);
+}
+
+#[test]
+fn test_exclude_crates() {
+    let mut crates = vec![
+        scored_crate("reqwest", 0.9),
+        scored_crate("reqwets", 0.8),
+        scored_crate("hyper", 0.7),
+        RecommendCrate {
+            id: "42".to_string(),
+            name: "ureq".to_string(),
+            ..Default::default()
+        },
+    ];
+
+    // 按名称或id排除，其余结果保持原有顺序
+    exclude_crates(&mut crates, &["reqwets".to_string(), "42".to_string()]);
+    let names: Vec<&str> = crates.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["reqwest", "hyper"]);
+
+    exclude_crates(&mut crates, &[]);
+    assert_eq!(crates.len(), 2);
+}
+
+#[test]
+fn test_min_score_filter() {
+    let ranked = vec![
+        scored_crate("serde", 0.9),
+        scored_crate("serde_json", 0.4),
+        scored_crate("json", 0.05),
+    ];
+
+    let mut crates = ranked.clone();
+    filter_by_min_score(&mut crates, 0.3, false);
+    let names: Vec<&str> = crates.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["serde", "serde_json"]);
+
+    // 全部低于阈值时默认保留得分最高的一个
+    let mut crates = ranked.clone();
+    filter_by_min_score(&mut crates, 0.95, false);
+    assert_eq!(crates.len(), 1);
+    assert_eq!(crates[0].name, "serde");
+
+    let mut crates = ranked;
+    filter_by_min_score(&mut crates, 0.95, true);
+    assert!(crates.is_empty());
+}
+
+#[test]
+fn test_name_match_boost() {
+    let mut crates = vec![
+        scored_crate("serde", 0.5),
+        scored_crate("serde-json", 0.5),
+        scored_crate("json", 0.5),
+        scored_crate("miniserde", 0.5),
+    ];
+
+    apply_name_match_boost(&mut crates, "Serde_JSON parser", 0.3, 0.1);
+
+    // 名称相同（忽略大小写和 -/_ 差异）加0.3，名称是查询词前缀加0.1
+    assert!((crates[0].final_score - 0.6).abs() < 1e-6);
+    assert!((crates[1].final_score - 0.8).abs() < 1e-6);
+    assert!((crates[2].final_score - 0.5).abs() < 1e-6);
+    assert!((crates[3].final_score - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_recommend_crate_serde_round_trip() {
+    let crate_item = RecommendCrate {
+        version: Some("0.12.4".to_string()),
+        downloads: 1000,
+        ..scored_crate("reqwest", 0.75)
+    };
+
+    let json = serde_json::to_value(&crate_item).unwrap();
+    assert_eq!(json["name"], "reqwest");
+    assert_eq!(json["version"], "0.12.4");
+    assert_eq!(json["repository"], serde_json::Value::Null);
+
+    let parsed: RecommendCrate = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed.name, crate_item.name);
+    assert_eq!(parsed.downloads, 1000);
+    assert_eq!(parsed.final_score, 0.75);
+
+    let sort_by: SearchSortCriteria = serde_json::from_str("\"Downloads\"").unwrap();
+    assert!(matches!(sort_by, SearchSortCriteria::Downloads));
+}
+
+#[test]
+fn test_negative_cosine_does_not_rank_below_missing_embedding() {
+    let config = SearchConfig::default();
+    let score = |vector_score: f32| {
+        calculate_final_score(
+            0.5,
+            vector_score,
+            None,
+            0,
+            None,
+            &SearchSortCriteria::Comprehensive,
+            &config,
+        )
+    };
+
+    let mut crates = vec![
+        RecommendCrate {
+            downloads: 10,
+            ..scored_crate("dissimilar", score(-0.8))
+        },
+        RecommendCrate {
+            downloads: 100,
+            ..scored_crate("no-embedding", score(0.0))
+        },
+        scored_crate("similar", score(0.7)),
+    ];
+    sort_by_score_desc(&mut crates, |c| c.final_score);
+
+    // 负相似度与缺少向量同样按0计算，得分相同时按下载量排序
+    let names: Vec<&str> = crates.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, ["similar", "no-embedding", "dissimilar"]);
+    assert_eq!(crates[1].final_score, crates[2].final_score);
+    assert_eq!(score(f32::NAN), score(0.0));
+    assert_eq!(score(1.5), score(1.0));
+}
+
+#[test]
+fn test_recency_boost() {
+    let now = Utc::now();
+    let score = |config: &SearchConfig, updated_at: Option<DateTime<Utc>>| {
+        calculate_final_score(
+            0.5,
+            0.5,
+            updated_at,
+            0,
+            None,
+            &SearchSortCriteria::Comprehensive,
+            config,
+        )
+    };
+
+    // 默认不启用，更新时间不影响得分
+    let config = SearchConfig::default();
+    assert_eq!(score(&config, Some(now)), score(&config, None));
+
+    let config = SearchConfig {
+        recency_boost: Some(RecencyBoost {
+            weight: 0.2,
+            half_life_days: 30.0,
+        }),
+        ..SearchConfig::default()
+    };
+    let base = score(&config, None);
+    let fresh = score(&config, Some(now)) - base;
+    let one_half_life = score(&config, Some(now - Duration::days(30))) - base;
+    let stale = score(&config, Some(now - Duration::days(3650))) - base;
+
+    assert!((fresh - 0.2).abs() < 1e-3);
+    assert!((one_half_life - 0.1).abs() < 1e-3);
+    assert!(stale < 1e-3);
+}
+
+#[test]
+fn test_group_by_family() {
+    let crates = vec![
+        scored_crate("reqwest-middleware", 0.9),
+        scored_crate("serde", 0.8),
+        scored_crate("reqwest", 0.7),
+        scored_crate("reqwest_retry", 0.5),
+        scored_crate("serde_json", 0.4),
+        scored_crate("hyper", 0.3),
+    ];
+
+    let groups = group_by_family(&crates);
+    let names: Vec<(&str, Vec<&str>)> = groups
+        .iter()
+        .map(|group| {
+            (
+                group.primary.name.as_str(),
+                group.related.iter().map(|c| c.name.as_str()).collect(),
+            )
+        })
+        .collect();
+
+    // 主结果为家族中得分最高的crate，不一定是与家族同名的crate
+    assert_eq!(
+        names,
+        vec![
+            ("reqwest-middleware", vec!["reqwest", "reqwest_retry"]),
+            ("serde", vec!["serde_json"]),
+            ("hyper", vec![]),
+        ]
+    );
+}
+
+#[test]
+fn test_filter_by_version() {
+    let with_version = |name: &str, version: Option<&str>| RecommendCrate {
+        version: version.map(str::to_string),
+        ..scored_crate(name, 0.5)
+    };
+    let crates = vec![
+        with_version("old", Some("0.9.3")),
+        with_version("current", Some("1.4.0")),
+        with_version("next", Some("2.0.0-alpha.1")),
+        with_version("garbage", Some("latest")),
+        with_version("missing", None),
+    ];
+    let version_req = VersionReq::parse("^1.2").unwrap();
+    let names = |crates: &[RecommendCrate]| -> Vec<String> {
+        crates.iter().map(|c| c.name.clone()).collect()
+    };
+
+    let mut strict = crates.clone();
+    filter_by_version(&mut strict, &version_req, false);
+    assert_eq!(names(&strict), vec!["current"]);
+
+    // 无法解析的版本按需保留
+    let mut lenient = crates;
+    filter_by_version(&mut lenient, &version_req, true);
+    assert_eq!(names(&lenient), vec!["current", "garbage", "missing"]);
+}
+
+#[test]
+fn test_custom_sort_weights() {
+    let config = SearchConfig {
+        recency_boost: Some(RecencyBoost {
+            weight: 0.5,
+            half_life_days: 30.0,
+        }),
+        ..SearchConfig::default()
+    };
+    let score = |sort_by: SearchSortCriteria, downloads: i64, updated_at| {
+        calculate_final_score(0.8, 0.4, updated_at, downloads, None, &sort_by, &config)
+    };
+    let custom = |keyword, vector, downloads, recency| SearchSortCriteria::Custom {
+        keyword,
+        vector,
+        downloads,
+        recency,
+    };
+
+    // 权重按总和归一化，整体缩放不改变得分
+    assert!((score(custom(1.0, 1.0, 0.0, 0.0), 0, None) - 0.6).abs() < 1e-6);
+    assert!((score(custom(3.0, 3.0, 0.0, 0.0), 0, None) - 0.6).abs() < 1e-6);
+
+    // 下载量按对数缩放，1亿次下载为满分
+    let downloads_only = custom(0.0, 0.0, 1.0, 0.0);
+    assert_eq!(score(downloads_only.clone(), 0, None), 0.0);
+    assert!((score(downloads_only.clone(), 100_000_000, None) - 1.0).abs() < 1e-6);
+    assert!(score(downloads_only.clone(), 10_000, None) > score(downloads_only, 100, None));
+
+    // 更新时间得分使用配置的半衰期，不再叠加近期更新加分
+    let recency_only = custom(0.0, 0.0, 0.0, 2.0);
+    let now = Utc::now();
+    assert!((score(recency_only.clone(), 0, Some(now)) - 1.0).abs() < 1e-3);
+    assert!((score(recency_only, 0, Some(now - Duration::days(30))) - 0.5).abs() < 1e-3);
+
+    assert_eq!(score(custom(0.0, 0.0, 0.0, 0.0), 0, None), 0.0);
+}
+
+#[test]
+fn test_graph_boost() {
+    let mut crates = vec![
+        scored_crate("tokio", 0.9),
+        scored_crate("hyper", 0.4),
+        scored_crate("async-std", 0.3),
+        scored_crate("serde", 0.2),
+    ];
+    let edge = |a: &str, b: &str, weight: f32| (a.to_string(), b.to_string(), weight);
+    let edges = vec![
+        edge("hyper", "tokio", 10.0),
+        edge("tokio", "async-std", 5.0),
+        edge("serde", "serde", 20.0),
+        edge("serde", "hyper", 20.0),
+    ];
+    let boost = GraphBoost {
+        weight: 0.2,
+        seed_count: 1,
+        ..GraphBoost::default()
+    };
+
+    apply_graph_boost(&mut crates, &edges, &boost);
+
+    // 只有与种子（tokio）相连的crate加分，按最大边权重归一化，边不区分方向
+    assert!((crates[0].final_score - 0.9).abs() < 1e-6);
+    assert!((crates[1].final_score - 0.5).abs() < 1e-6);
+    assert!((crates[2].final_score - 0.35).abs() < 1e-6);
+    assert!((crates[3].final_score - 0.2).abs() < 1e-6);
+}
+
+#[test]
+fn test_reciprocal_rank_fusion() {
+    let ranking = |names: &[&str]| -> Vec<RecommendCrate> {
+        names.iter().map(|name| scored_crate(name, 1.0)).collect()
+    };
+    let rankings = vec![
+        ranking(&["serde", "tokio", "hyper", "clap"]),
+        ranking(&["tokio", "serde", "clap"]),
+        ranking(&["tokio", "hyper", "serde"]),
+    ];
+
+    let fused = reciprocal_rank_fusion(rankings, 3);
+    let names: Vec<&str> = fused.iter().map(|c| c.name.as_str()).collect();
+    // 每个列表只取前3个，clap只在第二个列表中排第3
+    assert_eq!(names, ["tokio", "serde", "hyper", "clap"]);
+
+    let rrf = |rank: f32| 1.0 / (RRF_K + rank);
+    assert!((fused[0].final_score - (rrf(2.0) + rrf(1.0) + rrf(1.0))).abs() < 1e-6);
+    assert!((fused[3].final_score - rrf(3.0)).abs() < 1e-6);
+
+    assert!(reciprocal_rank_fusion(Vec::new(), 10).is_empty());
+}
+
+#[test]
+fn test_popularity_column_score() {
+    let config = SearchConfig {
+        popularity: Some(PopularityColumn {
+            weight: 0.5,
+            full_score: 1000.0,
+            ..PopularityColumn::default()
+        }),
+        ..SearchConfig::default()
+    };
+    let score = |popularity: Option<f64>, sort_by: SearchSortCriteria| {
+        calculate_final_score(0.5, 0.5, None, 0, popularity, &sort_by, &config)
+    };
+    let base = score(None, SearchSortCriteria::Downloads);
+
+    // 只有下载量排序计入热度，按对数归一化，达到满分值后不再增加
+    assert!((score(Some(1000.0), SearchSortCriteria::Downloads) - base - 0.5).abs() < 1e-6);
+    assert!((score(Some(1e9), SearchSortCriteria::Downloads) - base - 0.5).abs() < 1e-6);
+    let partial = score(Some(30.0), SearchSortCriteria::Downloads) - base;
+    assert!(partial > 0.2 && partial < 0.3);
+    assert_eq!(score(Some(-5.0), SearchSortCriteria::Downloads), base);
+    assert_eq!(
+        score(Some(1000.0), SearchSortCriteria::Relavance),
+        score(None, SearchSortCriteria::Relavance)
+    );
+}
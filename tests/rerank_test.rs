@@ -1,4 +1,4 @@
-use cratespro_search::search::{RecommendCrate, SearchModule, SearchSortCriteria};
+use cratespro_search::search::{truncate, RecommendCrate, SearchModule, SearchSortCriteria};
 use dotenv::dotenv;
 use std::env;
 use tokio_postgres::NoTls;
@@ -71,6 +71,7 @@ async fn test_search_with_sort(
         SearchSortCriteria::Comprehensive => "综合排序",
         SearchSortCriteria::Relavance => "相关性排序",
         SearchSortCriteria::Downloads => "下载量排序",
+        SearchSortCriteria::Custom { .. } => "自定义权重排序",
     };
 
     println!("\n--- {} ---", sort_name);
@@ -93,7 +94,7 @@ async fn test_search_with_sort(
             "{}. {} - {} (关键词得分: {:.4}, 向量得分: {:.4}, 最终得分: {:.4})",
             i + 1,
             crate_info.name,
-            truncate_description(&crate_info.description, 50),
+            truncate(&crate_info.description, 50),
             crate_info.rank,
             crate_info.vector_score,
             crate_info.final_score
@@ -126,12 +127,3 @@ fn validate_ranking(results: &[RecommendCrate]) -> Result<(), Box<dyn std::error
 
     Ok(())
 }
-
-// 截断过长的描述
-fn truncate_description(description: &str, max_length: usize) -> String {
-    if description.len() <= max_length {
-        description.to_string()
-    } else {
-        format!("{}...", &description[..max_length])
-    }
-}
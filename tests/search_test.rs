@@ -1,5 +1,25 @@
-use cratespro_search::search::{SearchModule, SearchSortCriteria};
+use async_trait::async_trait;
+use cratespro_search::search::embedder::{
+    embedding_coverage, precompute_all_embeddings_with_progress, Embedder, EmbeddingMode,
+    MockEmbedder, PrecomputedPolicy,
+};
+use cratespro_search::search::lang::QueryKind;
+use cratespro_search::search::{
+    ensure_schema, escape_like_pattern, extract_keywords_with_config, rebuild_tsv, rerank_crates,
+    retrive_crates, rewrite_query_structured_with_config, rewrite_query_with_config,
+    spawn_cache_warmer, CrateVocabulary, FieldWeights, HybridSearchModule, KeywordScorer,
+    LlmConfig, PopularityColumn, QueryCache, QueryKindWeights, RecommendCrate, RetrievalFallback,
+    SearchConfig, SearchError, SearchEvent, SearchLogger, SearchModule, SearchOptions,
+    SearchRecord, SearchResponse, SearchSortCriteria, SpellingCorrection, StrategyLimits,
+    StructuredRewrite, SubsystemStatus, TermCombination, TraditionalSearchModule, TsQuerySyntax,
+};
 use dotenv::dotenv;
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, Semaphore};
 use tokio_postgres::NoTls;
 
 #[tokio::test]
@@ -42,6 +62,156 @@ async fn test_search_crate() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_hybrid_search() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let hybrid_module = HybridSearchModule::new(&pg_client).await;
+    let results = hybrid_module
+        .search("http client", SearchSortCriteria::Comprehensive)
+        .await?;
+
+    println!("混合搜索找到 {} 个匹配的包", results.len());
+    for (i, crate_info) in results.iter().take(10).enumerate() {
+        println!(
+            "{}. {} (最终得分: {:.4})",
+            i + 1,
+            crate_info.name,
+            crate_info.final_score
+        );
+    }
+
+    // 结果应按最终得分降序排列
+    assert!(results
+        .windows(2)
+        .all(|pair| pair[0].final_score >= pair[1].final_score));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_keyword_scorers() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
+    let scorers = [
+        KeywordScorer::TsRank,
+        KeywordScorer::TsRankCd { norm: 1 },
+        KeywordScorer::Bm25 { k1: 1.2, b: 0.75 },
+    ];
+
+    for scorer in scorers {
+        let config = SearchConfig {
+            keyword_scorer: scorer,
+            ..Default::default()
+        };
+        let results = retrive_crates(&pg_client, &table_name, "http, client", &config).await?;
+
+        println!("{:?}: 找到 {} 个匹配的包", scorer, results.len());
+        assert!(!results.is_empty(), "关键词检索结果不应为空");
+        assert!(results.iter().all(|r| r.rank.is_finite() && r.rank >= 0.0));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_keywords() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
+
+    // 前6个关键词都不会命中，只有排在后面的同义词能命中
+    let keywords = "qqa, qqb, qqc, qqd, qqe, qqf, , qqa, json parser, serde, command line";
+
+    let results =
+        retrive_crates(&pg_client, &table_name, keywords, &SearchConfig::default()).await?;
+    assert!(results.is_empty());
+
+    // 空关键词和重复关键词被跳过，生成的tsquery仍然有效
+    let config = SearchConfig {
+        max_keywords: 20,
+        ..Default::default()
+    };
+    let results = retrive_crates(&pg_client, &table_name, keywords, &config).await?;
+    let names: Vec<&str> = results.iter().map(|c| c.name.as_str()).collect();
+    println!("放宽关键词数量后的结果: {:?}", names);
+    assert!(names.contains(&"serde"));
+    assert!(names.contains(&"clap"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_primary_keyword_boost() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
+    let config = SearchConfig::default();
+
+    let rank_of = |results: &[RecommendCrate], name: &str| {
+        results
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.rank)
+            .unwrap_or(0.0)
+    };
+
+    let serde_first = retrive_crates(&pg_client, &table_name, "serde; json", &config).await?;
+    let json_first = retrive_crates(&pg_client, &table_name, "json; serde", &config).await?;
+
+    // 同样的关键词集合，核心关键词不同，排序随之改变
+    assert_eq!(serde_first.len(), json_first.len());
+    assert!(rank_of(&serde_first, "serde") > rank_of(&serde_first, "json"));
+    assert!(rank_of(&json_first, "json") > rank_of(&json_first, "serde"));
+
+    Ok(())
+}
+
 async fn test_search_term(
     search_module: &SearchModule<'_>,
     term: &str,
@@ -53,6 +223,7 @@ async fn test_search_term(
         SearchSortCriteria::Comprehensive => println!("排序方式: 综合"),
         SearchSortCriteria::Relavance => println!("排序方式: 相关性"),
         SearchSortCriteria::Downloads => println!("排序方式: 下载量"),
+        SearchSortCriteria::Custom { .. } => println!("排序方式: 自定义权重"),
     }
 
     // 执行搜索
@@ -74,3 +245,2978 @@ async fn test_search_term(
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_explain_search() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 关闭LLM改写，使改写结果可预期
+    let search_module = SearchModule::builder()
+        .use_llm_rewrite(false)
+        .build(&pg_client);
+    let explanation = search_module
+        .explain_search("The HTTP client", SearchSortCriteria::Relavance)
+        .await?;
+
+    println!("{:#?}", explanation);
+
+    assert_eq!(explanation.original_query, "The HTTP client");
+    assert!(!explanation.is_natural_language);
+    assert_eq!(explanation.extracted_keywords, None);
+    assert_eq!(explanation.rewritten_query, "http client");
+    assert_eq!(explanation.keywords, vec!["http client".to_string()]);
+    assert_eq!(explanation.tsquery, "http & client:*");
+    assert!(explanation.result_count <= explanation.retrieved_count);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_health_check() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::new(&pg_client).await;
+    let report = search_module.health_check_with(false).await?;
+    println!("{:#?}", report);
+
+    assert!(report.table.is_ok());
+    assert_eq!(report.embedding, SubsystemStatus::Skipped);
+    for column in ["name", "description", "tsv"] {
+        assert!(!report.missing_columns.contains(&column.to_string()));
+    }
+
+    // 不存在的表应报告为失败而不是返回错误
+    let missing_table = SearchModule::builder()
+        .table_name("crates_not_exist")
+        .build(&pg_client);
+    let report = missing_table.health_check_with(false).await?;
+    assert!(!report.table.is_ok());
+    assert!(!report.is_healthy());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_search_multiple_tables() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 使用临时表模拟预发布版本的crate表，连接关闭后自动删除
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_prerelease (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_prerelease SELECT * FROM crates WHERE name = 'reqwest';
+            INSERT INTO crates_prerelease (id, name, description, tsv)
+            VALUES ('pre-1', 'http-next', 'Next generation HTTP client',
+                to_tsvector('english', 'http-next Next generation HTTP client'));",
+        )
+        .await?;
+
+    let search_module = SearchModule::builder()
+        .use_llm_rewrite(false)
+        .build(&pg_client);
+    let options = SearchOptions {
+        tables: vec!["crates".to_string(), "crates_prerelease".to_string()],
+        ..Default::default()
+    };
+    let results = search_module
+        .search_crate_with_options("http", SearchSortCriteria::Relavance, &options)
+        .await?;
+
+    let names: Vec<&str> = results.iter().map(|c| c.name.as_str()).collect();
+    println!("多表搜索结果: {:?}", names);
+
+    // 两个表的结果合并，同时存在于两个表中的crate只出现一次
+    assert!(names.contains(&"http-next"));
+    assert!(names.contains(&"ureq"));
+    assert_eq!(names.iter().filter(|name| **name == "reqwest").count(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancelled_search_leaves_db_consistent() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let snapshot_query = "SELECT id, name, description FROM crates ORDER BY id";
+    let before: Vec<(String, String, String)> = pg_client
+        .query(snapshot_query, &[])
+        .await?
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2)))
+        .collect();
+
+    let search_module = SearchModule::builder()
+        .use_llm_rewrite(false)
+        .build(&pg_client);
+
+    // 在不同的时间点取消搜索
+    for timeout_ms in [0, 1, 5, 20] {
+        let _ = tokio::time::timeout(
+            Duration::from_millis(timeout_ms),
+            search_module.search_crate("http client", SearchSortCriteria::Comprehensive),
+        )
+        .await;
+    }
+
+    let after: Vec<(String, String, String)> = pg_client
+        .query(snapshot_query, &[])
+        .await?
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2)))
+        .collect();
+    assert_eq!(before, after);
+
+    // 取消后同一连接仍可正常完成搜索
+    let results = search_module
+        .search_crate("http client", SearchSortCriteria::Comprehensive)
+        .await?;
+    assert!(!results.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_search_crate_stream() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .use_llm_rewrite(false)
+        .build(&pg_client);
+
+    let (sender, mut receiver) = mpsc::channel(4);
+    let search =
+        search_module.search_crate_stream("http client", SearchSortCriteria::Comprehensive, sender);
+    let collect = async {
+        let mut events = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            events.push(event);
+        }
+        events
+    };
+    let (result, events) = tokio::join!(search, collect);
+    result?;
+
+    // 先收到关键词结果，再收到重排序后的最终结果
+    assert_eq!(events.len(), 2);
+    match (&events[0], &events[1]) {
+        (SearchEvent::KeywordResults(keyword), SearchEvent::Reranked(ranked)) => {
+            assert!(!keyword.is_empty());
+            assert_eq!(keyword.len(), ranked.len());
+        }
+        other => panic!("事件顺序不符合预期: {:?}", other),
+    }
+
+    Ok(())
+}
+
+// 记录调用次数的嵌入提供者，用于确认离线模式下没有调用外部接口
+struct CountingEmbedder {
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl Embedder for CountingEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(texts.iter().map(|_| vec![1.0, 0.0]).collect())
+    }
+}
+
+#[tokio::test]
+async fn test_offline_mode() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let config = SearchConfig {
+        offline: true,
+        ..Default::default()
+    };
+    // 即使配置了API密钥，离线模式也不应访问LLM接口
+    let search_module = SearchModule::builder()
+        .config(config)
+        .api_key("sk-test")
+        .chat_url("http://127.0.0.1:9/v1/chat/completions")
+        .embedder(CountingEmbedder {
+            calls: calls.clone(),
+        })
+        .build(&pg_client);
+
+    let results = search_module
+        .search_crate(
+            "how can I send http requests?",
+            SearchSortCriteria::Comprehensive,
+        )
+        .await?;
+
+    assert!(!results.is_empty());
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    assert!(results
+        .iter()
+        .all(|c| c.vector_score == 0.0 && c.final_score == c.rank));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_precomputed_policy() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 临时表中没有任何预先计算的嵌入向量
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_without_vectors (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_without_vectors SELECT * FROM crates;
+            ALTER TABLE crates_without_vectors DROP COLUMN IF EXISTS embedding;",
+        )
+        .await?;
+
+    let search_with = |policy: PrecomputedPolicy| {
+        let config = SearchConfig {
+            embedding_mode: EmbeddingMode::Precomputed,
+            precomputed_policy: policy,
+            ..Default::default()
+        };
+        SearchModule::builder()
+            .table_name("crates_without_vectors")
+            .use_llm_rewrite(false)
+            .config(config)
+            .embedder(CountingEmbedder {
+                calls: Arc::new(AtomicUsize::new(0)),
+            })
+            .build(&pg_client)
+    };
+
+    let strict = search_with(PrecomputedPolicy::Error);
+    assert!(strict
+        .search_crate("http client", SearchSortCriteria::Comprehensive)
+        .await
+        .is_err());
+
+    let fallback = search_with(PrecomputedPolicy::FallbackToKeyword);
+    let results = fallback
+        .search_crate("http client", SearchSortCriteria::Comprehensive)
+        .await?;
+    assert!(!results.is_empty());
+    assert!(results.iter().all(|c| c.vector_score == 0.0));
+
+    // 只有部分crate缺少向量时，这些crate按关键词得分，其余crate仍使用向量得分
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_partial_vectors (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_partial_vectors SELECT * FROM crates;
+            UPDATE crates_partial_vectors SET embedding = NULL WHERE name <> 'reqwest';",
+        )
+        .await?;
+    let partial = SearchModule::builder()
+        .table_name("crates_partial_vectors")
+        .use_llm_rewrite(false)
+        .config(SearchConfig {
+            embedding_mode: EmbeddingMode::Precomputed,
+            ..Default::default()
+        })
+        .embedder(MockEmbedder::new(8))
+        .build(&pg_client);
+    let outcome = partial
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert!(outcome.used_vector_rerank);
+    assert!(outcome
+        .fallback_reasons
+        .iter()
+        .any(|reason| reason.contains("仅关键词得分")));
+    assert!(outcome
+        .results
+        .iter()
+        .filter(|c| c.name != "reqwest")
+        .all(|c| c.vector_score == 0.0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embedding_coverage() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE coverage_crates (id text PRIMARY KEY, embedding real[]);
+            INSERT INTO coverage_crates VALUES
+                ('1', '{0.1, 0.2}'), ('2', NULL), ('3', '{0.3, 0.4}'), ('4', NULL);",
+        )
+        .await?;
+
+    let stats = embedding_coverage(&pg_client, "coverage_crates", &SearchConfig::default()).await?;
+    assert_eq!(stats.total, 4);
+    assert_eq!(stats.with_embedding, 2);
+    assert_eq!(stats.without_embedding, 2);
+    assert!((stats.ratio() - 0.5).abs() < 1e-9);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embedding_coverage_with_embeddings_table() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // crate表本身没有 embedding 列，向量只存放在独立的嵌入表中，且嵌入表缺少部分crate的行
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE split_crates (id text PRIMARY KEY, name text);
+            INSERT INTO split_crates VALUES ('1', 'a'), ('2', 'b'), ('3', 'c'), ('4', 'd');
+            CREATE TEMP TABLE split_embeddings (id text PRIMARY KEY, embedding real[]);
+            INSERT INTO split_embeddings VALUES ('1', '{0.1, 0.2}'), ('2', NULL), ('3', '{0.3, 0.4}');",
+        )
+        .await?;
+
+    let config = SearchConfig {
+        embeddings_table: Some("split_embeddings".to_string()),
+        ..Default::default()
+    };
+    let stats = embedding_coverage(&pg_client, "split_crates", &config).await?;
+    assert_eq!(stats.total, 4);
+    assert_eq!(stats.with_embedding, 2);
+    assert_eq!(stats.without_embedding, 2);
+
+    // 不合法的嵌入表名在拼接进SQL前被拒绝
+    let config = SearchConfig {
+        embeddings_table: Some("split_embeddings; DROP TABLE split_crates".to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        embedding_coverage(&pg_client, "split_crates", &config).await,
+        Err(SearchError::Config(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_builder_rejects_invalid_identifiers() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 不合法的嵌入表名在构建时报错，而不是忽略后使用crate表的 embedding 列
+    let result = SearchModule::builder()
+        .embeddings_table("split_embeddings; DROP TABLE crates")
+        .try_build(&pg_client);
+    assert!(matches!(result, Err(SearchError::Config(_))));
+
+    let result = SearchModule::builder()
+        .config(SearchConfig {
+            embeddings_table: Some("split embeddings".to_string()),
+            ..Default::default()
+        })
+        .try_build(&pg_client);
+    assert!(matches!(result, Err(SearchError::Config(_))));
+
+    // 不合法的嵌入文本列名同样在构建时报错
+    let result = SearchModule::builder()
+        .config(SearchConfig {
+            embedding_text_column: Some("search_text, id".to_string()),
+            ..Default::default()
+        })
+        .try_build(&pg_client);
+    assert!(matches!(result, Err(SearchError::Config(_))));
+
+    assert!(SearchModule::builder()
+        .embeddings_table("split_embeddings")
+        .try_build(&pg_client)
+        .is_ok());
+
+    Ok(())
+}
+
+// 对描述中含有 "broken" 的文本返回空向量，其余文本返回固定向量
+struct PartiallyFailingEmbedder;
+
+#[async_trait]
+impl Embedder for PartiallyFailingEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        Ok(texts
+            .iter()
+            .map(|text| {
+                if text.contains("broken") {
+                    Vec::new()
+                } else {
+                    vec![1.0, 0.0]
+                }
+            })
+            .collect())
+    }
+}
+
+#[tokio::test]
+async fn test_precompute_progress_counts_failures() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE progress_crates
+                (id text PRIMARY KEY, name text, description text, embedding vector(2));
+            INSERT INTO progress_crates (id, name, description) VALUES
+                ('1', 'a', 'ok'), ('2', 'b', 'broken'), ('3', 'c', 'ok'),
+                ('4', 'd', 'broken'), ('5', 'e', 'ok');",
+        )
+        .await?;
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let summary = precompute_all_embeddings_with_progress(
+        &pg_client,
+        "progress_crates",
+        2,
+        &PartiallyFailingEmbedder,
+        tx,
+        &SearchConfig::default(),
+    )
+    .await?;
+    assert_eq!(summary.succeeded, 3);
+    assert_eq!(summary.failed, 2);
+
+    let mut events = Vec::new();
+    while let Some(event) = rx.recv().await {
+        events.push(event);
+    }
+    // 有失败的crate时最后一次进度仍达到总数
+    let last = events.last().expect("应发送进度事件");
+    assert_eq!(last.processed, last.total);
+    assert_eq!(last.total, 5);
+    assert_eq!(last.failed, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_precompute_with_embedding_text_column() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE text_column_crates
+                (id text PRIMARY KEY, name text, description text, search_text text, embedding vector(8));
+            INSERT INTO text_column_crates (id, name, description, search_text) VALUES
+                ('1', 'a', 'raw description', 'curated text'), ('2', 'b', 'only description', ''),
+                ('3', 'c', NULL, NULL), ('4', NULL, NULL, NULL);",
+        )
+        .await?;
+
+    let texts = Arc::new(Mutex::new(Vec::new()));
+    let (tx, _rx) = mpsc::channel(16);
+    let config = SearchConfig {
+        embedding_text_column: Some("search_text".to_string()),
+        ..Default::default()
+    };
+    let summary = precompute_all_embeddings_with_progress(
+        &pg_client,
+        "text_column_crates",
+        10,
+        &RecordingEmbedder {
+            texts: texts.clone(),
+        },
+        tx,
+        &config,
+    )
+    .await?;
+
+    // 嵌入文本列为空时仍使用描述，描述为NULL时只用名称；没有任何文本的crate跳过并记为失败
+    let mut texts = texts.lock().unwrap().clone();
+    texts.sort();
+    assert_eq!(texts, vec!["a : curated text", "b : only description", "c"]);
+    assert_eq!(summary.failed_ids, vec!["4".to_string()]);
+
+    // 不合法的列名在拼接进SQL前被拒绝
+    let (tx, _rx) = mpsc::channel(16);
+    let config = SearchConfig {
+        embedding_text_column: Some("search_text, id".to_string()),
+        ..Default::default()
+    };
+    assert!(precompute_all_embeddings_with_progress(
+        &pg_client,
+        "text_column_crates",
+        10,
+        &PartiallyFailingEmbedder,
+        tx,
+        &config,
+    )
+    .await
+    .is_err());
+
+    Ok(())
+}
+
+// 总是失败的嵌入提供者，模拟嵌入接口不可用
+struct FailingEmbedder;
+
+#[async_trait]
+impl Embedder for FailingEmbedder {
+    async fn embed(&self, _texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        Err("嵌入接口不可用".into())
+    }
+}
+
+#[tokio::test]
+async fn test_search_outcome_reports_fallbacks() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // LLM接口和嵌入接口都不可用，搜索仍返回仅关键词排序的结果
+    let degraded = SearchModule::builder()
+        .api_key("sk-test")
+        .chat_url("http://127.0.0.1:9/v1/chat/completions")
+        .embedder(FailingEmbedder)
+        .build(&pg_client);
+    let outcome = degraded
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+
+    assert!(!outcome.results.is_empty());
+    assert!(!outcome.used_llm_rewrite);
+    assert!(!outcome.used_vector_rerank);
+    assert_eq!(outcome.fallback_reasons.len(), 2);
+
+    // 主动关闭LLM不计入降级原因
+    let healthy = SearchModule::builder()
+        .use_llm_rewrite(false)
+        .embedder(CountingEmbedder {
+            calls: Arc::new(AtomicUsize::new(0)),
+        })
+        .build(&pg_client);
+    let outcome = healthy
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+
+    assert!(!outcome.used_llm_rewrite);
+    assert!(outcome.used_vector_rerank);
+    assert!(outcome.fallback_reasons.is_empty());
+
+    Ok(())
+}
+
+// 输入超过长度上限时整批失败的嵌入提供者，模拟嵌入模型的token上限
+struct LengthLimitedEmbedder {
+    max_chars: usize,
+}
+
+#[async_trait]
+impl Embedder for LengthLimitedEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        if texts
+            .iter()
+            .any(|text| text.chars().count() > self.max_chars)
+        {
+            return Err("输入超过模型的最大长度".into());
+        }
+        Ok(texts.iter().map(|_| vec![1.0, 0.0]).collect())
+    }
+}
+
+#[tokio::test]
+async fn test_oversized_description_is_truncated() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_long_description (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_long_description SELECT * FROM crates;
+            UPDATE crates_long_description
+            SET description = description || repeat(' an http client for rust', 5000)
+            WHERE name = 'reqwest';",
+        )
+        .await?;
+
+    let search_module = SearchModule::builder()
+        .table_name("crates_long_description")
+        .use_llm_rewrite(false)
+        .config(SearchConfig {
+            persist_on_demand: false,
+            ..Default::default()
+        })
+        .embedder(LengthLimitedEmbedder { max_chars: 8000 })
+        .build(&pg_client);
+
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+
+    assert!(outcome.results.iter().any(|c| c.name == "reqwest"));
+    assert!(outcome.used_vector_rerank);
+    assert!(outcome.fallback_reasons.is_empty());
+
+    // 上限更小的嵌入模型通过配置缩短嵌入文本
+    let search_module = SearchModule::builder()
+        .table_name("crates_long_description")
+        .use_llm_rewrite(false)
+        .config(SearchConfig {
+            persist_on_demand: false,
+            max_embedding_chars: 512,
+            ..Default::default()
+        })
+        .embedder(LengthLimitedEmbedder { max_chars: 512 })
+        .build(&pg_client);
+
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert!(outcome.used_vector_rerank);
+    assert!(outcome.fallback_reasons.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_search_facets() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .use_llm_rewrite(false)
+        .config(SearchConfig {
+            offline: true,
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    let without_facets = search_module
+        .search_crate_with_outcome(
+            "http",
+            SearchSortCriteria::Relavance,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert!(without_facets.facets.is_empty());
+
+    let options = SearchOptions {
+        with_facets: true,
+        ..Default::default()
+    };
+    let outcome = search_module
+        .search_crate_with_outcome("http", SearchSortCriteria::Relavance, &options)
+        .await?;
+    println!("分面统计: {:?}", outcome.facets);
+
+    // 匹配的crate中 reqwest 和 hyper 带有 http 关键词，且 reqwest 在http-client分类下
+    assert_eq!(outcome.facets.get("http"), Some(&2));
+    assert_eq!(outcome.facets.get("web-programming::http-client"), Some(&1));
+    assert_eq!(outcome.facets.get("network-programming"), Some(&1));
+    assert!(!outcome.facets.contains_key("encoding"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_field_weights() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 一个crate的名称命中查询词，另一个只在描述中命中
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_weighted (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_weighted (id, name, description) VALUES
+                ('1', 'tomlkit', 'style preserving parser'),
+                ('2', 'confparse', 'toml parser with span information');
+            UPDATE crates_weighted SET tsv =
+                setweight(to_tsvector('english', name), 'A') ||
+                setweight(to_tsvector('english', description), 'B');",
+        )
+        .await?;
+
+    let top_with = |weights: FieldWeights| {
+        let config = SearchConfig {
+            field_weights: Some(weights),
+            ..Default::default()
+        };
+        let pg_client = &pg_client;
+        async move {
+            let results = retrive_crates(pg_client, "crates_weighted", "toml", &config).await?;
+            assert_eq!(results.len(), 2);
+            Ok::<_, Box<dyn std::error::Error>>(results[0].name.clone())
+        }
+    };
+
+    assert_eq!(top_with(FieldWeights::default()).await?, "tomlkit");
+    assert_eq!(
+        top_with(FieldWeights {
+            name: 0.1,
+            description: 1.0,
+        })
+        .await?,
+        "confparse"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_diagnose_crate() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .config(SearchConfig {
+            offline: true,
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    let matched = search_module.diagnose("http", "reqwest").await?;
+    println!("诊断结果: {:?}", matched);
+    assert!(matched.exists);
+    assert!(matched.matches_tsquery);
+    assert!(matched.ts_rank.is_some_and(|rank| rank > 0.0));
+    assert_eq!(matched.vector_score, None);
+
+    let unmatched = search_module.diagnose("http", "serde").await?;
+    assert!(unmatched.exists);
+    assert!(!unmatched.matches_tsquery);
+
+    let missing = search_module.diagnose("http", "no-such-crate").await?;
+    assert!(!missing.exists);
+    assert_eq!(missing.ts_rank, None);
+    assert!(!missing.has_embedding);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_term_combination() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
+    // 核心关键词为 http、client，同义词为 json
+    let keywords = "http, client; json";
+
+    let names_with = |term_combination: TermCombination| {
+        let config = SearchConfig {
+            term_combination,
+            ..Default::default()
+        };
+        let pg_client = &pg_client;
+        let table_name = &table_name;
+        async move {
+            let results = retrive_crates(pg_client, table_name, keywords, &config).await?;
+            let mut names: Vec<String> = results.into_iter().map(|c| c.name).collect();
+            names.sort();
+            Ok::<_, Box<dyn std::error::Error>>(names)
+        }
+    };
+
+    let any = names_with(TermCombination::Or).await?;
+    let all = names_with(TermCombination::And).await?;
+    let primary = names_with(TermCombination::PrimaryAnd).await?;
+    println!("OR: {:?}, AND: {:?}, 核心AND: {:?}", any, all, primary);
+
+    // 没有crate同时命中 http、client 和 json
+    assert!(all.is_empty());
+    // 同时命中 http 和 client，或者命中 json
+    assert_eq!(primary, vec!["json", "reqwest", "serde_json", "ureq"]);
+    // 只命中 http 的 hyper 只出现在OR结果中
+    assert!(any.contains(&"hyper".to_string()));
+    assert!(primary.iter().all(|name| any.contains(name)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_websearch_tsquery_syntax() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
+    let config_with =
+        |term_combination: TermCombination, tsquery_syntax: TsQuerySyntax| SearchConfig {
+            term_combination,
+            tsquery_syntax,
+            ..Default::default()
+        };
+
+    // 与 to_tsquery 相同的组合语义：同时命中 http 和 client，或者命中 json
+    let results = retrive_crates(
+        &pg_client,
+        &table_name,
+        "http, client; json",
+        &config_with(TermCombination::PrimaryAnd, TsQuerySyntax::WebSearch),
+    )
+    .await?;
+    let mut names: Vec<String> = results.into_iter().map(|c| c.name).collect();
+    names.sort();
+    assert_eq!(names, vec!["json", "reqwest", "serde_json", "ureq"]);
+
+    // 含特殊字符的关键词拼接进 to_tsquery 会产生语法错误，网页搜索语法可以正常解析
+    let odd_keywords = "c++, tokio::spawn, \"http client";
+    assert!(retrive_crates(
+        &pg_client,
+        &table_name,
+        odd_keywords,
+        &config_with(TermCombination::Or, TsQuerySyntax::ToTsQuery),
+    )
+    .await
+    .is_err());
+    let results = retrive_crates(
+        &pg_client,
+        &table_name,
+        odd_keywords,
+        &config_with(TermCombination::Or, TsQuerySyntax::WebSearch),
+    )
+    .await?;
+    assert!(results.iter().any(|c| c.name == "reqwest"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retrieval_fallbacks() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_with = |retrieval_fallbacks: Vec<RetrievalFallback>| {
+        SearchModule::builder()
+            .config(SearchConfig {
+                offline: true,
+                retrieval_fallbacks,
+                ..Default::default()
+            })
+            .build(&pg_client)
+    };
+
+    // 拼写错误的查询不会命中tsquery
+    let strict = search_with(Vec::new())
+        .search_crate_with_outcome(
+            "reqwset",
+            SearchSortCriteria::Relavance,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert!(strict.results.is_empty());
+
+    let outcome = search_with(vec![
+        RetrievalFallback::WebSearch,
+        RetrievalFallback::Trigram,
+    ])
+    .search_crate_with_outcome(
+        "reqwset",
+        SearchSortCriteria::Relavance,
+        &SearchOptions::default(),
+    )
+    .await?;
+    println!("后备检索结果: {:?}", outcome.fallback_reasons);
+    assert_eq!(outcome.results[0].name, "reqwest");
+    assert!(outcome.fallback_reasons[0].contains("Trigram"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rerank_with_mock_embedder() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 关键词得分相同，只有描述与查询的语义相似度不同
+    let candidate = |id: &str, name: &str, description: &str| RecommendCrate {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        rank: 0.5,
+        ..Default::default()
+    };
+    let crates = vec![
+        candidate("mock-1", "jsonkit", "fast json parser"),
+        candidate(
+            "mock-2",
+            "netkit",
+            "async http client with connection pooling",
+        ),
+    ];
+
+    let config = SearchConfig {
+        persist_on_demand: false,
+        ..Default::default()
+    };
+    let results = rerank_crates(
+        crates,
+        "http client",
+        SearchSortCriteria::Comprehensive,
+        &pg_client,
+        "crates",
+        &MockEmbedder::default(),
+        &config,
+        &SearchOptions::default(),
+    )
+    .await?;
+
+    assert_eq!(results[0].name, "netkit");
+    assert!(results[0].vector_score > results[1].vector_score);
+    let expected = 0.6 * 0.5 + 0.4 * results[0].vector_score;
+    assert!((results[0].final_score - expected).abs() < 1e-5);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retrieved_keywords() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
+    let results = retrive_crates(&pg_client, &table_name, "http", &SearchConfig::default()).await?;
+
+    let keywords_of = |name: &str| {
+        results
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.keywords.clone())
+    };
+    assert_eq!(
+        keywords_of("reqwest"),
+        Some(vec![
+            "http".to_string(),
+            "client".to_string(),
+            "request".to_string()
+        ])
+    );
+    // 关键词为NULL时返回空列表
+    assert_eq!(keywords_of("ureq"), Some(Vec::new()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_total_matches() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 候选数量上限小于匹配总数
+    let search_module = SearchModule::builder()
+        .config(SearchConfig {
+            offline: true,
+            candidate_limit: 1,
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    let without_count = search_module
+        .search_crate_with_outcome(
+            "http",
+            SearchSortCriteria::Relavance,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert_eq!(without_count.total_matches, None);
+
+    let options = SearchOptions {
+        with_total_count: true,
+        ..Default::default()
+    };
+    let outcome = search_module
+        .search_crate_with_outcome("http", SearchSortCriteria::Relavance, &options)
+        .await?;
+    assert_eq!(outcome.results.len(), 1);
+    // reqwest、hyper 和 ureq
+    assert_eq!(outcome.total_matches, Some(3));
+
+    // 排除的crate不计入匹配总数
+    let options = SearchOptions {
+        with_total_count: true,
+        exclude: vec!["reqwest".to_string()],
+        ..Default::default()
+    };
+    let outcome = search_module
+        .search_crate_with_outcome("http", SearchSortCriteria::Relavance, &options)
+        .await?;
+    assert_eq!(outcome.total_matches, Some(2));
+    assert!(outcome.results.iter().all(|c| c.name != "reqwest"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_exclude_crates() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .config(SearchConfig {
+            offline: true,
+            result_limit: Some(2),
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    // reqwest、hyper 和 ureq 都匹配 http，排除一个后仍返回 result_limit 个结果
+    let options = SearchOptions {
+        exclude: vec!["reqwest".to_string()],
+        ..Default::default()
+    };
+    let outcome = search_module
+        .search_crate_with_outcome("http", SearchSortCriteria::Relavance, &options)
+        .await?;
+    assert_eq!(outcome.results.len(), 2);
+    assert!(outcome.results.iter().all(|c| c.name != "reqwest"));
+
+    // 超过SQL排除上限的列表在重排序后过滤
+    let mut exclude: Vec<String> = (0..200).map(|i| format!("blocked-{}", i)).collect();
+    exclude.push("hyper".to_string());
+    let options = SearchOptions {
+        exclude,
+        ..Default::default()
+    };
+    let outcome = search_module
+        .search_crate_with_outcome("http", SearchSortCriteria::Relavance, &options)
+        .await?;
+    assert!(!outcome.results.is_empty());
+    assert!(outcome.results.iter().all(|c| c.name != "hyper"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_like_wildcards_match_literally() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 未转义时 "%_%" 会匹配任意非空名称
+    let pattern = format!("%{}%", escape_like_pattern("_"));
+    let rows = pg_client
+        .query(
+            "SELECT name FROM crates WHERE name ILIKE $1 ESCAPE '\\'",
+            &[&pattern],
+        )
+        .await?;
+    assert!(!rows.is_empty());
+    assert!(rows
+        .iter()
+        .all(|row| row.get::<_, String>("name").contains('_')));
+
+    let search_module = TraditionalSearchModule::new(&pg_client).await;
+    for query in ["100%_complete", "%", "\\"] {
+        let results = search_module
+            .search(query, SearchSortCriteria::Relavance)
+            .await?;
+        assert!(results.is_empty(), "{:?} 不应匹配任何crate", query);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_traditional_strategy_limits() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let default_results = TraditionalSearchModule::new(&pg_client)
+        .await
+        .search("http client", SearchSortCriteria::Relavance)
+        .await?;
+    assert!(default_results.len() > 1);
+
+    // 只保留全文搜索的一个候选
+    let limited_results = TraditionalSearchModule::new(&pg_client)
+        .await
+        .with_strategy_limits(StrategyLimits {
+            exact: 0,
+            prefix: 0,
+            fulltext: 1,
+            advanced_fulltext: 0,
+        })
+        .search("http client", SearchSortCriteria::Relavance)
+        .await?;
+    assert!(!limited_results.is_empty());
+    assert!(limited_results.len() < default_results.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rebuild_tsv() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 批量导入后tsv列为空，检索不到任何crate
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_reindexed (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_reindexed (id, name, description) VALUES
+                ('1', 'tomlkit', 'style preserving parser'),
+                ('2', 'confparse', 'toml parser with span information');",
+        )
+        .await?;
+    let config = SearchConfig::default();
+    assert!(
+        retrive_crates(&pg_client, "crates_reindexed", "toml", &config)
+            .await?
+            .is_empty()
+    );
+
+    assert_eq!(
+        rebuild_tsv(&pg_client, "crates_reindexed", "english").await?,
+        2
+    );
+    let results = retrive_crates(&pg_client, "crates_reindexed", "toml", &config).await?;
+    assert_eq!(results.len(), 2);
+    // 名称命中的权重高于描述命中
+    assert_eq!(results[0].name, "tomlkit");
+
+    // 无效的文本搜索配置返回数据库错误
+    assert!(matches!(
+        rebuild_tsv(&pg_client, "crates_reindexed", "no_such_config").await,
+        Err(SearchError::Database(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ensure_schema() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // 只有必需列的新表
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_bare (id text PRIMARY KEY, name text, description text);
+            INSERT INTO crates_bare (id, name, description) VALUES
+                ('1', 'tomlkit', 'style preserving parser'),
+                ('2', 'confparse', 'toml parser with span information');
+            CREATE TEMP TABLE crates_incomplete (id text, name text);",
+        )
+        .await?;
+
+    // 没有pgvector扩展的数据库在创建embedding列之前报配置错误，tsv列已经补全
+    match ensure_schema(&pg_client, "crates_bare", 8, &SearchConfig::default()).await {
+        Ok(()) => {
+            let embedding_type: String = pg_client
+                .query_one(
+                    "SELECT format_type(atttypid, atttypmod) FROM pg_attribute
+                    WHERE attrelid = 'crates_bare'::regclass AND attname = 'embedding'",
+                    &[],
+                )
+                .await?
+                .get(0);
+            assert_eq!(embedding_type, "vector(8)");
+        }
+        Err(SearchError::Config(message)) => assert!(message.contains("vector")),
+        Err(e) => return Err(e.into()),
+    }
+    let config = SearchConfig::default();
+    let results = retrive_crates(&pg_client, "crates_bare", "toml", &config).await?;
+    assert_eq!(results.len(), 2);
+
+    // 缺少必需列或数据表不存在时返回配置错误
+    assert!(matches!(
+        ensure_schema(&pg_client, "crates_incomplete", 8, &SearchConfig::default()).await,
+        Err(SearchError::Config(message)) if message.contains("description")
+    ));
+    assert!(matches!(
+        ensure_schema(&pg_client, "crates_missing", 8, &SearchConfig::default()).await,
+        Err(SearchError::Config(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_text_search_config() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // tsv列使用simple配置生成，保留english配置中的停用词
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_simple (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_simple (id, name, description) VALUES
+                ('1', 'once', 'a cell which can be written to only once');",
+        )
+        .await?;
+    rebuild_tsv(&pg_client, "crates_simple", "simple").await?;
+
+    // 默认配置下 "once" 是english停用词，无法检索到
+    let results = retrive_crates(
+        &pg_client,
+        "crates_simple",
+        "once",
+        &SearchConfig::default(),
+    )
+    .await?;
+    assert!(results.is_empty());
+
+    let config = SearchConfig {
+        text_search_config: Some("simple".to_string()),
+        ..Default::default()
+    };
+    let results = retrive_crates(&pg_client, "crates_simple", "once", &config).await?;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "once");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_suggest() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .build(&pg_client);
+
+    // 拼写错误的查询没有匹配结果，建议最接近的crate名称
+    let suggestions = search_module.suggest("reqest").await;
+    assert_eq!(suggestions.first().map(String::as_str), Some("reqwest"));
+    assert!(suggestions.len() <= 3);
+
+    // 查询本身有结果时不给出建议
+    assert!(search_module.suggest("reqwest").await.is_empty());
+
+    Ok(())
+}
+
+// 启动对所有请求都返回同一改写结果的Chat Completions服务，返回接口地址
+async fn serve_chat_completion(content: &'static str) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            // 请求体很小，读到请求头结束即可（忽略请求体）
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 4096];
+            while !String::from_utf8_lossy(&buffer).contains("\r\n\r\n") {
+                let n = socket.read(&mut chunk).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                buffer.extend_from_slice(&chunk[..n]);
+            }
+            let body = serde_json::json!({ "choices": [{ "message": { "content": content } }] })
+                .to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    });
+    format!("http://{}/v1/chat/completions", addr)
+}
+
+#[tokio::test]
+async fn test_original_terms_weight() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    // LLM把查询改写成了不相关的关键词
+    let chat_url = serve_chat_completion("tokio, async").await;
+    let build = |original_terms_weight: Option<f32>| {
+        SearchModule::builder()
+            .table_name("crates")
+            .api_key("test-key")
+            .chat_url(chat_url.clone())
+            .use_llm_rewrite(true)
+            .embedder(MockEmbedder::default())
+            .config(SearchConfig {
+                original_terms_weight,
+                ..Default::default()
+            })
+            .build(&pg_client)
+    };
+
+    // 只使用改写结果时，原始查询的意图丢失
+    let results = build(None)
+        .search_crate("ureq", SearchSortCriteria::Relavance)
+        .await?;
+    assert!(results.iter().all(|c| c.name != "ureq"));
+
+    // 保留原始查询词并加权后，命中原始查询词的crate排在最前
+    let search_module = build(Some(2.0));
+    let explanation = search_module
+        .explain_search("ureq", SearchSortCriteria::Relavance)
+        .await?;
+    assert!(explanation.keywords.contains(&"ureq".to_string()));
+    let results = search_module
+        .search_crate("ureq", SearchSortCriteria::Relavance)
+        .await?;
+    assert_eq!(results.first().map(|c| c.name.as_str()), Some("ureq"));
+    assert!(results.iter().any(|c| c.name == "tokio"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_result_filter() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_licensed (LIKE crates INCLUDING ALL);
+            ALTER TABLE crates_licensed ADD COLUMN license text, ADD COLUMN yanked boolean;
+            INSERT INTO crates_licensed (id, name, description, license, yanked) VALUES
+                ('1', 'http-a', 'http client', 'MIT', false),
+                ('2', 'http-b', 'http client', NULL, false),
+                ('3', 'http-c', 'http client', 'MIT', true),
+                ('4', 'http-d', 'http client', 'Apache-2.0', false),
+                ('5', 'http-e', 'http client', 'MIT', false);",
+        )
+        .await?;
+    rebuild_tsv(&pg_client, "crates_licensed", "english").await?;
+
+    let search_module = SearchModule::builder()
+        .table_name("crates_licensed")
+        .config(SearchConfig {
+            offline: true,
+            result_limit: Some(2),
+            metadata_columns: vec!["license".to_string(), "yanked".to_string()],
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    let options = SearchOptions::default().with_filter(|c: &RecommendCrate| {
+        c.metadata.contains_key("license") && c.metadata.get("yanked") != Some(&true.into())
+    });
+    let results = search_module
+        .search_crate_with_options("http", SearchSortCriteria::Relavance, &options)
+        .await?;
+
+    // 先过滤再截断，结果数量仍为 result_limit
+    assert_eq!(results.len(), 2);
+    for crate_item in &results {
+        assert!(!["http-b", "http-c"].contains(&crate_item.name.as_str()));
+        assert!(crate_item.metadata.contains_key("license"));
+    }
+
+    Ok(())
+}
+
+// 响应很慢的嵌入接口
+struct SlowEmbedder;
+
+#[async_trait]
+impl Embedder for SlowEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Ok(texts.iter().map(|_| vec![1.0, 0.0]).collect())
+    }
+}
+
+#[tokio::test]
+async fn test_rerank_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .use_llm_rewrite(false)
+        .embedder(SlowEmbedder)
+        .config(SearchConfig {
+            rerank_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    // 嵌入向量超时后立即返回按关键词得分排序的结果
+    let started = std::time::Instant::now();
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(!outcome.results.is_empty());
+    assert!(!outcome.used_vector_rerank);
+    assert_eq!(outcome.fallback_reasons.len(), 1);
+    assert!(outcome.fallback_reasons[0].contains("100"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shared_embedding_permits() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_without_embeddings (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_without_embeddings SELECT * FROM crates;
+            UPDATE crates_without_embeddings SET embedding = NULL;",
+        )
+        .await?;
+
+    // 许可被其他模块占满时，按需生成嵌入等待许可直到超时
+    let permits = Arc::new(Semaphore::new(1));
+    let held = permits.clone().acquire_owned().await?;
+    let search_module = SearchModule::builder()
+        .table_name("crates_without_embeddings")
+        .use_llm_rewrite(false)
+        .embedder(MockEmbedder::new(8))
+        .embedding_permits(permits.clone())
+        .config(SearchConfig {
+            persist_on_demand: false,
+            rerank_timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert!(!outcome.used_vector_rerank);
+    assert!(outcome.fallback_reasons[0].contains("200"));
+
+    // 释放许可后正常重排序
+    drop(held);
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert!(outcome.used_vector_rerank);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stage_timings() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .use_llm_rewrite(false)
+        .embedder(SlowEmbedder)
+        .config(SearchConfig {
+            rerank_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    // 默认不统计耗时
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert_eq!(outcome.timings, None);
+
+    // 获取嵌入向量直到超时的耗时计入 embed_ms
+    let options = SearchOptions {
+        with_timings: true,
+        ..Default::default()
+    };
+    let started = std::time::Instant::now();
+    let outcome = search_module
+        .search_crate_with_outcome("http client", SearchSortCriteria::Comprehensive, &options)
+        .await?;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    let timings = outcome.timings.expect("应统计各阶段耗时");
+    assert!(timings.embed_ms >= 100);
+    assert!(
+        timings.rewrite_ms + timings.retrieve_ms + timings.embed_ms + timings.rerank_ms
+            <= elapsed_ms
+    );
+
+    Ok(())
+}
+
+// 把搜索记录收集到共享列表中的日志钩子
+struct CollectingLogger(Arc<Mutex<Vec<SearchRecord>>>);
+
+impl SearchLogger for CollectingLogger {
+    fn log(&self, record: SearchRecord) {
+        self.0.lock().unwrap().push(record);
+    }
+}
+
+#[tokio::test]
+async fn test_search_logger() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .config(SearchConfig {
+            offline: true,
+            ..Default::default()
+        })
+        .logger(CollectingLogger(records.clone()))
+        .build(&pg_client);
+
+    let results = search_module
+        .search_crate("http client", SearchSortCriteria::Relavance)
+        .await?;
+    search_module
+        .search_crate("serialization", SearchSortCriteria::Downloads)
+        .await?;
+
+    // 每次搜索记录一条
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 2);
+    let record = &records[0];
+    assert_eq!(record.query, "http client");
+    assert!(record.rewritten_query.contains("http"));
+    assert!(record.tsquery.contains("http"));
+    assert_eq!(record.result_count, results.len());
+    assert_eq!(record.top_result, results.first().map(|c| c.name.clone()));
+    assert!(!record.used_llm_rewrite);
+    assert!(record.rewrite_ms + record.retrieve_ms + record.rerank_ms <= record.total_ms + 2);
+
+    let json = serde_json::to_value(&records[1])?;
+    assert_eq!(json["query"], "serialization");
+    assert_eq!(json["sort_by"], "Downloads");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unlimited_results() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search = |result_limit: Option<usize>| {
+        SearchModule::builder()
+            .table_name("crates")
+            .config(SearchConfig {
+                offline: true,
+                result_limit,
+                ..Default::default()
+            })
+            .build(&pg_client)
+    };
+
+    let unlimited = search(None)
+        .search_crate("http client", SearchSortCriteria::Relavance)
+        .await?;
+    let limited = search(Some(1))
+        .search_crate("http client", SearchSortCriteria::Relavance)
+        .await?;
+
+    // 不限制数量时返回全部排序结果，前几名与限制数量时一致
+    assert!(unlimited.len() > 1);
+    assert_eq!(limited.len(), 1);
+    assert_eq!(limited[0].name, unlimited[0].name);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_table_name_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let is_config_error = |e: Box<dyn std::error::Error>| {
+        matches!(
+            e.downcast_ref::<SearchError>(),
+            Some(SearchError::Config(_))
+        )
+    };
+    let config = SearchConfig {
+        offline: true,
+        ..Default::default()
+    };
+
+    // 构建时指定的表名不合法，在执行任何SQL前返回配置错误
+    let search_module = SearchModule::builder()
+        .table_name("crates; DROP TABLE crates")
+        .config(config.clone())
+        .build(&pg_client);
+    let err = search_module
+        .search_crate("http client", SearchSortCriteria::Relavance)
+        .await
+        .unwrap_err();
+    assert!(is_config_error(err));
+    assert!(matches!(
+        search_module.health_check_with(false).await,
+        Err(SearchError::Config(_))
+    ));
+
+    // 按请求传入的表名同样校验
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .config(config)
+        .build(&pg_client);
+    let options = SearchOptions {
+        tables: vec!["crates".to_string(), "crates--".to_string()],
+        ..Default::default()
+    };
+    let err = search_module
+        .search_crate_with_options("http", SearchSortCriteria::Relavance, &options)
+        .await
+        .unwrap_err();
+    assert!(is_config_error(err));
+
+    assert!(matches!(
+        rebuild_tsv(&pg_client, "crates x", "english").await,
+        Err(SearchError::Config(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_autocomplete() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .build(&pg_client);
+    let names = |crates: Vec<RecommendCrate>| -> Vec<String> {
+        crates.into_iter().map(|c| c.name).collect()
+    };
+
+    // 不区分大小写，按下载量降序
+    assert_eq!(
+        names(search_module.autocomplete("SER", 10).await?),
+        vec!["serde", "serde_json"]
+    );
+    assert_eq!(
+        names(search_module.autocomplete("reqwest", 1).await?),
+        vec!["reqwest"]
+    );
+
+    // 通配符按字面匹配
+    assert!(search_module.autocomplete("%", 10).await?.is_empty());
+    assert_eq!(
+        names(search_module.autocomplete("serde_", 10).await?),
+        vec!["serde_json"]
+    );
+    assert!(search_module.autocomplete("  ", 10).await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_search_consensus() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .embedder(MockEmbedder::new(8))
+        .use_llm_rewrite(false)
+        .build(&pg_client);
+    let criteria = [
+        SearchSortCriteria::Comprehensive,
+        SearchSortCriteria::Relavance,
+        SearchSortCriteria::Downloads,
+    ];
+
+    let consensus = search_module
+        .search_consensus("http client", &criteria, 10)
+        .await?;
+    assert!(!consensus.is_empty());
+
+    // 融合结果中的crate各不相同，且按融合得分降序
+    let mut ids: Vec<&str> = consensus.iter().map(|c| c.id.as_str()).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), consensus.len());
+    assert!(consensus
+        .windows(2)
+        .all(|pair| pair[0].final_score >= pair[1].final_score));
+
+    // 只有一种排序方式时与该排序的结果顺序相同
+    let single = search_module
+        .search_consensus("http client", &criteria[..1], usize::MAX)
+        .await?;
+    let ranked = search_module
+        .search_crate("http client", SearchSortCriteria::Comprehensive)
+        .await?;
+    let names = |crates: &[RecommendCrate]| -> Vec<String> {
+        crates.iter().map(|c| c.name.clone()).collect()
+    };
+    assert_eq!(names(&single), names(&ranked));
+
+    let err = search_module
+        .search_consensus("http client", &[], 10)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SearchError>(),
+        Some(SearchError::Config(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_include_embeddings() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .use_llm_rewrite(false)
+        .config(SearchConfig {
+            persist_on_demand: false,
+            ..Default::default()
+        })
+        .embedder(MockEmbedder::new(8))
+        .build(&pg_client);
+    let options = |include_embeddings| SearchOptions {
+        include_embeddings,
+        ..Default::default()
+    };
+
+    // 默认不附带，序列化结果中也不出现该字段
+    let results = search_module
+        .search_crate_with_options(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &options(false),
+        )
+        .await?;
+    assert!(results.iter().all(|c| c.embedding.is_none()));
+    assert!(serde_json::to_value(&results[0])?
+        .get("embedding")
+        .is_none());
+
+    let results = search_module
+        .search_crate_with_options(
+            "http client",
+            SearchSortCriteria::Comprehensive,
+            &options(true),
+        )
+        .await?;
+    assert!(!results.is_empty());
+    for crate_item in &results {
+        let embedding = crate_item.embedding.as_ref().expect("缺少嵌入向量");
+        assert_eq!(embedding.len(), 8);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connection_loss_is_retriable() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    let connection_task = tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search = |table_name: &str| {
+        SearchModule::builder()
+            .table_name(table_name)
+            .config(SearchConfig {
+                offline: true,
+                ..Default::default()
+            })
+            .build(&pg_client)
+    };
+    let database_error = |e: Box<dyn std::error::Error>| match e.downcast::<SearchError>() {
+        Ok(e) if matches!(*e, SearchError::Database(_)) => *e,
+        Ok(e) => panic!("预期数据库错误: {}", e),
+        Err(e) => panic!("数据库错误未转换为SearchError: {}", e),
+    };
+
+    // 表不存在属于查询本身的错误，不可重试
+    let err = search("crates_missing_table")
+        .search_crate("http client", SearchSortCriteria::Relavance)
+        .await
+        .unwrap_err();
+    assert!(!database_error(err).is_retriable());
+    assert!(!SearchError::Config("x".to_string()).is_retriable());
+
+    // 后台连接任务结束后，后续查询返回可重试的数据库错误
+    connection_task.abort();
+    let _ = connection_task.await;
+    let err = search("crates")
+        .search_crate("http client", SearchSortCriteria::Relavance)
+        .await
+        .unwrap_err();
+    assert!(database_error(err).is_retriable());
+
+    let err = TraditionalSearchModule::new(&pg_client)
+        .await
+        .search("http client", SearchSortCriteria::Relavance)
+        .await
+        .unwrap_err();
+    assert!(database_error(err).is_retriable());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_normalize_unicode_query() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search = |normalize_unicode: bool| {
+        SearchModule::builder()
+            .table_name("crates")
+            .config(SearchConfig {
+                offline: true,
+                normalize_unicode,
+                ..Default::default()
+            })
+            .build(&pg_client)
+    };
+    let query = "ＨＴＴＰ ｃｌｉｅｎｔ";
+
+    // 默认不规范化，全角字符原样进入tsquery
+    let explanation = search(false)
+        .explain_search(query, SearchSortCriteria::Relavance)
+        .await?;
+    assert!(!explanation.tsquery.contains("http"));
+
+    let explanation = search(true)
+        .explain_search(query, SearchSortCriteria::Relavance)
+        .await?;
+    assert!(explanation.tsquery.contains("http"));
+    assert!(explanation.tsquery.contains("client"));
+
+    let results = search(true)
+        .search_crate(query, SearchSortCriteria::Relavance)
+        .await?;
+    assert!(results.iter().any(|c| c.name == "reqwest"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_popularity_column() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    pg_client
+        .batch_execute(
+            "CREATE TEMP TABLE crates_popularity (LIKE crates INCLUDING ALL);
+            INSERT INTO crates_popularity SELECT * FROM crates;
+            ALTER TABLE crates_popularity ADD COLUMN blended_score integer;
+            UPDATE crates_popularity SET blended_score = 1000000 WHERE name = 'ureq';",
+        )
+        .await?;
+
+    let search = |popularity: Option<PopularityColumn>| {
+        SearchModule::builder()
+            .table_name("crates_popularity")
+            .use_llm_rewrite(false)
+            .embedder(MockEmbedder::new(8))
+            .config(SearchConfig {
+                persist_on_demand: false,
+                popularity,
+                ..Default::default()
+            })
+            .build(&pg_client)
+    };
+
+    let results = search(None)
+        .search_crate("http client", SearchSortCriteria::Downloads)
+        .await?;
+    assert!(results.iter().all(|c| c.popularity.is_none()));
+    assert_ne!(results[0].name, "ureq");
+
+    // 热度列的值被读取并计入下载量排序的得分
+    let results = search(Some(PopularityColumn {
+        column: "blended_score".to_string(),
+        weight: 1.0,
+        full_score: 1_000_000.0,
+    }))
+    .search_crate("http client", SearchSortCriteria::Downloads)
+    .await?;
+    assert_eq!(results[0].name, "ureq");
+    assert_eq!(results[0].popularity, Some(1_000_000.0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rewrite_query_structured() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let llm_config = |chat_url: String| LlmConfig {
+        api_key: Some("test-key".to_string()),
+        chat_url,
+        enabled: true,
+        ..LlmConfig::from_env()
+    };
+
+    // JSON可以包在代码块中，未知分类被丢弃
+    let chat_url = serve_chat_completion(
+        "```json\n{\"keywords\": [\"http client\", \" reqwest \"], \"categories\": [\"Web-Programming\", \"networking\"]}\n```",
+    )
+    .await;
+    let rewrite = rewrite_query_structured_with_config("http请求库", &llm_config(chat_url)).await?;
+    assert_eq!(rewrite.keywords, vec!["http client", "reqwest"]);
+    assert_eq!(rewrite.categories, vec!["web-programming"]);
+
+    // 按分类过滤时，属于预测分类或其子分类的crate被保留
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .config(SearchConfig {
+            offline: true,
+            metadata_columns: vec!["categories".to_string()],
+            ..Default::default()
+        })
+        .build(&pg_client);
+    let options = SearchOptions {
+        filter: rewrite.category_filter(),
+        ..Default::default()
+    };
+    let results = search_module
+        .search_crate_with_options("http", SearchSortCriteria::Relavance, &options)
+        .await?;
+    let names: Vec<&str> = results.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["reqwest"]);
+
+    // 回复不是JSON时退回为只有关键词的结果
+    let chat_url = serve_chat_completion("http client, reqwest; web client").await;
+    let rewrite =
+        rewrite_query_structured_with_config("http client", &llm_config(chat_url)).await?;
+    assert_eq!(
+        rewrite,
+        StructuredRewrite {
+            keywords: vec![
+                "http client".to_string(),
+                "reqwest".to_string(),
+                "web client".to_string()
+            ],
+            categories: Vec::new(),
+        }
+    );
+    assert!(rewrite.category_filter().is_none());
+
+    Ok(())
+}
+
+// 记录请求过向量的文本，用于确认缓存命中时没有调用嵌入接口
+struct RecordingEmbedder {
+    texts: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl Embedder for RecordingEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        self.texts.lock().unwrap().extend(texts.iter().cloned());
+        MockEmbedder::new(8).embed(texts).await
+    }
+}
+
+#[tokio::test]
+async fn test_cache_warmer() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let cache = Arc::new(QueryCache::new());
+    let texts = Arc::new(Mutex::new(Vec::new()));
+    let build = |chat_url: String| {
+        SearchModule::builder()
+            .table_name("crates")
+            .api_key("test-key")
+            .chat_url(chat_url)
+            .use_llm_rewrite(true)
+            .embedder(RecordingEmbedder {
+                texts: texts.clone(),
+            })
+            .query_cache(cache.clone())
+            .config(SearchConfig {
+                persist_on_demand: false,
+                ..Default::default()
+            })
+            .build(&pg_client)
+    };
+
+    let chat_url = serve_chat_completion("http, client; reqwest").await;
+    let handle = spawn_cache_warmer(
+        &build(chat_url),
+        vec!["http client".to_string()],
+        Duration::from_secs(3600),
+    );
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while cache.rewrite("http client").is_none() || cache.embedding("http client").is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    handle.abort();
+    assert_eq!(
+        cache.rewrite("http client").as_deref(),
+        Some("http, client; reqwest")
+    );
+
+    // LLM接口不可用时，预热过的查询仍使用缓存的改写结果和查询向量
+    let search_module = build("http://127.0.0.1:1/v1/chat/completions".to_string());
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "http client",
+            SearchSortCriteria::Relavance,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert!(outcome.used_llm_rewrite);
+    assert!(outcome.used_vector_rerank);
+    assert!(outcome.results.iter().any(|c| c.name == "reqwest"));
+    let embedded = texts.lock().unwrap();
+    assert_eq!(embedded.iter().filter(|t| *t == "http client").count(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_search_crate_with_response() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let chat_url = serve_chat_completion("reqwest, hyper, HTTP client, reqwest").await;
+    let search_module = SearchModule::builder()
+        .table_name("crates")
+        .api_key("test-key")
+        .chat_url(chat_url)
+        .use_llm_rewrite(true)
+        .embedder(MockEmbedder::default())
+        .config(SearchConfig {
+            persist_on_demand: false,
+            ..Default::default()
+        })
+        .build(&pg_client);
+
+    let response: SearchResponse = search_module
+        .search_crate_with_response(
+            "How do I send HTTP requests from Rust?",
+            SearchSortCriteria::Relavance,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert_eq!(
+        response.rewritten_query,
+        "reqwest, hyper, HTTP client, reqwest"
+    );
+    // 关键词列表与检索时使用的一致：去重并转为小写
+    assert_eq!(
+        response.extracted_keywords,
+        vec!["reqwest", "hyper", "http client"]
+    );
+    assert!(response.results.iter().any(|c| c.name == "reqwest"));
+
+    // 不使用LLM时，基本关键词提取按配置生成词组
+    for (keyword_max_ngram, has_phrase) in [(2, true), (1, false)] {
+        let search_module = SearchModule::builder()
+            .table_name("crates")
+            .config(SearchConfig {
+                offline: true,
+                keyword_max_ngram,
+                ..Default::default()
+            })
+            .build(&pg_client);
+        let response = search_module
+            .search_crate_with_response(
+                "How do I send HTTP requests from Rust?",
+                SearchSortCriteria::Relavance,
+                &SearchOptions::default(),
+            )
+            .await?;
+        assert_eq!(
+            response
+                .extracted_keywords
+                .iter()
+                .any(|keyword| keyword.contains(' ')),
+            has_phrase
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rerank_candidate_cap_and_top_n() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let candidate = |id: &str, rank: f32| RecommendCrate {
+        id: id.to_string(),
+        name: id.to_string(),
+        description: format!("{} description", id),
+        rank,
+        ..Default::default()
+    };
+    let crates = vec![
+        candidate("mock-high", 0.9),
+        candidate("mock-low", 0.1),
+        candidate("mock-mid", 0.5),
+        candidate("mock-lower", 0.3),
+    ];
+
+    let texts = Arc::new(Mutex::new(Vec::new()));
+    let config = SearchConfig {
+        persist_on_demand: false,
+        rerank_candidate_cap: Some(2),
+        ..Default::default()
+    };
+    let results = rerank_crates(
+        crates.clone(),
+        "http client",
+        SearchSortCriteria::Relavance,
+        &pg_client,
+        "crates",
+        &RecordingEmbedder {
+            texts: texts.clone(),
+        },
+        &config,
+        &SearchOptions::default(),
+    )
+    .await?;
+
+    // 只保留关键词得分最高的两个候选，只为它们和查询获取嵌入向量
+    let mut names: Vec<&str> = results.iter().map(|c| c.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["mock-high", "mock-mid"]);
+    assert_eq!(texts.lock().unwrap().len(), 3);
+
+    // 只重排序前两个候选，其余候选按关键词得分原样接在后面
+    texts.lock().unwrap().clear();
+    let config = SearchConfig {
+        persist_on_demand: false,
+        rerank_top_n: Some(2),
+        ..Default::default()
+    };
+    let results = rerank_crates(
+        crates,
+        "http client",
+        SearchSortCriteria::Relavance,
+        &pg_client,
+        "crates",
+        &RecordingEmbedder {
+            texts: texts.clone(),
+        },
+        &config,
+        &SearchOptions::default(),
+    )
+    .await?;
+    let names: Vec<&str> = results.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names.len(), 4);
+    assert!(names[..2].contains(&"mock-high") && names[..2].contains(&"mock-mid"));
+    assert_eq!(names[2..], ["mock-lower", "mock-low"]);
+    assert_eq!(texts.lock().unwrap().len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_query_kind_weights() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let search = |query_kind_weights: Option<QueryKindWeights>, query_kind: Option<QueryKind>| {
+        let pg_client = &pg_client;
+        async move {
+            let search_module = SearchModule::builder()
+                .table_name("crates")
+                .use_llm_rewrite(false)
+                .embedder(MockEmbedder::default())
+                .config(SearchConfig {
+                    persist_on_demand: false,
+                    query_kind_weights,
+                    ..Default::default()
+                })
+                .build(pg_client);
+            let options = SearchOptions {
+                query_kind,
+                ..Default::default()
+            };
+            let results = search_module
+                .search_crate_with_options("http", SearchSortCriteria::Relavance, &options)
+                .await?;
+            // hyper的名称与查询词无关，最终得分只由关键词和向量得分加权得到
+            let hyper = results
+                .into_iter()
+                .find(|c| c.name == "hyper")
+                .expect("hyper应在结果中");
+            Ok::<_, Box<dyn std::error::Error>>(hyper)
+        }
+    };
+    let expected = |c: &RecommendCrate, keyword: f32, vector: f32| {
+        (c.final_score - (keyword * c.rank + vector * c.vector_score)).abs() < 1e-5
+    };
+
+    // 不按查询类型调整时使用 relevance 的 0.8 / 0.2
+    let hyper = search(None, None).await?;
+    assert!(expected(&hyper, 0.8, 0.2));
+
+    // 自动识别为关键词查询：向量权重乘以0.5后按比例缩放回总和1
+    let weights = QueryKindWeights::default();
+    assert_eq!(weights.vector_scale(QueryKind::Keyword), 0.5);
+    let hyper = search(Some(weights), None).await?;
+    assert!(expected(&hyper, 0.8 / 0.9, 0.1 / 0.9));
+
+    // 调用方指定为自然语言查询时向量权重更高
+    let hyper = search(Some(weights), Some(QueryKind::NaturalLanguage)).await?;
+    assert!(expected(&hyper, 0.8 / 1.1, 0.3 / 1.1));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_spelling_correction() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let vocabulary = CrateVocabulary::load(&pg_client, "crates", None).await?;
+    assert!(vocabulary.contains("Tokio"));
+    assert_eq!(vocabulary.correct("tokoi async", 2), "tokio async");
+    // 已知名称、过短的词和中文保持不变，阈值为0时不纠正
+    assert_eq!(vocabulary.correct("serde json", 2), "serde json");
+    assert_eq!(vocabulary.correct("clp 异步", 2), "clp 异步");
+    assert_eq!(vocabulary.correct("tokoi async", 0), "tokoi async");
+
+    // 词表在首次搜索时从数据表加载
+    let search_module = SearchModule::builder()
+        .config(SearchConfig {
+            offline: true,
+            spelling_correction: Some(SpellingCorrection::default()),
+            ..Default::default()
+        })
+        .embedder(MockEmbedder::new(8))
+        .build(&pg_client);
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "tokoi async",
+            SearchSortCriteria::Relavance,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert_eq!(outcome.corrected_query.as_deref(), Some("tokio async"));
+    assert_eq!(
+        outcome.results.first().map(|c| c.name.as_str()),
+        Some("tokio")
+    );
+
+    // 直接提供的词表优先于从数据表加载
+    let search_module = SearchModule::builder()
+        .config(SearchConfig {
+            offline: true,
+            spelling_correction: Some(SpellingCorrection {
+                max_edit_distance: 1,
+                vocabulary_limit: None,
+            }),
+            ..Default::default()
+        })
+        .embedder(MockEmbedder::new(8))
+        .vocabulary(Arc::new(CrateVocabulary::new(["hyper"])))
+        .build(&pg_client);
+    let outcome = search_module
+        .search_crate_with_outcome(
+            "tokoi hyperr",
+            SearchSortCriteria::Relavance,
+            &SearchOptions::default(),
+        )
+        .await?;
+    assert_eq!(outcome.corrected_query.as_deref(), Some("tokoi hyper"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_concurrent_query_embedding() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let texts = Arc::new(Mutex::new(Vec::new()));
+    let build = |concurrent_query_embedding: bool| {
+        SearchModule::builder()
+            .table_name("crates")
+            .use_llm_rewrite(false)
+            .embedder(RecordingEmbedder {
+                texts: texts.clone(),
+            })
+            .config(SearchConfig {
+                persist_on_demand: false,
+                concurrent_query_embedding,
+                ..Default::default()
+            })
+            .build(&pg_client)
+    };
+
+    let sequential = build(false)
+        .search_crate("http client", SearchSortCriteria::Comprehensive)
+        .await?;
+    texts.lock().unwrap().clear();
+    let concurrent = build(true)
+        .search_crate("http client", SearchSortCriteria::Comprehensive)
+        .await?;
+
+    // 并发获取的查询向量在重排序阶段直接复用，不会重复请求
+    let query_requests = texts
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|text| *text == "http client")
+        .count();
+    assert_eq!(query_requests, 1);
+    assert!(!concurrent.is_empty());
+    assert_eq!(
+        sequential.iter().map(|c| &c.name).collect::<Vec<_>>(),
+        concurrent.iter().map(|c| &c.name).collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+// 返回固定回复的Chat Completions服务，同时记录收到的请求体
+async fn serve_chat_completion_capturing(
+    content: &'static str,
+) -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            // 读取请求头和按 Content-Length 指定长度的请求体
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 4096];
+            let body = loop {
+                let n = socket.read(&mut chunk).await.unwrap();
+                if n == 0 {
+                    break Vec::new();
+                }
+                buffer.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&buffer).to_string();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if buffer.len() >= header_end + 4 + content_length {
+                        break buffer[header_end + 4..header_end + 4 + content_length].to_vec();
+                    }
+                }
+            };
+            if let Ok(body) = serde_json::from_slice(&body) {
+                let _ = tx.send(body);
+            }
+            let body = serde_json::json!({ "choices": [{ "message": { "content": content } }] })
+                .to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    });
+    (format!("http://{}/v1/chat/completions", addr), rx)
+}
+
+#[tokio::test]
+async fn test_llm_sampling_options() -> Result<(), Box<dyn std::error::Error>> {
+    let (chat_url, mut requests) = serve_chat_completion_capturing("http, client").await;
+
+    // 默认温度0.3，按请求类型使用默认的max_tokens，不发送seed
+    let llm_config = LlmConfig {
+        api_key: Some("test-key".to_string()),
+        chat_url,
+        enabled: true,
+        ..LlmConfig::from_env()
+    };
+    extract_keywords_with_config("http请求库", &llm_config).await?;
+    let body = requests.recv().await.unwrap();
+    assert!((body["temperature"].as_f64().unwrap() - 0.3).abs() < 1e-6);
+    assert_eq!(body["max_tokens"], 100);
+    assert!(body.get("seed").is_none());
+
+    // 配置的温度、max_tokens和seed同时用于改写和关键词提取请求
+    let llm_config = LlmConfig {
+        temperature: 0.0,
+        max_tokens: Some(64),
+        seed: Some(42),
+        ..llm_config
+    };
+    rewrite_query_with_config("http请求库", &llm_config).await?;
+    extract_keywords_with_config("http请求库", &llm_config).await?;
+    for _ in 0..2 {
+        let body = requests.recv().await.unwrap();
+        assert_eq!(body["temperature"], 0.0);
+        assert_eq!(body["max_tokens"], 64);
+        assert_eq!(body["seed"], 42);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_tsquery_falls_back_to_plainto() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let (pg_client, connection) = tokio_postgres::connect(
+        "host=localhost user=cratespro password=cratespro dbname=cratesproSearch",
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("数据库连接错误: {}", e);
+        }
+    });
+
+    let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "crates".to_string());
+
+    // 括号和冒号会生成语法错误的tsquery，改为以 plainto_tsquery 解析原始查询重新检索
+    let results =
+        retrive_crates(&pg_client, &table_name, "serde(", &SearchConfig::default()).await?;
+    assert!(results.iter().any(|c| c.name == "serde"));
+
+    let config = SearchConfig {
+        text_search_config: Some("english".to_string()),
+        ..Default::default()
+    };
+    let results = retrive_crates(&pg_client, &table_name, "tokio:", &config).await?;
+    assert!(results.iter().any(|c| c.name == "tokio"));
+
+    Ok(())
+}